@@ -0,0 +1,40 @@
+use crate::bus::{RoomEvent, SignalingBus};
+use async_trait::async_trait;
+use tokio::sync::{RwLock, mpsc};
+
+/// Bus for a single process: events are handed straight to local subscribers.
+pub struct InProcessBus {
+    subscribers: RwLock<Vec<mpsc::UnboundedSender<RoomEvent>>>,
+}
+
+impl InProcessBus {
+    pub fn new() -> Self {
+        Self {
+            subscribers: RwLock::new(Vec::new()),
+        }
+    }
+}
+
+impl Default for InProcessBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl SignalingBus for InProcessBus {
+    async fn publish(&self, event: RoomEvent) {
+        let subscribers = self.subscribers.read().await;
+        for subscriber in subscribers.iter() {
+            let _ = subscriber.send(event.clone());
+        }
+    }
+
+    async fn subscribe(&self) -> mpsc::UnboundedReceiver<RoomEvent> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut subscribers = self.subscribers.write().await;
+        subscribers.retain(|subscriber| !subscriber.is_closed());
+        subscribers.push(tx);
+        rx
+    }
+}
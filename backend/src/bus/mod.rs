@@ -0,0 +1,44 @@
+pub mod memory;
+pub mod redis;
+
+use crate::models::ServerMessage;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// A message for the peers of a room, wherever they are connected
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoomEvent {
+    pub room_id: String,
+    /// Only this peer receives the message, when set
+    pub to: Option<Uuid>,
+    /// Peer that must not receive the message (usually the sender)
+    pub exclude: Option<Uuid>,
+    pub message: BusMessage,
+}
+
+/// What a room event carries. Only `Client` messages ever reach a client;
+/// the rest coordinate nodes.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum BusMessage {
+    /// Sent to the addressed peers as is
+    Client(ServerMessage),
+    /// The owner's answer, routed to the node holding the join request.
+    /// The joiner itself receives `RoomJoined` or `JoinRejected`.
+    JoinRequestAnswered { request_id: Uuid, approved: bool },
+}
+
+/// Trait for delivering room events across nodes.
+/// Implementations can use an in-process channel, Redis pub/sub, NATS, etc.
+#[async_trait]
+pub trait SignalingBus: Send + Sync {
+    /// Publish an event to every node, including this one
+    async fn publish(&self, event: RoomEvent);
+
+    /// Receive the events published on any node, to deliver to local peers
+    async fn subscribe(&self) -> mpsc::UnboundedReceiver<RoomEvent>;
+}
+
+pub use memory::InProcessBus;
+pub use redis::RedisBus;
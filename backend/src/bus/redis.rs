@@ -0,0 +1,113 @@
+use crate::bus::{InProcessBus, RoomEvent, SignalingBus};
+use async_trait::async_trait;
+use futures::StreamExt;
+use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+const CHANNEL: &str = "filecoffee:signals";
+
+/// A room event as published on the Redis channel
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    /// Node that published the event, so it can skip its own echo
+    origin: Uuid,
+    event: RoomEvent,
+}
+
+/// Bus shared between replicas through Redis pub/sub.
+/// Events for peers on this node are delivered in-process; every event is also
+/// published on Redis for the other nodes.
+pub struct RedisBus {
+    conn: ConnectionManager,
+    node_id: Uuid,
+    local: Arc<InProcessBus>,
+}
+
+impl RedisBus {
+    /// Connect to Redis and start relaying events published by other nodes.
+    pub async fn connect(redis_url: &str) -> redis::RedisResult<Self> {
+        let client = redis::Client::open(redis_url)?;
+        let conn = ConnectionManager::new(client.clone()).await?;
+        let node_id = Uuid::new_v4();
+        let local = Arc::new(InProcessBus::new());
+
+        let relay_local = local.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = relay_remote_events(&client, node_id, &relay_local).await {
+                    tracing::error!(error = %e, "Redis subscription lost, retrying");
+                }
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        });
+
+        Ok(Self {
+            conn,
+            node_id,
+            local,
+        })
+    }
+}
+
+/// Forward events published by other nodes to local subscribers until the connection drops.
+async fn relay_remote_events(
+    client: &redis::Client,
+    node_id: Uuid,
+    local: &InProcessBus,
+) -> redis::RedisResult<()> {
+    let mut pubsub = client.get_async_pubsub().await?;
+    pubsub.subscribe(CHANNEL).await?;
+    tracing::info!(node_id = %node_id, "Subscribed to signaling bus");
+
+    let mut messages = pubsub.on_message();
+    while let Some(msg) = messages.next().await {
+        let Ok(text) = msg.get_payload::<String>() else {
+            continue;
+        };
+        let envelope: Envelope = match serde_json::from_str(&text) {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                tracing::warn!(error = %e, "Invalid signaling bus message");
+                continue;
+            }
+        };
+
+        if envelope.origin != node_id {
+            local.publish(envelope.event).await;
+        }
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl SignalingBus for RedisBus {
+    async fn publish(&self, event: RoomEvent) {
+        let envelope = Envelope {
+            origin: self.node_id,
+            event,
+        };
+
+        match serde_json::to_string(&envelope) {
+            Ok(text) => {
+                let mut conn = self.conn.clone();
+                let result: redis::RedisResult<()> = conn.publish(CHANNEL, text).await;
+                if let Err(e) = result {
+                    tracing::error!(room_id = %envelope.event.room_id, error = %e, "Failed to publish room event");
+                }
+            }
+            Err(e) => tracing::error!(error = %e, "Failed to serialize room event"),
+        }
+
+        self.local.publish(envelope.event).await;
+    }
+
+    async fn subscribe(&self) -> mpsc::UnboundedReceiver<RoomEvent> {
+        self.local.subscribe().await
+    }
+}
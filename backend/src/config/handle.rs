@@ -0,0 +1,177 @@
+use super::{CommandLine, Config, ConfigError};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+/// Settings only read at startup; a reload that changes them is logged but has no effect
+const RESTART_REQUIRED: &[&str] = &[
+    "port",
+    "metrics_port",
+    "trusted_proxies",
+    "room_store",
+    "signaling_bus",
+    "redis_url",
+    "slug_template",
+    "slug_locale",
+    "slug_adjectives_file",
+    "slug_nouns_file",
+    "slug_blocklist_file",
+];
+
+/// Settings changed by a reload, by config file name
+#[derive(Debug, Serialize)]
+pub struct ReloadReport {
+    /// Applied right away (WebSocket settings apply to connections opened from now on)
+    pub applied: Vec<String>,
+    /// Need a restart to take effect
+    pub restart_required: Vec<String>,
+}
+
+/// The configuration shared by the services and routes, replaced as a whole on reload.
+/// Readers take a snapshot with `current` and see a reload on their next call.
+#[derive(Clone)]
+pub struct ConfigHandle {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    /// Command line the process was started with, re-applied on every reload
+    cli: CommandLine,
+    sender: watch::Sender<Arc<Config>>,
+    /// Serializes reloads, so each report compares against the configuration it replaced
+    reloading: Mutex<()>,
+}
+
+impl ConfigHandle {
+    pub fn new(config: Config, cli: CommandLine) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                cli,
+                sender: watch::Sender::new(Arc::new(config)),
+                reloading: Mutex::new(()),
+            }),
+        }
+    }
+
+    pub fn current(&self) -> Arc<Config> {
+        self.inner.sender.borrow().clone()
+    }
+
+    /// Watch for reloads, for state derived from the configuration
+    pub fn subscribe(&self) -> watch::Receiver<Arc<Config>> {
+        self.inner.sender.subscribe()
+    }
+
+    /// Re-read the configuration file, environment and command line. An invalid
+    /// configuration is rejected as a whole and the current one stays in place.
+    pub fn reload(&self) -> Result<ReloadReport, ConfigError> {
+        let _reloading = self
+            .inner
+            .reloading
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+
+        let config = Config::load(&self.inner.cli).inspect_err(|e| {
+            tracing::error!(errors = ?e.0, "Configuration reload rejected");
+        })?;
+        let (restart_required, applied): (Vec<_>, Vec<_>) =
+            changed_settings(&self.current(), &config)
+                .into_iter()
+                .partition(|name| RESTART_REQUIRED.contains(&name.as_str()));
+
+        self.inner.sender.send_replace(Arc::new(config));
+        tracing::info!(changed = ?applied, "Configuration reloaded");
+        if !restart_required.is_empty() {
+            tracing::warn!(
+                settings = ?restart_required,
+                "Changed settings need a restart to take effect"
+            );
+        }
+        Ok(ReloadReport {
+            applied,
+            restart_required,
+        })
+    }
+}
+
+/// Names of the settings that differ between two configurations
+fn changed_settings(old: &Config, new: &Config) -> Vec<String> {
+    let (Ok(toml::Value::Table(old_table)), Ok(toml::Value::Table(new_table))) =
+        (toml::Value::try_from(old), toml::Value::try_from(new))
+    else {
+        return Vec::new();
+    };
+
+    let mut changed: Vec<String> = old_table
+        .keys()
+        .chain(new_table.keys().filter(|key| !old_table.contains_key(*key)))
+        .filter(|key| old_table.get(*key) != new_table.get(*key))
+        .cloned()
+        .collect();
+
+    // Secrets serialize redacted, so compare their values
+    let secrets = [
+        ("turn_secret", &old.turn_secret, &new.turn_secret),
+        ("relay_secret", &old.relay_secret, &new.relay_secret),
+        ("admin_token", &old.admin_token, &new.admin_token),
+        (
+            "redis_url",
+            &Some(old.redis_url.clone()),
+            &Some(new.redis_url.clone()),
+        ),
+    ];
+    for (name, old, new) in secrets {
+        let differs = old.as_ref().map(|s| s.expose()) != new.as_ref().map(|s| s.expose());
+        if differs && !changed.iter().any(|c| c == name) {
+            changed.push(name.to_string());
+        }
+    }
+
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::TempFile;
+
+    fn handle(file: &TempFile) -> ConfigHandle {
+        let cli = CommandLine::parse([format!("--config={}", file.path().display())]).unwrap();
+        ConfigHandle::new(Config::load(&cli).unwrap(), cli)
+    }
+
+    #[test]
+    fn reload_reports_what_changed() {
+        let file = TempFile::new("port = 3030\nroom_ttl_seconds = 60\n");
+        let config = handle(&file);
+        let mut updates = config.subscribe();
+
+        let report = config.reload().unwrap();
+        assert!(report.applied.is_empty() && report.restart_required.is_empty());
+
+        std::fs::write(
+            file.path(),
+            "port = 3031\nroom_ttl_seconds = 120\nrelay_secret = \"rotated\"\n",
+        )
+        .unwrap();
+        let report = config.reload().unwrap();
+        assert_eq!(report.applied, ["room_ttl_seconds", "relay_secret"]);
+        assert_eq!(report.restart_required, ["port"]);
+
+        // Everything is swapped in, even what only takes effect after a restart
+        assert!(updates.has_changed().unwrap());
+        let current = updates.borrow_and_update().clone();
+        assert_eq!(current.port, 3031);
+        assert_eq!(current.room_ttl_seconds, 120);
+    }
+
+    #[test]
+    fn invalid_reload_keeps_the_current_configuration() {
+        let file = TempFile::new("room_ttl_seconds = 60\n");
+        let config = handle(&file);
+
+        std::fs::write(file.path(), "room_ttl_seconds = 120\nroom_max_peers = 1\n").unwrap();
+        assert!(config.reload().is_err());
+        assert_eq!(config.current().room_ttl_seconds, 60);
+    }
+}
@@ -0,0 +1,462 @@
+use super::Secret;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Where a setting's value came from, for error messages
+#[derive(Debug, Clone, Copy)]
+enum Source {
+    File,
+    Env,
+    Cli,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Source::File => "config file",
+            Source::Env => "environment",
+            Source::Cli => "command line",
+        })
+    }
+}
+
+/// Command-line arguments of the server
+#[derive(Debug, Default)]
+pub struct CommandLine {
+    /// TOML file to read settings from (`--config`, or `CONFIG_FILE`)
+    pub config_file: Option<String>,
+    /// Print the effective configuration and exit
+    pub print_config: bool,
+    pub help: bool,
+    /// Settings given as `--room-max-peers 4`, keyed by environment variable name
+    overrides: Vec<(String, String)>,
+}
+
+pub const USAGE: &str = "\
+Usage: backend [--config FILE] [--print-config] [--SETTING VALUE]...
+
+Settings are read from the config file, then the environment, then the command line,
+each overriding the last. A setting is named `room_max_peers` in the config file,
+`ROOM_MAX_PEERS` in the environment and `--room-max-peers` on the command line.
+Every setting needs a value: `--relay-enabled true`, or `--room-ttl-seconds=-1` for
+a value starting with `-`.
+
+Options:
+  --config FILE     Read settings from this TOML file (or set CONFIG_FILE)
+  --print-config    Print the effective configuration, secrets redacted, and exit
+  -h, --help        Print this help";
+
+impl CommandLine {
+    /// Parse arguments, without the program name. Settings take their value as
+    /// `--key=value` or `--key value`; a value starting with `-` needs the `=` form.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut cli = Self::default();
+        let mut args = args.into_iter().peekable();
+
+        while let Some(arg) = args.next() {
+            let Some(flag) = arg.strip_prefix("--") else {
+                if arg == "-h" {
+                    cli.help = true;
+                    continue;
+                }
+                return Err(format!("unexpected argument: {arg}"));
+            };
+
+            let (name, value) = match flag.split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None => (flag.to_string(), None),
+            };
+            match name.as_str() {
+                "help" => cli.help = true,
+                "print-config" => cli.print_config = true,
+                _ => {
+                    if name.is_empty() {
+                        return Err("missing setting name after `--`".to_string());
+                    }
+                    let value = match value {
+                        Some(value) => value,
+                        None => args
+                            .next_if(|next| !next.starts_with('-'))
+                            .ok_or_else(|| format!("missing value for --{name}"))?,
+                    };
+                    if name == "config" {
+                        cli.config_file = Some(value);
+                    } else {
+                        cli.overrides.push((setting_key(&name), value));
+                    }
+                }
+            }
+        }
+        Ok(cli)
+    }
+}
+
+/// Raw settings from the config file, the environment and the command line, keyed by
+/// environment variable name. The command line wins over the environment, which wins
+/// over the file.
+///
+/// Values that fail to parse are collected as errors rather than replaced by the default,
+/// and so are settings from the file or the command line that nothing reads.
+#[derive(Default)]
+pub struct Layers {
+    /// Values from the file and the command line; the environment is read on demand
+    values: HashMap<String, (String, Source)>,
+    /// Settings read while building the config
+    used: HashSet<String>,
+    errors: Vec<String>,
+}
+
+impl Layers {
+    pub fn new(cli: &CommandLine) -> Self {
+        let mut layers = Self::default();
+
+        let config_file = cli
+            .config_file
+            .clone()
+            .or_else(|| env::var("CONFIG_FILE").ok().filter(|v| !v.is_empty()));
+        if let Some(path) = config_file {
+            layers.read_file(&path);
+        }
+        for (key, value) in &cli.overrides {
+            layers
+                .values
+                .insert(key.clone(), (value.clone(), Source::Cli));
+        }
+        layers
+    }
+
+    fn read_file(&mut self, path: &str) {
+        let table = match std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| text.parse::<toml::Table>().map_err(|e| e.to_string()))
+        {
+            Ok(table) => table,
+            Err(e) => {
+                self.errors
+                    .push(format!("cannot read config file {path}: {e}"));
+                return;
+            }
+        };
+
+        for (name, value) in table {
+            match toml_value(&value) {
+                Some(value) => {
+                    self.values
+                        .insert(setting_key(&name), (value, Source::File));
+                }
+                None => self.errors.push(format!(
+                    "{name} in {path}: expected a string, number, boolean or array of those"
+                )),
+            }
+        }
+    }
+
+    /// Raw value of a setting from the highest layer that sets it
+    fn raw(&mut self, key: &str) -> Option<(String, Source)> {
+        self.used.insert(key.to_string());
+        if let Some((value, Source::Cli)) = self.values.get(key) {
+            return Some((value.clone(), Source::Cli));
+        }
+        if let Ok(value) = env::var(key) {
+            return Some((value, Source::Env));
+        }
+        self.values.get(key).cloned()
+    }
+
+    /// Parse a setting, falling back to `default` when it is unset or invalid
+    pub fn get<T>(&mut self, key: &str, default: T) -> T
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        self.get_opt(key).unwrap_or(default)
+    }
+
+    /// Parse a setting that has no default. Empty values are unset.
+    pub fn get_opt<T>(&mut self, key: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let (value, source) = self.raw(key).filter(|(value, _)| !value.is_empty())?;
+        match value.trim().parse() {
+            Ok(parsed) => Some(parsed),
+            Err(e) => {
+                self.errors
+                    .push(format!("{key}: invalid value {value:?} ({source}): {e}"));
+                None
+            }
+        }
+    }
+
+    /// Parse a comma-separated list setting. Set but empty means an empty list.
+    pub fn get_list<T>(&mut self, key: &str) -> Option<Vec<T>>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let (value, source) = self.raw(key)?;
+        let mut items = Vec::new();
+        for item in value.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match item.parse() {
+                Ok(parsed) => items.push(parsed),
+                Err(e) => self
+                    .errors
+                    .push(format!("{key}: invalid item {item:?} ({source}): {e}")),
+            }
+        }
+        Some(items)
+    }
+
+    /// A secret, set directly or read from the file named by `<key>_FILE`
+    pub fn get_secret(&mut self, key: &str) -> Option<Secret> {
+        let file_key = format!("{key}_FILE");
+        let file: Option<PathBuf> = self.get_opt(&file_key);
+        let value: Option<String> = self.get_opt(key);
+
+        match (value, file) {
+            (Some(_), Some(_)) => {
+                self.errors
+                    .push(format!("{key}: set either {key} or {file_key}, not both"));
+                None
+            }
+            (Some(value), None) => Some(Secret::new(key, value)),
+            (None, Some(file)) => Secret::from_file(key, file)
+                .map_err(|e| self.errors.push(format!("{file_key}: {e}")))
+                .ok(),
+            (None, None) => None,
+        }
+    }
+
+    /// Errors found so far, including settings from the file or command line that
+    /// nothing read (most likely typos).
+    pub fn finish(mut self) -> Vec<String> {
+        let mut unknown: Vec<_> = self
+            .values
+            .iter()
+            .filter(|(key, _)| !self.used.contains(*key))
+            .map(|(key, (_, source))| format!("{key}: unknown setting ({source})"))
+            .collect();
+        unknown.sort();
+        self.errors.extend(unknown);
+        self.errors
+    }
+}
+
+/// `room-max-peers` or `room_max_peers` to `ROOM_MAX_PEERS`
+fn setting_key(name: &str) -> String {
+    name.replace('-', "_").to_ascii_uppercase()
+}
+
+/// A TOML value as the string the environment would hold; arrays become comma-separated lists
+fn toml_value(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(s) => Some(s.clone()),
+        toml::Value::Integer(i) => Some(i.to_string()),
+        toml::Value::Float(f) => Some(f.to_string()),
+        toml::Value::Boolean(b) => Some(b.to_string()),
+        toml::Value::Array(items) => items
+            .iter()
+            .map(|item| match item {
+                toml::Value::Array(_) | toml::Value::Table(_) => None,
+                item => toml_value(item),
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|items| items.join(",")),
+        toml::Value::Datetime(_) | toml::Value::Table(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::TempFile;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn layers(file: Option<&TempFile>, cli_args: &[&str]) -> Layers {
+        let mut cli = CommandLine::parse(args(cli_args)).unwrap();
+        cli.config_file = file.map(|f| f.path().display().to_string());
+        Layers::new(&cli)
+    }
+
+    #[test]
+    fn parses_flags_and_settings() {
+        let cli = CommandLine::parse(args(&[
+            "--config",
+            "app.toml",
+            "--print-config",
+            "--room-max-peers",
+            "4",
+            "--relay-enabled=true",
+            "--port=8080",
+            "--room-ttl-seconds=-1",
+            "-h",
+        ]))
+        .unwrap();
+
+        assert_eq!(cli.config_file.as_deref(), Some("app.toml"));
+        assert!(cli.print_config);
+        assert!(cli.help);
+        assert_eq!(
+            cli.overrides,
+            vec![
+                ("ROOM_MAX_PEERS".to_string(), "4".to_string()),
+                ("RELAY_ENABLED".to_string(), "true".to_string()),
+                ("PORT".to_string(), "8080".to_string()),
+                ("ROOM_TTL_SECONDS".to_string(), "-1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_stray_arguments() {
+        assert!(CommandLine::parse(args(&["serve"])).is_err());
+        assert!(CommandLine::parse(args(&["--", "4"])).is_err());
+    }
+
+    #[test]
+    fn settings_need_a_value() {
+        for cli_args in [
+            &["--relay-enabled"][..],
+            &["--room-max-peers", "-h"],
+            &["--room-max-peers", "--print-config"],
+            &["--room-ttl-seconds", "-1", "--port", "8080"],
+            &["--config"],
+        ] {
+            assert_eq!(
+                CommandLine::parse(args(cli_args)).unwrap_err(),
+                format!("missing value for {}", cli_args[0])
+            );
+        }
+    }
+
+    #[test]
+    fn command_line_wins_over_environment_over_file() {
+        // PATH is always set in the environment
+        let file = TempFile::new("path = \"from-file\"\nroom_max_peers = 3\n");
+
+        let mut from_env = layers(Some(&file), &[]);
+        assert_eq!(from_env.get_opt::<String>("PATH"), env::var("PATH").ok());
+        assert_eq!(from_env.get("ROOM_MAX_PEERS", 2), 3);
+        assert!(from_env.finish().is_empty());
+
+        let mut from_cli = layers(
+            Some(&file),
+            &["--path", "from-cli", "--room-max-peers", "5"],
+        );
+        assert_eq!(
+            from_cli.get_opt::<String>("PATH").as_deref(),
+            Some("from-cli")
+        );
+        assert_eq!(from_cli.get("ROOM_MAX_PEERS", 2), 5);
+        assert!(from_cli.finish().is_empty());
+    }
+
+    #[test]
+    fn file_arrays_become_lists() {
+        let file = TempFile::new("allowed_origins = [\"https://a.test\", \"https://b.test\"]\n");
+        let mut layers = layers(Some(&file), &[]);
+        assert_eq!(
+            layers.get_list::<String>("ALLOWED_ORIGINS"),
+            Some(vec![
+                "https://a.test".to_string(),
+                "https://b.test".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn reports_unknown_settings() {
+        let file = TempFile::new("room_max_peerz = 3\n");
+        let mut layers = layers(Some(&file), &["--prot", "8080"]);
+        layers.get("ROOM_MAX_PEERS", 2);
+
+        assert_eq!(
+            layers.finish(),
+            vec![
+                "PROT: unknown setting (command line)".to_string(),
+                "ROOM_MAX_PEERZ: unknown setting (config file)".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_invalid_values_instead_of_using_the_default() {
+        let mut layers = layers(
+            None,
+            &[
+                "--room-max-peers",
+                "two",
+                "--trusted-proxies",
+                "10.0.0.1,nope",
+            ],
+        );
+
+        assert_eq!(layers.get("ROOM_MAX_PEERS", 2), 2);
+        let proxies: Option<Vec<std::net::IpAddr>> = layers.get_list("TRUSTED_PROXIES");
+        assert_eq!(proxies.map(|p| p.len()), Some(1));
+
+        let errors = layers.finish();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("ROOM_MAX_PEERS: invalid value \"two\" (command line)"));
+        assert!(errors[1].starts_with("TRUSTED_PROXIES: invalid item \"nope\" (command line)"));
+    }
+
+    #[test]
+    fn reads_secrets_from_files() {
+        let secret = TempFile::new("from-file\n");
+        let path = secret.path().display().to_string();
+        let mut layers = layers(None, &["--turn-secret-file", &path]);
+
+        let turn_secret = layers.get_secret("TURN_SECRET").unwrap();
+        assert_eq!(turn_secret.expose(), "from-file");
+        assert!(layers.finish().is_empty());
+    }
+
+    #[test]
+    fn rejects_a_secret_set_both_ways() {
+        let secret = TempFile::new("from-file\n");
+        let path = secret.path().display().to_string();
+        let mut layers = layers(
+            None,
+            &["--turn-secret", "direct", "--turn-secret-file", &path],
+        );
+
+        assert!(layers.get_secret("TURN_SECRET").is_none());
+        assert_eq!(
+            layers.finish(),
+            vec!["TURN_SECRET: set either TURN_SECRET or TURN_SECRET_FILE, not both".to_string()]
+        );
+    }
+
+    #[test]
+    fn reports_unreadable_secret_files() {
+        let secret = TempFile::new("");
+        let path = secret.path().display().to_string();
+        let mut layers = layers(None, &["--turn-secret-file", &path]);
+
+        assert!(layers.get_secret("TURN_SECRET").is_none());
+        let errors = layers.finish();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("TURN_SECRET_FILE: "));
+    }
+
+    #[test]
+    fn reports_unreadable_config_files() {
+        let file = TempFile::new("port = [[1]]\n");
+        let errors = layers(Some(&file), &[]).finish();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("port in "));
+
+        let file = TempFile::new("port = ");
+        let errors = layers(Some(&file), &[]).finish();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("cannot read config file"));
+    }
+}
@@ -0,0 +1,150 @@
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Shown instead of secrets in logs and by `--print-config`
+pub const REDACTED: &str = "<redacted>";
+
+/// A secret setting, such as `TURN_SECRET`. `Debug` and serialization never show the value.
+///
+/// Set `NAME_FILE` instead of `NAME` to read it from a file (Docker and Kubernetes secrets).
+/// Clones share the value, so a file-backed secret can be rotated in place with `reload`.
+#[derive(Clone)]
+pub struct Secret {
+    inner: Arc<SecretInner>,
+}
+
+struct SecretInner {
+    /// Setting name, for logs
+    name: String,
+    value: RwLock<String>,
+    file: Option<PathBuf>,
+}
+
+impl Secret {
+    pub fn new(name: &str, value: String) -> Self {
+        Self::build(name, value, None)
+    }
+
+    pub fn from_file(name: &str, file: PathBuf) -> Result<Self, String> {
+        let value = read_secret_file(&file)?;
+        Ok(Self::build(name, value, Some(file)))
+    }
+
+    fn build(name: &str, value: String, file: Option<PathBuf>) -> Self {
+        Self {
+            inner: Arc::new(SecretInner {
+                name: name.to_string(),
+                value: RwLock::new(value),
+                file,
+            }),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    /// The current value. Keep it out of logs.
+    pub fn expose(&self) -> String {
+        self.inner
+            .value
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Re-read a file-backed secret. Returns whether the value changed.
+    pub fn reload(&self) -> Result<bool, String> {
+        let Some(file) = &self.inner.file else {
+            return Ok(false);
+        };
+        let value = read_secret_file(file)?;
+        let mut current = self.inner.value.write().unwrap_or_else(|e| e.into_inner());
+        if *current == value {
+            return Ok(false);
+        }
+        *current = value;
+        Ok(true)
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret({REDACTED})")
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+/// Read a secret file, without the trailing newline most editors and `echo` add
+fn read_secret_file(file: &Path) -> Result<String, String> {
+    let value = std::fs::read_to_string(file)
+        .map_err(|e| format!("cannot read {}: {e}", file.display()))?;
+    let value = value.trim_end_matches(['\r', '\n']);
+    if value.is_empty() {
+        return Err(format!("{} is empty", file.display()));
+    }
+    Ok(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::TempFile;
+
+    fn from_file(file: &TempFile) -> Result<Secret, String> {
+        Secret::from_file("TURN_SECRET", file.path().to_path_buf())
+    }
+
+    #[test]
+    fn file_value_drops_the_trailing_newline() {
+        let file = TempFile::new("s3cret \r\n");
+        assert_eq!(from_file(&file).unwrap().expose(), "s3cret ");
+    }
+
+    #[test]
+    fn empty_file_is_an_error() {
+        let file = TempFile::new("\n");
+        assert!(from_file(&file).unwrap_err().ends_with("is empty"));
+    }
+
+    #[test]
+    fn reload_rotates_every_clone() {
+        let file = TempFile::new("old\n");
+        let secret = from_file(&file).unwrap();
+        let clone = secret.clone();
+
+        assert_eq!(secret.reload(), Ok(false));
+        std::fs::write(file.path(), "new\n").unwrap();
+        assert_eq!(secret.reload(), Ok(true));
+        assert_eq!(clone.expose(), "new");
+
+        // A file emptied by mistake keeps the last good value
+        std::fs::write(file.path(), "").unwrap();
+        assert!(secret.reload().is_err());
+        assert_eq!(clone.expose(), "new");
+    }
+
+    #[test]
+    fn plain_secret_does_not_reload() {
+        let secret = Secret::new("ADMIN_TOKEN", "token".to_string());
+        assert_eq!(secret.reload(), Ok(false));
+        assert_eq!(secret.expose(), "token");
+    }
+
+    #[test]
+    fn value_is_redacted() {
+        let secret = Secret::new("ADMIN_TOKEN", "token".to_string());
+        assert_eq!(format!("{secret:?}"), format!("Secret({REDACTED})"));
+        assert_eq!(
+            serde_json::to_string(&secret).unwrap(),
+            format!("\"{REDACTED}\"")
+        );
+    }
+}
@@ -1,4 +1,6 @@
+use crate::models::{ServerMessage, error_codes};
 use thiserror::Error;
+use uuid::Uuid;
 use warp::reject::Reject;
 
 #[derive(Error, Debug)]
@@ -7,17 +9,83 @@ pub enum AppError {
     RoomNotFound(String),
 
     #[error("Invalid password")]
-    InvalidPassword,
+    InvalidPassword { failed_attempts: u32 },
+
+    #[error("Too many password attempts, retry in {retry_after_secs}s")]
+    PasswordAttemptsExceeded { retry_after_secs: u64 },
+
+    #[error("Invalid room name: {0}")]
+    InvalidRoomName(String),
+
+    #[error("Room name already taken: {0}")]
+    RoomNameTaken(String),
+
+    #[error("Room is locked")]
+    RoomLocked,
+
+    #[error("Only the room owner can do this")]
+    NotRoomOwner,
 
     #[error("Room capacity exceeded")]
     RoomCapacityExceeded,
 
-    #[error("Rate limit exceeded")]
-    RateLimitExceeded,
+    #[error("Too many join requests are waiting for the room owner")]
+    TooManyJoinRequests,
+
+    #[error("Rate limit exceeded, retry in {retry_after_secs}s")]
+    RateLimitExceeded { retry_after_secs: u64 },
 
     #[error("Not in a room")]
     NotInRoom,
 
+    #[error("Session expired or unknown")]
+    SessionExpired,
+
+    #[error("Peer not found: {0}")]
+    PeerNotFound(uuid::Uuid),
+
+    #[error("Relay is disabled")]
+    RelayDisabled,
+
+    #[error("Invalid or expired relay token")]
+    InvalidRelayToken,
+
+    #[error("Origin not allowed")]
+    OriginNotAllowed,
+
+    #[error("Invalid message: {0}")]
+    InvalidMessage(String),
+
+    #[error("Messages are limited to {max_bytes} bytes")]
+    MessageTooLarge { max_bytes: usize },
+
+    #[error("Too many messages, disconnecting")]
+    MessageRateExceeded,
+
+    #[error("Connection too slow, disconnecting")]
+    SlowConsumer,
+
+    #[error("Server is shutting down, retry in {retry_after_secs}s")]
+    ServerShuttingDown { retry_after_secs: u64 },
+
+    #[error("Missing or invalid admin token")]
+    Unauthorized,
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("Not found")]
+    NotFound,
+
+    #[error("Method not allowed")]
+    MethodNotAllowed,
+
+    #[error("Request body too large")]
+    PayloadTooLarge,
+
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+
     #[error("Internal error: {0}")]
     InternalError(String),
 }
@@ -25,17 +93,190 @@ pub enum AppError {
 // Make AppError compatible with Warp rejections
 impl Reject for AppError {}
 
-// Convert AppError to HTTP status + message
+// Convert AppError to HTTP status, error code and client message
 impl AppError {
     pub fn status_code(&self) -> warp::http::StatusCode {
         use warp::http::StatusCode;
         match self {
             AppError::RoomNotFound(_) => StatusCode::NOT_FOUND,
-            AppError::InvalidPassword => StatusCode::UNAUTHORIZED,
+            AppError::InvalidPassword { .. } => StatusCode::UNAUTHORIZED,
+            AppError::PasswordAttemptsExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::InvalidRoomName(_) => StatusCode::BAD_REQUEST,
+            AppError::RoomNameTaken(_) => StatusCode::CONFLICT,
+            AppError::RoomLocked => StatusCode::FORBIDDEN,
+            AppError::NotRoomOwner => StatusCode::FORBIDDEN,
             AppError::RoomCapacityExceeded => StatusCode::CONFLICT,
-            AppError::RateLimitExceeded => StatusCode::TOO_MANY_REQUESTS,
+            AppError::TooManyJoinRequests => StatusCode::TOO_MANY_REQUESTS,
+            AppError::RateLimitExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
             AppError::NotInRoom => StatusCode::BAD_REQUEST,
+            AppError::SessionExpired => StatusCode::GONE,
+            AppError::PeerNotFound(_) => StatusCode::NOT_FOUND,
+            AppError::RelayDisabled => StatusCode::FORBIDDEN,
+            AppError::InvalidRelayToken => StatusCode::FORBIDDEN,
+            AppError::OriginNotAllowed => StatusCode::FORBIDDEN,
+            AppError::InvalidMessage(_) => StatusCode::BAD_REQUEST,
+            AppError::MessageTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::MessageRateExceeded => StatusCode::TOO_MANY_REQUESTS,
+            AppError::SlowConsumer => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::ServerShuttingDown { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AppError::InvalidConfig(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            AppError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
             AppError::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
-}
\ No newline at end of file
+
+    /// Machine-readable code, the same over HTTP and WebSocket
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::RoomNotFound(_) => error_codes::ROOM_NOT_FOUND,
+            AppError::InvalidPassword { .. } => error_codes::INVALID_PASSWORD,
+            AppError::PasswordAttemptsExceeded { .. } => error_codes::PASSWORD_ATTEMPTS_EXCEEDED,
+            AppError::InvalidRoomName(_) => error_codes::INVALID_ROOM_NAME,
+            AppError::RoomNameTaken(_) => error_codes::ROOM_NAME_TAKEN,
+            AppError::RoomLocked => error_codes::ROOM_LOCKED,
+            AppError::NotRoomOwner => error_codes::NOT_ROOM_OWNER,
+            AppError::RoomCapacityExceeded => error_codes::ROOM_FULL,
+            AppError::TooManyJoinRequests => error_codes::TOO_MANY_JOIN_REQUESTS,
+            AppError::RateLimitExceeded { .. } => error_codes::RATE_LIMITED,
+            AppError::NotInRoom => error_codes::NOT_IN_ROOM,
+            AppError::SessionExpired => error_codes::SESSION_EXPIRED,
+            AppError::PeerNotFound(_) => error_codes::PEER_NOT_FOUND,
+            AppError::RelayDisabled => error_codes::RELAY_DISABLED,
+            AppError::InvalidRelayToken => error_codes::INVALID_RELAY_TOKEN,
+            AppError::OriginNotAllowed => error_codes::ORIGIN_NOT_ALLOWED,
+            AppError::InvalidMessage(_) => error_codes::INVALID_MESSAGE,
+            AppError::MessageTooLarge { .. } => error_codes::MESSAGE_TOO_LARGE,
+            AppError::MessageRateExceeded => error_codes::MESSAGE_RATE_EXCEEDED,
+            AppError::SlowConsumer => error_codes::SLOW_CONSUMER,
+            AppError::ServerShuttingDown { .. } => error_codes::SERVER_SHUTTING_DOWN,
+            AppError::Unauthorized => error_codes::UNAUTHORIZED,
+            AppError::InvalidConfig(_) => error_codes::INVALID_CONFIG,
+            AppError::NotFound => error_codes::NOT_FOUND,
+            AppError::MethodNotAllowed => error_codes::METHOD_NOT_ALLOWED,
+            AppError::PayloadTooLarge => error_codes::PAYLOAD_TOO_LARGE,
+            AppError::BadRequest(_) => error_codes::BAD_REQUEST,
+            AppError::InternalError(_) => error_codes::INTERNAL_ERROR,
+        }
+    }
+
+    /// Seconds the client should wait before retrying, when there is a known wait
+    pub fn retry_after_secs(&self) -> Option<u64> {
+        match self {
+            AppError::PasswordAttemptsExceeded { retry_after_secs }
+            | AppError::RateLimitExceeded { retry_after_secs }
+            | AppError::ServerShuttingDown { retry_after_secs } => Some(*retry_after_secs),
+            _ => None,
+        }
+    }
+
+    /// The error as sent to clients, under `request_id`.
+    /// The id is logged with the error, so a client can quote it in a support request.
+    pub fn to_message(&self, request_id: String) -> ServerMessage {
+        if self.status_code().is_server_error() {
+            tracing::error!(request_id = %request_id, code = self.code(), error = %self, "Request failed");
+        } else {
+            tracing::info!(request_id = %request_id, code = self.code(), error = %self, "Request rejected");
+        }
+
+        ServerMessage::Error {
+            code: self.code().to_string(),
+            message: self.to_string(),
+            retry_after_secs: self.retry_after_secs(),
+            request_id,
+        }
+    }
+}
+
+/// Fresh id tying an error sent to a client to its log line
+pub fn new_request_id() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use warp::http::StatusCode;
+
+    /// One error of each kind
+    fn every_error() -> Vec<AppError> {
+        vec![
+            AppError::RoomNotFound("room".into()),
+            AppError::InvalidPassword { failed_attempts: 1 },
+            AppError::PasswordAttemptsExceeded {
+                retry_after_secs: 2,
+            },
+            AppError::InvalidRoomName("too short".into()),
+            AppError::RoomNameTaken("room".into()),
+            AppError::RoomLocked,
+            AppError::NotRoomOwner,
+            AppError::RoomCapacityExceeded,
+            AppError::TooManyJoinRequests,
+            AppError::RateLimitExceeded {
+                retry_after_secs: 3,
+            },
+            AppError::NotInRoom,
+            AppError::SessionExpired,
+            AppError::PeerNotFound(Uuid::nil()),
+            AppError::RelayDisabled,
+            AppError::InvalidRelayToken,
+            AppError::OriginNotAllowed,
+            AppError::InvalidMessage("bad".into()),
+            AppError::MessageTooLarge { max_bytes: 16 },
+            AppError::MessageRateExceeded,
+            AppError::SlowConsumer,
+            AppError::ServerShuttingDown {
+                retry_after_secs: 4,
+            },
+            AppError::Unauthorized,
+            AppError::InvalidConfig("port".into()),
+            AppError::NotFound,
+            AppError::MethodNotAllowed,
+            AppError::PayloadTooLarge,
+            AppError::BadRequest("bad".into()),
+            AppError::InternalError("oops".into()),
+        ]
+    }
+
+    #[test]
+    fn messages_carry_the_code_and_retry_delay() {
+        for e in every_error() {
+            let ServerMessage::Error {
+                code,
+                message,
+                retry_after_secs,
+                request_id,
+            } = e.to_message("request".into())
+            else {
+                panic!("{e:?} is not sent as an error");
+            };
+            assert_eq!(code, e.code());
+            assert_eq!(message, e.to_string());
+            assert_eq!(retry_after_secs, e.retry_after_secs());
+            assert_eq!(request_id, "request");
+        }
+    }
+
+    #[test]
+    fn each_code_has_one_status() {
+        let mut statuses: HashMap<&str, StatusCode> = HashMap::new();
+        for e in every_error() {
+            let status = *statuses.entry(e.code()).or_insert(e.status_code());
+            assert_eq!(status, e.status_code(), "{} has two statuses", e.code());
+            assert!(status.is_client_error() || status.is_server_error());
+        }
+        assert_eq!(statuses.len(), every_error().len(), "codes are distinct");
+        assert_eq!(
+            statuses[error_codes::RATE_LIMITED],
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            statuses[error_codes::INTERNAL_ERROR],
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
@@ -1,21 +1,41 @@
-use crate::models::{ClientMessage, PeerSender, ServerMessage, error_codes};
-use crate::services::{RoomService, SignalingService};
+use crate::error::{AppError, new_request_id};
+use crate::middleware::rate_limit::RateLimiter;
+use crate::models::{ClientMessage, PeerSender, ServerMessage};
+use crate::services::{
+    JoinOutcome, RelayService, ResumedSession, RoomService, SessionState, SignalingService,
+};
+use std::net::IpAddr;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Instant;
+use tokio::sync::{RwLock, oneshot};
 use uuid::Uuid;
 use warp::ws::Message;
 
+/// Close code for a connection that went away (RFC 6455)
+const CLOSE_GOING_AWAY: u16 = 1001;
+
 /// Context for the current peer connection.
 pub struct PeerContext {
     pub room_id: Option<String>,
     pub peer_id: Option<Uuid>,
+    /// Room and id of a join request waiting for the owner's approval
+    pub join_request: Option<(String, Uuid)>,
+    /// Token this connection was given to resume its session
+    pub resume_token: Option<String>,
+    pub client_ip: IpAddr,
+    /// Last time anything was received on the socket, for the heartbeat timeout
+    pub last_seen: Instant,
 }
 
 impl PeerContext {
-    pub fn new() -> Self {
+    pub fn new(client_ip: IpAddr) -> Self {
         Self {
             room_id: None,
             peer_id: None,
+            join_request: None,
+            resume_token: None,
+            client_ip,
+            last_seen: Instant::now(),
         }
     }
 }
@@ -25,18 +45,53 @@ impl PeerContext {
 pub async fn handle_client_message(
     msg: ClientMessage,
     peer_tx: &PeerSender,
-    room_service: &RoomService,
-    signaling_service: &SignalingService,
+    room_service: &Arc<RoomService>,
+    signaling_service: &Arc<SignalingService>,
+    relay_service: &RelayService,
+    rate_limiter: &RateLimiter,
     peer_context: &Arc<RwLock<PeerContext>>,
 ) {
+    // Room creation and joining are rate limited per client IP
+    if matches!(
+        msg,
+        ClientMessage::CreateRoom { .. }
+            | ClientMessage::JoinRoom { .. }
+            | ClientMessage::ResumeSession { .. }
+    ) {
+        let client_ip = peer_context.read().await.client_ip;
+        if let Err(e) = rate_limiter.check(client_ip).await {
+            send_error(peer_tx, &e);
+            return;
+        }
+    }
+
     match msg {
-        ClientMessage::CreateRoom { password } => {
-            handle_create_room(password, peer_tx, room_service, peer_context).await;
+        ClientMessage::CreateRoom {
+            password,
+            room_name,
+            require_approval,
+        } => {
+            handle_create_room(
+                password,
+                room_name,
+                require_approval,
+                peer_tx,
+                room_service,
+                peer_context,
+            )
+            .await;
         }
-        ClientMessage::JoinRoom { room_id, password } => {
+        ClientMessage::JoinRoom {
+            room_id,
+            password,
+            token,
+            display_name,
+        } => {
             handle_join_room(
                 room_id,
+                token,
                 password,
+                display_name,
                 peer_tx,
                 room_service,
                 signaling_service,
@@ -44,90 +99,351 @@ pub async fn handle_client_message(
             )
             .await;
         }
-        ClientMessage::Signal { data } => {
-            handle_signal(data, peer_tx, room_service, signaling_service, peer_context).await;
+        ClientMessage::ResumeSession {
+            room_id,
+            peer_id,
+            resume_token,
+            last_seq,
+        } => {
+            handle_resume_session(
+                room_id,
+                peer_id,
+                resume_token,
+                last_seq,
+                peer_tx,
+                room_service,
+                peer_context,
+            )
+            .await;
+        }
+        ClientMessage::Signal { data, to } => {
+            handle_signal(
+                data,
+                to,
+                peer_tx,
+                room_service,
+                signaling_service,
+                peer_context,
+            )
+            .await;
+        }
+        ClientMessage::RequestRelay { to } => {
+            handle_request_relay(
+                to,
+                peer_tx,
+                room_service,
+                signaling_service,
+                relay_service,
+                peer_context,
+            )
+            .await;
         }
         ClientMessage::Ping => {
             send_message(peer_tx, ServerMessage::Pong);
         }
+        ClientMessage::KickPeer { .. }
+        | ClientMessage::LockRoom { .. }
+        | ClientMessage::SetPassword { .. }
+        | ClientMessage::CloseRoom { .. }
+        | ClientMessage::AnswerJoinRequest { .. } => {
+            handle_host_control(msg, peer_tx, room_service, signaling_service, peer_context).await;
+        }
     }
 }
 
 async fn handle_create_room(
     password: Option<String>,
+    room_name: Option<String>,
+    require_approval: Option<bool>,
     peer_tx: &PeerSender,
     room_service: &RoomService,
     peer_context: &Arc<RwLock<PeerContext>>,
 ) {
-    match room_service.create_room(password.clone()).await {
-        Ok(room_id) => {
+    match room_service
+        .create_room(password, room_name, require_approval)
+        .await
+    {
+        Ok(room) => {
+            let room_id = room.id().to_string();
+            let share_token = room.share_token().map(str::to_string);
+
             // Add creator as first peer
-            match room_service
-                .join_room(&room_id, password, peer_tx.clone())
-                .await
-            {
+            match room_service.join_as_owner(&room, peer_tx.clone()).await {
                 Ok((peer_id, _)) => {
+                    let resume_token = room_service.issue_resume_token(&room_id, peer_id).await;
+
                     // Update peer context
                     let mut ctx = peer_context.write().await;
                     ctx.room_id = Some(room_id.clone());
                     ctx.peer_id = Some(peer_id);
+                    ctx.resume_token = Some(resume_token.clone());
                     drop(ctx);
 
-                    send_message(peer_tx, ServerMessage::RoomCreated { room_id });
-                }
-                Err(e) => {
-                    send_error(peer_tx, error_codes::ROOM_NOT_FOUND, &e.to_string());
+                    let ice_servers = room_service.ice_servers(&room_id, peer_id);
+                    send_message(
+                        peer_tx,
+                        ServerMessage::RoomCreated {
+                            room_id,
+                            share_token,
+                            owner_token: room.owner_token().unwrap_or_default().to_string(),
+                            peer_id,
+                            resume_token,
+                            ice_servers,
+                        },
+                    );
                 }
+                Err(e) => send_error(peer_tx, &e),
             }
         }
-        Err(e) => {
-            send_error(peer_tx, error_codes::ROOM_NOT_FOUND, &e.to_string());
-        }
+        Err(e) => send_error(peer_tx, &e),
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_join_room(
     room_id: String,
+    share_token: Option<String>,
     password: Option<String>,
+    display_name: Option<String>,
     peer_tx: &PeerSender,
-    room_service: &RoomService,
-    signaling_service: &SignalingService,
+    room_service: &Arc<RoomService>,
+    signaling_service: &Arc<SignalingService>,
     peer_context: &Arc<RwLock<PeerContext>>,
 ) {
+    let ctx = peer_context.read().await;
+    let client_ip = ctx.client_ip;
+    if ctx.join_request.is_some() {
+        let e = AppError::InvalidMessage("A join request is already waiting for approval".into());
+        send_error(peer_tx, &e);
+        return;
+    }
+    drop(ctx);
+
     match room_service
-        .join_room(&room_id, password, peer_tx.clone())
+        .join_room(
+            &room_id,
+            share_token.as_deref(),
+            password,
+            display_name,
+            peer_tx.clone(),
+            client_ip,
+        )
         .await
     {
-        Ok((peer_id, peer_count)) => {
-            // Update peer context
-            let mut ctx = peer_context.write().await;
-            ctx.room_id = Some(room_id.clone());
-            ctx.peer_id = Some(peer_id);
-            drop(ctx);
+        Ok(JoinOutcome::Joined {
+            peer_id,
+            peer_count,
+        }) => {
+            finish_join(
+                &room_id,
+                peer_id,
+                peer_count,
+                peer_tx,
+                room_service,
+                signaling_service,
+                peer_context,
+            )
+            .await;
+        }
+        Ok(JoinOutcome::Pending {
+            request_id,
+            display_name,
+            fingerprint,
+            decision,
+        }) => {
+            peer_context.write().await.join_request = Some((room_id.clone(), request_id));
+            send_message(peer_tx, ServerMessage::JoinPending { request_id });
 
-            // Notify other peers
             if let Some(room) = room_service.get_room(&room_id).await {
                 signaling_service
-                    .broadcast_peer_joined(&room, peer_id, peer_count)
+                    .notify_join_request(&room, request_id, display_name, fingerprint)
                     .await;
             }
 
-            send_message(peer_tx, ServerMessage::RoomJoined);
+            tokio::spawn(await_join_answer(
+                room_id,
+                request_id,
+                decision,
+                peer_tx.clone(),
+                room_service.clone(),
+                signaling_service.clone(),
+                peer_context.clone(),
+            ));
         }
         Err(e) => {
-            let code = match &e {
-                crate::error::AppError::RoomNotFound(_) => error_codes::ROOM_NOT_FOUND,
-                crate::error::AppError::InvalidPassword => error_codes::INVALID_PASSWORD,
-                crate::error::AppError::RoomCapacityExceeded => error_codes::ROOM_FULL,
-                _ => error_codes::INVALID_MESSAGE,
-            };
-            send_error(peer_tx, code, &e.to_string());
+            if let AppError::InvalidPassword { failed_attempts } = e
+                && room_service.notify_password_failures()
+                && let Some(room) = room_service.get_room(&room_id).await
+            {
+                signaling_service
+                    .notify_password_failure(&room, failed_attempts)
+                    .await;
+            }
+
+            send_error(peer_tx, &e);
         }
     }
 }
 
+/// Wait for the owner to answer a join request, then admit or turn away the joiner
+async fn await_join_answer(
+    room_id: String,
+    request_id: Uuid,
+    decision: oneshot::Receiver<bool>,
+    peer_tx: PeerSender,
+    room_service: Arc<RoomService>,
+    signaling_service: Arc<SignalingService>,
+    peer_context: Arc<RwLock<PeerContext>>,
+) {
+    let answer = tokio::time::timeout(room_service.join_request_timeout(), decision).await;
+    peer_context.write().await.join_request = None;
+
+    let reason = match answer {
+        Ok(Ok(true)) => {
+            match room_service
+                .admit_join_request(&room_id, request_id, peer_tx.clone())
+                .await
+            {
+                Ok((peer_id, peer_count)) => {
+                    finish_join(
+                        &room_id,
+                        peer_id,
+                        peer_count,
+                        &peer_tx,
+                        &room_service,
+                        &signaling_service,
+                        &peer_context,
+                    )
+                    .await;
+                }
+                Err(e) => send_error(&peer_tx, &e),
+            }
+            return;
+        }
+        Ok(Ok(false)) => "The room owner declined your request",
+        // Dropped with the room, or because the joiner left
+        Ok(Err(_)) => "The room is no longer available",
+        Err(_) => {
+            if room_service.cancel_join_request(&room_id, request_id).await
+                && let Some(room) = room_service.get_room(&room_id).await
+            {
+                signaling_service
+                    .notify_join_request_cancelled(&room, request_id)
+                    .await;
+            }
+            "The room owner did not answer in time"
+        }
+    };
+
+    send_message(
+        &peer_tx,
+        ServerMessage::JoinRejected {
+            reason: reason.to_string(),
+        },
+    );
+}
+
+/// Record a successful join, tell the other peers and send `RoomJoined`
+async fn finish_join(
+    room_id: &str,
+    peer_id: Uuid,
+    peer_count: usize,
+    peer_tx: &PeerSender,
+    room_service: &RoomService,
+    signaling_service: &SignalingService,
+    peer_context: &Arc<RwLock<PeerContext>>,
+) {
+    let resume_token = room_service.issue_resume_token(room_id, peer_id).await;
+
+    // Update peer context. `cleanup_peer` closes the sender before reading the context,
+    // so a joiner admitted after its connection closed is only seen here.
+    let mut ctx = peer_context.write().await;
+    if peer_tx.is_closed() {
+        drop(ctx);
+        if let Err(e) = room_service.leave_room(room_id, peer_id).await {
+            tracing::error!(room_id = %room_id, peer_id = %peer_id, error = %e, "Failed to remove disconnected joiner");
+        }
+        return;
+    }
+    ctx.room_id = Some(room_id.to_string());
+    ctx.peer_id = Some(peer_id);
+    ctx.resume_token = Some(resume_token.clone());
+    drop(ctx);
+
+    // Notify other peers
+    if let Some(room) = room_service.get_room(room_id).await {
+        signaling_service
+            .broadcast_peer_joined(&room, peer_id, peer_count)
+            .await;
+    }
+
+    let peers = room_service.other_peer_ids(room_id, peer_id).await;
+    let ice_servers = room_service.ice_servers(room_id, peer_id);
+    send_message(
+        peer_tx,
+        ServerMessage::RoomJoined {
+            peer_id,
+            peers,
+            resume_token,
+            ice_servers,
+        },
+    );
+}
+
+async fn handle_resume_session(
+    room_id: String,
+    peer_id: Uuid,
+    resume_token: String,
+    last_seq: Option<u64>,
+    peer_tx: &PeerSender,
+    room_service: &RoomService,
+    peer_context: &Arc<RwLock<PeerContext>>,
+) {
+    let ctx = peer_context.read().await;
+    if ctx.room_id.is_some() || ctx.join_request.is_some() {
+        let e = AppError::InvalidMessage("This connection already belongs to a room".into());
+        send_error(peer_tx, &e);
+        return;
+    }
+    drop(ctx);
+
+    match room_service
+        .resume_session(&room_id, peer_id, &resume_token, peer_tx.clone(), last_seq)
+        .await
+    {
+        Ok(ResumedSession {
+            resume_token,
+            mailbox,
+        }) => {
+            let mut ctx = peer_context.write().await;
+            ctx.room_id = Some(room_id.clone());
+            ctx.peer_id = Some(peer_id);
+            ctx.resume_token = Some(resume_token.clone());
+            drop(ctx);
+
+            let peers = room_service.other_peer_ids(&room_id, peer_id).await;
+            let ice_servers = room_service.ice_servers(&room_id, peer_id);
+            send_message(
+                peer_tx,
+                ServerMessage::SessionResumed {
+                    peer_id,
+                    peers,
+                    resume_token,
+                    ice_servers,
+                },
+            );
+
+            // Signals that arrived while the connection was down, then new ones as they come
+            let replayed = mailbox.reattach(peer_tx.clone(), last_seq);
+            tracing::debug!(room_id = %room_id, peer_id = %peer_id, replayed, "Buffered signals resent");
+        }
+        Err(e) => send_error(peer_tx, &e),
+    }
+}
+
 async fn handle_signal(
     data: serde_json::Value,
+    to: Option<Uuid>,
     peer_tx: &PeerSender,
     room_service: &RoomService,
     signaling_service: &SignalingService,
@@ -137,71 +453,280 @@ async fn handle_signal(
     let (room_id, peer_id) = match (&ctx.room_id, ctx.peer_id) {
         (Some(rid), Some(pid)) => (rid.clone(), pid),
         _ => {
-            send_error(peer_tx, error_codes::NOT_IN_ROOM, "Not in a room");
+            send_error(peer_tx, &AppError::NotInRoom);
             return;
         }
     };
     drop(ctx);
 
-    if let Some(room) = room_service.get_room(&room_id).await {
-        signaling_service
-            .broadcast_signal(&room, peer_id, data)
-            .await;
+    // The sender is connected here, so its room is in the local cache; every
+    // trickled ICE candidate passes this way, so avoid shared storage when possible
+    let Some(room) = room_service.get_local_room(&room_id).await else {
+        return;
+    };
+
+    match to {
+        Some(target_id) => {
+            if target_id == peer_id || !room_service.has_peer_cached(&room_id, target_id).await {
+                send_error(peer_tx, &AppError::PeerNotFound(target_id));
+                return;
+            }
+            signaling_service
+                .send_signal(&room, peer_id, target_id, data)
+                .await;
+        }
+        None => {
+            signaling_service
+                .broadcast_signal(&room, peer_id, data)
+                .await;
+        }
     }
 }
 
-/// Cleanup when a peer disconnects
-pub async fn cleanup_peer(
-    peer_context: Arc<RwLock<PeerContext>>,
+/// Hand both peers a relay token, for when WebRTC cannot connect them
+async fn handle_request_relay(
+    to: Uuid,
+    peer_tx: &PeerSender,
     room_service: &RoomService,
     signaling_service: &SignalingService,
+    relay_service: &RelayService,
+    peer_context: &Arc<RwLock<PeerContext>>,
 ) {
     let ctx = peer_context.read().await;
     let (room_id, peer_id) = match (&ctx.room_id, ctx.peer_id) {
         (Some(rid), Some(pid)) => (rid.clone(), pid),
-        _ => return,
+        _ => {
+            send_error(peer_tx, &AppError::NotInRoom);
+            return;
+        }
     };
     drop(ctx);
 
-    // Get room before removing peer (to broadcast to remaining peers)
-    if let Some(room) = room_service.get_room(&room_id).await {
-        let was_deleted = room_service
-            .leave_room(&room_id, peer_id)
-            .await
-            .unwrap_or(false);
+    if !relay_service.is_enabled() {
+        send_error(peer_tx, &AppError::RelayDisabled);
+        return;
+    }
+    if to == peer_id || !room_service.has_peer(&room_id, to).await {
+        send_error(peer_tx, &AppError::PeerNotFound(to));
+        return;
+    }
+    let Some(room) = room_service.get_room(&room_id).await else {
+        return;
+    };
 
-        if !was_deleted {
-            let remaining = room.peers().read().await.len().saturating_sub(1);
+    send_message(peer_tx, relay_service.relay_ready(&room_id, peer_id, to));
+    signaling_service
+        .notify_relay_ready(&room, to, relay_service.relay_ready(&room_id, to, peer_id))
+        .await;
+}
+
+/// Kick, lock, password, close and join approval commands from the room owner
+async fn handle_host_control(
+    msg: ClientMessage,
+    peer_tx: &PeerSender,
+    room_service: &RoomService,
+    signaling_service: &SignalingService,
+    peer_context: &Arc<RwLock<PeerContext>>,
+) {
+    let Some(room_id) = peer_context.read().await.room_id.clone() else {
+        send_error(peer_tx, &AppError::NotInRoom);
+        return;
+    };
+
+    if let Err(e) = run_host_control(msg, &room_id, room_service, signaling_service).await {
+        send_error(peer_tx, &e);
+    }
+}
+
+async fn run_host_control(
+    msg: ClientMessage,
+    room_id: &str,
+    room_service: &RoomService,
+    signaling_service: &SignalingService,
+) -> Result<(), AppError> {
+    match msg {
+        ClientMessage::KickPeer {
+            owner_token,
+            peer_id,
+        } => {
+            let room = room_service
+                .kick_peer(room_id, &owner_token, peer_id)
+                .await?;
+            signaling_service.notify_kicked(&room, peer_id).await;
+        }
+        ClientMessage::LockRoom {
+            owner_token,
+            locked,
+        } => {
+            let room = room_service
+                .set_locked(room_id, &owner_token, locked)
+                .await?;
+            signaling_service.broadcast_room_locked(&room, locked).await;
+        }
+        ClientMessage::SetPassword {
+            owner_token,
+            password,
+        } => {
+            let (room, has_password) = room_service
+                .set_password(room_id, &owner_token, password)
+                .await?;
             signaling_service
-                .broadcast_peer_left(&room, remaining)
+                .broadcast_password_changed(&room, has_password)
                 .await;
         }
+        ClientMessage::CloseRoom { owner_token } => {
+            let room = room_service.close_room(room_id, &owner_token).await?;
+            signaling_service.broadcast_room_closed(&room).await;
+        }
+        ClientMessage::AnswerJoinRequest {
+            owner_token,
+            request_id,
+            approve,
+        } => {
+            let room = room_service
+                .answer_join_request(room_id, &owner_token, request_id, approve)
+                .await?;
+            signaling_service
+                .answer_join_request(&room, request_id, approve)
+                .await;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Cleanup when a peer disconnects
+pub async fn cleanup_peer(
+    peer_context: Arc<RwLock<PeerContext>>,
+    peer_tx: &PeerSender,
+    room_service: &Arc<RoomService>,
+    signaling_service: &Arc<SignalingService>,
+) {
+    // Refuse anything sent from now on; a join answered meanwhile checks this
+    peer_tx.close(CLOSE_GOING_AWAY, "Connection lost");
+
+    let ctx = peer_context.read().await;
+    let join_request = ctx.join_request.clone();
+    let resume_token = ctx.resume_token.clone();
+    let (room_id, peer_id) = match (&ctx.room_id, ctx.peer_id) {
+        (Some(rid), Some(pid)) => (rid.clone(), pid),
+        _ => {
+            drop(ctx);
+            // Withdraw a join request still waiting for the owner
+            if let Some((room_id, request_id)) = join_request
+                && room_service.cancel_join_request(&room_id, request_id).await
+                && let Some(room) = room_service.get_room(&room_id).await
+            {
+                signaling_service
+                    .notify_join_request_cancelled(&room, request_id)
+                    .await;
+            }
+            return;
+        }
+    };
+    drop(ctx);
+
+    if let Some(resume_token) = resume_token {
+        match room_service
+            .session_state(&room_id, peer_id, &resume_token)
+            .await
+        {
+            // Another connection already took over this peer
+            SessionState::Replaced => return,
+            // Hold the peer's place for a while; other peers see nothing unless it expires
+            SessionState::Current if !room_service.session_grace().is_zero() => {
+                tokio::spawn(expire_session(
+                    room_id,
+                    peer_id,
+                    resume_token,
+                    room_service.clone(),
+                    signaling_service.clone(),
+                ));
+                return;
+            }
+            _ => {}
+        }
+    }
+
+    leave_and_notify(&room_id, peer_id, room_service, signaling_service).await;
+}
+
+/// Remove a dropped peer once its grace period ends, unless its session was resumed.
+/// The timer belongs to the connection that was given `resume_token`: once the session
+/// is resumed, the new connection holds the peer and arms its own timer if it drops.
+async fn expire_session(
+    room_id: String,
+    peer_id: Uuid,
+    resume_token: String,
+    room_service: Arc<RoomService>,
+    signaling_service: Arc<SignalingService>,
+) {
+    tokio::time::sleep(room_service.session_grace()).await;
+
+    match room_service
+        .session_state(&room_id, peer_id, &resume_token)
+        .await
+    {
+        SessionState::Replaced => {}
+        SessionState::Current => {
+            tracing::info!(room_id = %room_id, peer_id = %peer_id, "Session grace period expired");
+            leave_and_notify(&room_id, peer_id, &room_service, &signaling_service).await;
+        }
+        // Kicked or room closed while away
+        SessionState::Revoked => {
+            leave_and_notify(&room_id, peer_id, &room_service, &signaling_service).await;
+        }
+    }
+}
+
+async fn leave_and_notify(
+    room_id: &str,
+    peer_id: Uuid,
+    room_service: &RoomService,
+    signaling_service: &SignalingService,
+) {
+    // The peer may have left already, e.g. when an expiring session was also kicked
+    if !room_service.has_peer(room_id, peer_id).await {
+        return;
+    }
+
+    // Get room before removing peer (to broadcast to remaining peers)
+    if let Some(room) = room_service.get_room(room_id).await {
+        match room_service.leave_room(room_id, peer_id).await {
+            Ok(0) => {}
+            Ok(remaining) => {
+                signaling_service
+                    .broadcast_peer_left(&room, peer_id, remaining)
+                    .await;
+            }
+            Err(e) => {
+                tracing::error!(room_id = %room_id, peer_id = %peer_id, error = %e, "Failed to leave room");
+            }
+        }
     }
 }
 
 // Helper functions
-fn send_message(peer_tx: &PeerSender, msg: ServerMessage) {
+pub fn send_message(peer_tx: &PeerSender, msg: ServerMessage) {
     if let Ok(text) = serde_json::to_string(&msg) {
         let _ = peer_tx.send(Message::text(text));
     }
 }
 
-fn send_error(peer_tx: &PeerSender, code: &str, message: &str) {
-    send_message(
-        peer_tx,
-        ServerMessage::Error {
-            code: code.to_string(),
-            message: message.to_string(),
-        },
-    );
+pub fn send_error(peer_tx: &PeerSender, e: &AppError) {
+    send_message(peer_tx, e.to_message(new_request_id()));
 }
 
 /// HTTP handler for checking room existence
 pub async fn check_room_handler(
     room_id: String,
+    share_token: Option<String>,
     room_service: Arc<RoomService>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    match room_service.get_room_info(&room_id).await {
+    match room_service
+        .get_room_info(&room_id, share_token.as_deref())
+        .await
+    {
         Some((exists, has_password)) => {
             let response = ServerMessage::RoomExists {
                 exists,
@@ -209,6 +734,188 @@ pub async fn check_room_handler(
             };
             Ok(warp::reply::json(&response))
         }
-        None => Err(warp::reject::not_found()),
+        None => Err(warp::reject::custom(AppError::RoomNotFound(room_id))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::InProcessBus;
+    use crate::config::OverflowPolicy;
+    use crate::models::peer_sender::PeerReceiver;
+    use crate::models::{Room, peer_channel};
+    use crate::services::room::tests::{sender, service};
+    use crate::store::InMemoryRoomStore;
+
+    const CLIENT_IP: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(192, 0, 2, 1));
+
+    fn services() -> (Arc<RoomService>, Arc<SignalingService>) {
+        let rooms = service(Arc::new(InMemoryRoomStore::new()));
+        let signaling = SignalingService::new(Arc::new(InProcessBus::new()));
+        (Arc::new(rooms), Arc::new(signaling))
+    }
+
+    /// Create a room requiring approval. Returns the room and its owner's peer id.
+    async fn approval_room(rooms: &RoomService) -> (Room, Uuid) {
+        let room = rooms.create_room(None, None, Some(true)).await.unwrap();
+        let (owner_id, _) = rooms.join_as_owner(&room, sender()).await.unwrap();
+        (room, owner_id)
+    }
+
+    /// Ask to join `room` and have the owner approve right away
+    async fn approved_request(
+        rooms: &RoomService,
+        room: &Room,
+        peer_tx: &PeerSender,
+    ) -> (Uuid, oneshot::Receiver<bool>) {
+        let outcome = rooms
+            .join_room(
+                room.id(),
+                room.share_token(),
+                None,
+                None,
+                peer_tx.clone(),
+                CLIENT_IP,
+            )
+            .await
+            .unwrap();
+        let JoinOutcome::Pending {
+            request_id,
+            decision,
+            ..
+        } = outcome
+        else {
+            panic!("join was not held for approval");
+        };
+        let local = rooms.get_local_room(room.id()).await.unwrap();
+        assert!(local.resolve_join_request(request_id, true).await);
+        (request_id, decision)
+    }
+
+    #[tokio::test]
+    async fn joiner_that_left_while_pending_is_not_admitted() {
+        let (rooms, signaling) = services();
+        let (room, owner_id) = approval_room(&rooms).await;
+        let (peer_tx, peer_rx) = peer_channel(8, OverflowPolicy::Disconnect);
+        let peer_context = Arc::new(RwLock::new(PeerContext::new(CLIENT_IP)));
+
+        let (request_id, decision) = approved_request(&rooms, &room, &peer_tx).await;
+        peer_context.write().await.join_request = Some((room.id().to_string(), request_id));
+        // The joiner disconnects before the approval is acted on
+        drop(peer_rx);
+        cleanup_peer(peer_context.clone(), &peer_tx, &rooms, &signaling).await;
+
+        await_join_answer(
+            room.id().to_string(),
+            request_id,
+            decision,
+            peer_tx,
+            rooms.clone(),
+            signaling,
+            peer_context.clone(),
+        )
+        .await;
+
+        assert_eq!(rooms.peer_ids(room.id()).await, vec![owner_id]);
+        assert!(peer_context.read().await.room_id.is_none());
+    }
+
+    #[tokio::test]
+    async fn joiner_that_left_during_admission_is_removed() {
+        let (rooms, signaling) = services();
+        let (room, owner_id) = approval_room(&rooms).await;
+        let (peer_tx, _peer_rx) = peer_channel(8, OverflowPolicy::Disconnect);
+        let peer_context = Arc::new(RwLock::new(PeerContext::new(CLIENT_IP)));
+
+        let (request_id, _) = approved_request(&rooms, &room, &peer_tx).await;
+        let (peer_id, peer_count) = rooms
+            .admit_join_request(room.id(), request_id, peer_tx.clone())
+            .await
+            .unwrap();
+        assert_eq!(peer_count, 2);
+        // The connection closes before it is bound to the room
+        cleanup_peer(peer_context.clone(), &peer_tx, &rooms, &signaling).await;
+
+        finish_join(
+            room.id(),
+            peer_id,
+            peer_count,
+            &peer_tx,
+            &rooms,
+            &signaling,
+            &peer_context,
+        )
+        .await;
+
+        assert_eq!(rooms.peer_ids(room.id()).await, vec![owner_id]);
+        assert!(peer_context.read().await.room_id.is_none());
+    }
+
+    /// Join `room` on a new connection. Returns the connection's queue and context.
+    async fn joined_connection(
+        rooms: &Arc<RoomService>,
+        signaling: &Arc<SignalingService>,
+        room: &Room,
+    ) -> (PeerSender, PeerReceiver, Arc<RwLock<PeerContext>>) {
+        let (peer_tx, peer_rx) = peer_channel(8, OverflowPolicy::Disconnect);
+        let peer_context = Arc::new(RwLock::new(PeerContext::new(CLIENT_IP)));
+        handle_join_room(
+            room.id().to_string(),
+            room.share_token().map(str::to_string),
+            None,
+            None,
+            &peer_tx,
+            rooms,
+            signaling,
+            &peer_context,
+        )
+        .await;
+        assert!(peer_context.read().await.peer_id.is_some());
+        (peer_tx, peer_rx, peer_context)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropped_peer_leaves_once_the_grace_period_expires() {
+        let (rooms, signaling) = services();
+        let room = rooms.create_room(None, None, Some(false)).await.unwrap();
+        let (peer_tx, _peer_rx, peer_context) = joined_connection(&rooms, &signaling, &room).await;
+        let peer_id = peer_context.read().await.peer_id.unwrap();
+
+        cleanup_peer(peer_context, &peer_tx, &rooms, &signaling).await;
+        tokio::time::sleep(rooms.session_grace() / 2).await;
+        assert_eq!(rooms.peer_ids(room.id()).await, vec![peer_id]);
+
+        tokio::time::sleep(rooms.session_grace()).await;
+        assert!(rooms.peer_ids(room.id()).await.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resumed_session_outlives_the_grace_period() {
+        let (rooms, signaling) = services();
+        let room = rooms.create_room(None, None, Some(false)).await.unwrap();
+        let (peer_tx, _peer_rx, peer_context) = joined_connection(&rooms, &signaling, &room).await;
+        let (peer_id, resume_token) = {
+            let ctx = peer_context.read().await;
+            (ctx.peer_id.unwrap(), ctx.resume_token.clone().unwrap())
+        };
+        cleanup_peer(peer_context, &peer_tx, &rooms, &signaling).await;
+
+        let (new_tx, _new_rx) = peer_channel(8, OverflowPolicy::Disconnect);
+        let new_context = Arc::new(RwLock::new(PeerContext::new(CLIENT_IP)));
+        handle_resume_session(
+            room.id().to_string(),
+            peer_id,
+            resume_token,
+            None,
+            &new_tx,
+            &rooms,
+            &new_context,
+        )
+        .await;
+        assert_eq!(new_context.read().await.peer_id, Some(peer_id));
+
+        tokio::time::sleep(rooms.session_grace() * 2).await;
+        assert_eq!(rooms.peer_ids(room.id()).await, vec![peer_id]);
     }
 }
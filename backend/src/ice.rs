@@ -1,14 +1,16 @@
-use serde::Serialize;
+use crate::config::Config;
+use base64::{Engine as _, engine::general_purpose};
 use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
 use sha1::Sha1;
-use base64::{Engine as _, engine::general_purpose};
-use std::env;
 use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct IceServer {
-    pub urls: String,
+    /// All URLs of one server (e.g. UDP, TCP and TLS transports) share its credentials
+    pub urls: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub username: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -21,42 +23,123 @@ pub struct IceConfig {
     pub ice_servers: Vec<IceServer>,
 }
 
-pub fn get_ice_servers() -> IceConfig {
-    let mut servers = vec![
-        // Always include Google's public STUN server as a fallback
-        IceServer {
-            urls: "stun:stun.l.google.com:19302".to_string(),
+/// Public STUN servers only. Safe to hand out to anyone.
+pub fn get_stun_servers(config: &Config) -> IceConfig {
+    let mut servers = Vec::new();
+
+    if !config.stun_urls.is_empty() {
+        servers.push(IceServer {
+            urls: config.stun_urls.clone(),
             username: None,
             credential: None,
-        },
-    ];
+        });
+    }
+
+    IceConfig {
+        ice_servers: servers,
+    }
+}
+
+/// Build the full ICE server list for a peer that has joined a room.
+/// TURN credentials are bound to the room and peer, so relay usage in the
+/// coturn logs can be traced back to a room, and name the TURN realm.
+pub fn get_ice_servers(config: &Config, room_id: &str, peer_id: Uuid) -> IceConfig {
+    let mut ice_config = get_stun_servers(config);
 
     // Check if TURN is configured
-    if let (Ok(turn_url), Ok(turn_secret)) = (env::var("TURN_URL"), env::var("TURN_SECRET")) {
-        // Generate ephemeral credentials
-        // Username format: timestamp:random_id
-        
+    if let Some(turn_secret) = config
+        .turn_secret
+        .as_ref()
+        .filter(|_| !config.turn_urls.is_empty())
+    {
+        // Generate ephemeral credentials (coturn REST API)
+        // Username format: expiry:room_id:peer_id@realm
         let expiration = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
-            .as_secs() + 86400; // Valid for 24 hours
+            .as_secs()
+            + config.turn_credential_ttl().as_secs();
+
+        let username = format!(
+            "{}:{}:{}@{}",
+            expiration, room_id, peer_id, config.turn_realm
+        );
 
-        let username = format!("{}:filecoffee", expiration);
-        
         // HMAC-SHA1(secret, username)
         type HmacSha1 = Hmac<Sha1>;
-        let mut mac = HmacSha1::new_from_slice(turn_secret.as_bytes())
+        let mut mac = HmacSha1::new_from_slice(turn_secret.expose().as_bytes())
             .expect("HMAC can take key of any size");
         mac.update(username.as_bytes());
         let result = mac.finalize();
         let password = general_purpose::STANDARD.encode(result.into_bytes());
 
-        servers.push(IceServer {
-            urls: turn_url,
+        tracing::info!(room_id = %room_id, peer_id = %peer_id, expiration, "TURN credentials issued");
+
+        ice_config.ice_servers.push(IceServer {
+            urls: config.turn_urls.clone(),
             username: Some(username),
             credential: Some(password),
         });
     }
 
-    IceConfig { ice_servers: servers }
+    ice_config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::load;
+
+    fn turn_config() -> Config {
+        load(&[
+            "--stun-urls=stun:stun.example.com:3478",
+            "--turn-urls=turn:turn.example.com:3478,turns:turn.example.com:5349",
+            "--turn-secret=s3cret",
+            "--turn-realm=turn.example.com",
+            "--turn-credential-ttl-secs=600",
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn stun_only_without_turn() {
+        let config = load(&["--stun-urls=stun:stun.example.com:3478"]).unwrap();
+        let servers = get_ice_servers(&config, "room", Uuid::new_v4()).ice_servers;
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].urls, vec!["stun:stun.example.com:3478"]);
+        assert!(servers[0].username.is_none());
+    }
+
+    #[test]
+    fn turn_credentials_are_bound_to_room_peer_and_realm() {
+        let config = turn_config();
+        let peer_id = Uuid::new_v4();
+        let servers = get_ice_servers(&config, "room", peer_id).ice_servers;
+        assert_eq!(servers.len(), 2);
+
+        let turn = &servers[1];
+        assert_eq!(turn.urls, config.turn_urls);
+        let username = turn.username.clone().unwrap();
+        let (expiration, rest) = username.split_once(':').unwrap();
+        assert_eq!(rest, format!("room:{peer_id}@turn.example.com"));
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let expiration: u64 = expiration.parse().unwrap();
+        assert!((now + 599..=now + 601).contains(&expiration));
+
+        // coturn checks the credential as base64(HMAC-SHA1(secret, username))
+        let mut mac = Hmac::<Sha1>::new_from_slice(b"s3cret").unwrap();
+        mac.update(username.as_bytes());
+        let expected = general_purpose::STANDARD.encode(mac.finalize().into_bytes());
+        assert_eq!(turn.credential.as_deref(), Some(expected.as_str()));
+    }
+
+    #[test]
+    fn public_list_never_has_credentials() {
+        let servers = get_stun_servers(&turn_config()).ice_servers;
+        assert!(servers.iter().all(|server| server.credential.is_none()));
+    }
 }
@@ -1,62 +1,277 @@
+mod bus;
 mod config;
 mod error;
 mod handlers;
 mod ice;
+mod metrics;
+mod middleware;
 mod models;
 mod routes;
 mod services;
 mod slug_generator;
 mod store;
 
-use crate::config::Config;
-use crate::routes::{api_routes, ws_route};
-use crate::services::{RoomService, SignalingService};
-use crate::store::InMemoryRoomStore;
+use crate::bus::{InProcessBus, RedisBus, SignalingBus};
+use crate::config::{Backend, CommandLine, Config, ConfigHandle, USAGE};
+use crate::middleware::cors::{allowed_origin, origin_policy, preflight_route, with_cors};
+use crate::middleware::rate_limit::RateLimiter;
+use crate::middleware::rejection::handle_rejection;
+use crate::routes::{admin_routes, api_routes, metrics_route, relay_route, ws_route};
+use crate::services::{RelayService, RoomService, SignalingService};
+use crate::slug_generator::SlugGenerator;
+use crate::store::{InMemoryRoomStore, RedisRoomStore, RoomStore};
 use dotenvy::dotenv;
 use std::sync::Arc;
+use std::sync::atomic::Ordering;
 use std::time::Duration;
+use tokio::signal::unix::{SignalKind, signal};
 use warp::Filter;
 
+/// How often a shutdown checks whether connections have drained
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
 #[tokio::main]
 async fn main() {
     dotenv().ok();
 
+    let cli = match CommandLine::parse(std::env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+    if cli.help {
+        println!("{USAGE}");
+        return;
+    }
+    let config = match Config::load(&cli) {
+        Ok(config) => config,
+        Err(e) => {
+            eprint!("{e}");
+            std::process::exit(1);
+        }
+    };
+    if cli.print_config {
+        print!("{}", config.to_toml());
+        return;
+    }
+
     // Initialize tracing (structured logging)
     tracing_subscriber::fmt()
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
         .json()
         .init();
 
-    let config = Arc::new(Config::from_env());
+    // Services and routes read the configuration through the handle, which SIGHUP and
+    // the admin API reload. Settings only used here need a restart to change.
+    let config_handle = ConfigHandle::new(config, cli);
+    let config = config_handle.current();
 
     // Initialize services
-    let store = Arc::new(InMemoryRoomStore::new());
-    let room_service = Arc::new(RoomService::new(store.clone(), config.clone()));
-    let signaling_service = Arc::new(SignalingService::new());
+    let store: Arc<dyn RoomStore> = match config.room_store {
+        Backend::Memory => Arc::new(InMemoryRoomStore::new()),
+        Backend::Redis => Arc::new(
+            RedisRoomStore::connect(&config.redis_url.expose(), config_handle.clone())
+                .await
+                .expect("Failed to connect to Redis room store"),
+        ),
+    };
+    let slug_generator =
+        SlugGenerator::from_config(&config).expect("Invalid room name configuration");
+    let slug_entropy_bits = slug_generator.entropy_bits();
+    tracing::info!(
+        template = %config.slug_template,
+        locale = %config.slug_locale,
+        entropy_bits = format!("{slug_entropy_bits:.1}"),
+        "Room name generator initialized"
+    );
+    if slug_entropy_bits < 32.0 && !config.room_share_tokens {
+        tracing::warn!(
+            entropy_bits = format!("{slug_entropy_bits:.1}"),
+            "Room names are easy to guess; enable ROOM_SHARE_TOKENS or use a longer SLUG_TEMPLATE"
+        );
+    }
 
-    // Spawn room cleanup task
-    let cleanup_room_service = room_service.clone();
-    let cleanup_config = config.clone();
+    let room_service = Arc::new(RoomService::new(
+        store.clone(),
+        config_handle.clone(),
+        slug_generator,
+    ));
+
+    let bus: Arc<dyn SignalingBus> = match config.signaling_bus {
+        Backend::Memory => Arc::new(InProcessBus::new()),
+        Backend::Redis => Arc::new(
+            RedisBus::connect(&config.redis_url.expose())
+                .await
+                .expect("Failed to connect to Redis signaling bus"),
+        ),
+    };
+    let signaling_service = Arc::new(SignalingService::new(bus));
+    let relay_service = Arc::new(RelayService::new(config_handle.clone()));
+    if config.relay_enabled && config.relay_secret.is_none() {
+        tracing::warn!(
+            "RELAY_SECRET is not set; relay tokens only work on the node that issued them"
+        );
+    }
+
+    // Deliver signaling bus events to the peers connected to this node
+    let delivery_signaling_service = signaling_service.clone();
+    let delivery_room_service = room_service.clone();
     tokio::spawn(async move {
+        delivery_signaling_service
+            .run_delivery(delivery_room_service)
+            .await;
+    });
+
+    tracing::info!(
+        room_store = ?config.room_store,
+        signaling_bus = ?config.signaling_bus,
+        "State backends initialized"
+    );
+
+    // HTTP and WebSocket room operations get separate budgets per client IP
+    let api_rate_limiter = Arc::new(RateLimiter::new(config.rate_limit_requests_per_minute));
+    let ws_rate_limiter = Arc::new(RateLimiter::new(config.rate_limit_requests_per_minute));
+
+    // Apply reloaded rate limits
+    let mut config_updates = config_handle.subscribe();
+    let reload_rate_limiters = [api_rate_limiter.clone(), ws_rate_limiter.clone()];
+    tokio::spawn(async move {
+        while config_updates.changed().await.is_ok() {
+            let requests_per_minute = config_updates
+                .borrow_and_update()
+                .rate_limit_requests_per_minute;
+            for limiter in &reload_rate_limiters {
+                limiter.set_requests_per_minute(requests_per_minute).await;
+            }
+        }
+    });
+
+    // SIGHUP reloads the configuration
+    let sighup_config = config_handle.clone();
+    tokio::spawn(async move {
+        let mut sighup = signal(SignalKind::hangup()).expect("Failed to listen for SIGHUP");
+        while sighup.recv().await.is_some() {
+            tracing::info!("Received SIGHUP, reloading configuration");
+            let config = sighup_config.clone();
+            // An invalid configuration is logged by `reload` and leaves the current one in place
+            let _ = tokio::task::spawn_blocking(move || config.reload()).await;
+        }
+    });
+
+    // Spawn room cleanup and secret reload task
+    let cleanup_room_service = room_service.clone();
+    let cleanup_config = config_handle.clone();
+    let cleanup_relay_service = relay_service.clone();
+    let cleanup_rate_limiters = [api_rate_limiter.clone(), ws_rate_limiter.clone()];
+    let cleanup_task = tokio::spawn(async move {
         let mut interval = tokio::time::interval(Duration::from_secs(60));
         loop {
             interval.tick().await;
             cleanup_room_service
-                .cleanup_stale_rooms(cleanup_config.room_ttl())
+                .cleanup_stale_rooms(cleanup_config.current().room_ttl())
                 .await;
+            cleanup_room_service.prune_password_failures().await;
+            cleanup_relay_service.prune(&cleanup_room_service).await;
+            for limiter in &cleanup_rate_limiters {
+                limiter.prune().await;
+            }
+            // Pick up secrets rotated in their files
+            cleanup_config.current().reload_secrets();
         }
     });
 
-    // Setup CORS
-    let cors = warp::cors()
-        .allow_any_origin() // TODO: Restrict in production
-        .allow_methods(vec!["GET", "POST", "OPTIONS"]);
+    // CORS and the WebSocket origin check follow ALLOWED_ORIGINS
+    let origin_policy = origin_policy(&config_handle);
+    if origin_policy.borrow().allows_any() {
+        tracing::warn!(
+            "ALLOWED_ORIGINS allows any origin; set it to the frontend's origin in production"
+        );
+    }
+    let origin_check = allowed_origin(origin_policy.clone());
+
+    // Metrics go on the admin port when one is configured, otherwise on the public port
+    if let Some(metrics_port) = config.metrics_port {
+        let admin_routes = metrics_route(room_service.clone(), true);
+        tokio::spawn(warp::serve(admin_routes).run(([0, 0, 0, 0], metrics_port)));
+        tracing::info!(port = metrics_port, "Metrics server starting");
+    }
 
     // Combine routes from modules
-    let routes = api_routes(room_service.clone())
-        .or(ws_route(room_service.clone(), signaling_service.clone()))
-        .with(cors);
+    let routes = preflight_route(origin_policy.clone())
+        .or(admin_routes(config_handle.clone()))
+        .or(api_routes(
+            config_handle.clone(),
+            room_service.clone(),
+            api_rate_limiter,
+        ))
+        .or(ws_route(
+            config_handle.clone(),
+            room_service.clone(),
+            signaling_service.clone(),
+            relay_service.clone(),
+            ws_rate_limiter,
+            origin_check.clone(),
+        ))
+        .or(relay_route(
+            room_service.clone(),
+            relay_service,
+            origin_check,
+        ))
+        .or(metrics_route(
+            room_service.clone(),
+            config.metrics_port.is_none(),
+        ))
+        .recover(handle_rejection);
+    let routes = with_cors(origin_policy, routes);
+
+    // SIGTERM/SIGINT stop new connections and start draining the open ones
+    let drain_room_service = room_service.clone();
+    let server = warp::serve(routes)
+        .bind(([0, 0, 0, 0], config.port))
+        .await
+        .graceful(async move {
+            shutdown_signal().await;
+            drain_room_service.start_draining();
+        });
 
     tracing::info!(port = config.port, "Server starting");
-    warp::serve(routes).run(([0, 0, 0, 0], config.port)).await;
+    server.run().await;
+
+    wait_for_drain(config_handle.current().shutdown_grace()).await;
+    cleanup_task.abort();
+    tracing::info!("Server stopped");
+}
+
+/// Resolve on SIGTERM (sent by orchestrators on deploys) or SIGINT
+async fn shutdown_signal() {
+    let mut sigterm = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+    tokio::select! {
+        _ = sigterm.recv() => tracing::info!("Received SIGTERM"),
+        _ = tokio::signal::ctrl_c() => tracing::info!("Received SIGINT"),
+    }
+}
+
+/// Wait until the WebSockets and relays on this node have closed, or `grace` has passed.
+async fn wait_for_drain(grace: Duration) {
+    let deadline = tokio::time::Instant::now() + grace;
+    let mut poll = tokio::time::interval(DRAIN_POLL_INTERVAL);
+    loop {
+        let peers = metrics::CONNECTED_PEERS.load(Ordering::Relaxed);
+        let relays = metrics::RELAY_CONNECTIONS.load(Ordering::Relaxed);
+        if peers == 0 && relays == 0 {
+            tracing::info!("All connections drained");
+            return;
+        }
+        if tokio::time::Instant::now() >= deadline {
+            tracing::warn!(
+                peers,
+                relays,
+                "Shutdown grace period over, closing remaining connections"
+            );
+            return;
+        }
+        poll.tick().await;
+    }
 }
@@ -0,0 +1,322 @@
+//! Process-wide counters and gauges, rendered in the Prometheus text format.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Messages currently waiting in peer outbound queues, across all peers
+pub static OUTBOUND_QUEUE_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+/// Lossy messages discarded because a peer's outbound queue was full
+pub static OUTBOUND_MESSAGES_DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Peers disconnected because their outbound queue overflowed
+pub static SLOW_CONSUMER_DISCONNECTS: AtomicU64 = AtomicU64::new(0);
+
+/// WebSocket connections open on this node
+pub static CONNECTED_PEERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Rooms created on this node
+pub static ROOMS_CREATED: AtomicU64 = AtomicU64::new(0);
+
+/// Peers that joined an existing room (the creator's own join is not counted)
+pub static ROOMS_JOINED: AtomicU64 = AtomicU64::new(0);
+
+/// Rooms removed by the stale room cleanup
+pub static ROOMS_EXPIRED: AtomicU64 = AtomicU64::new(0);
+
+/// Join attempts rejected for a wrong password
+pub static PASSWORD_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+/// Join attempts rejected because the room was full
+pub static CAPACITY_REJECTIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Relay sockets open on this node
+pub static RELAY_CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// File data bytes relayed through this node
+pub static RELAY_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Signals relayed, by SDP type
+pub static SIGNALS_RELAYED: SignalCounters = SignalCounters::new();
+
+/// Seconds from room creation until the room is deleted or expires
+pub static ROOM_LIFETIME: Histogram = Histogram::new(&[
+    10.0, 30.0, 60.0, 300.0, 900.0, 1800.0, 3600.0, 7200.0, 21600.0, 86400.0,
+]);
+
+/// Seconds from room creation until a second peer joins
+pub static TIME_TO_SECOND_PEER: Histogram =
+    Histogram::new(&[1.0, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0, 3600.0]);
+
+/// Highest number of buckets a `Histogram` can have
+const MAX_BUCKETS: usize = 12;
+
+/// Signal labels, in the order of `SignalCounters::counts`
+const SIGNAL_TYPES: [&str; 4] = ["offer", "answer", "candidate", "other"];
+
+pub struct SignalCounters {
+    counts: [AtomicU64; SIGNAL_TYPES.len()],
+}
+
+impl SignalCounters {
+    const fn new() -> Self {
+        Self {
+            counts: [const { AtomicU64::new(0) }; SIGNAL_TYPES.len()],
+        }
+    }
+
+    /// Count a relayed signal by the `type` field of its payload
+    pub fn record(&self, data: &serde_json::Value) {
+        let index = match data.get("type").and_then(|t| t.as_str()) {
+            Some("offer") => 0,
+            Some("answer") => 1,
+            Some("candidate") => 2,
+            _ if data.get("candidate").is_some() => 2,
+            _ => 3,
+        };
+        self.counts[index].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Fixed-bucket histogram of durations, in seconds
+pub struct Histogram {
+    bounds: &'static [f64],
+    buckets: [AtomicU64; MAX_BUCKETS],
+    count: AtomicU64,
+    sum_millis: AtomicU64,
+}
+
+impl Histogram {
+    const fn new(bounds: &'static [f64]) -> Self {
+        assert!(bounds.len() <= MAX_BUCKETS);
+        Self {
+            bounds,
+            buckets: [const { AtomicU64::new(0) }; MAX_BUCKETS],
+            count: AtomicU64::new(0),
+            sum_millis: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        if let Some(index) = self.bounds.iter().position(|bound| secs <= *bound) {
+            self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_millis
+            .fetch_add(duration.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+
+        // Buckets are stored individually; Prometheus expects them cumulative
+        let mut cumulative = 0;
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {cumulative}");
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let sum = self.sum_millis.load(Ordering::Relaxed) as f64 / 1000.0;
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}");
+        let _ = writeln!(out, "{name}_sum {sum}");
+        let _ = writeln!(out, "{name}_count {count}");
+    }
+}
+
+fn render_metric(out: &mut String, name: &str, kind: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    let _ = writeln!(out, "{name} {value}");
+}
+
+/// Render all metrics in the Prometheus text exposition format.
+/// `active_rooms` comes from the room store, so it covers every node sharing it.
+pub fn render(active_rooms: usize) -> String {
+    let mut out = String::new();
+
+    render_metric(
+        &mut out,
+        "filecoffee_active_rooms",
+        "gauge",
+        "Rooms currently open",
+        active_rooms as u64,
+    );
+    render_metric(
+        &mut out,
+        "filecoffee_connected_peers",
+        "gauge",
+        "WebSocket connections open on this node",
+        CONNECTED_PEERS.load(Ordering::Relaxed) as u64,
+    );
+    render_metric(
+        &mut out,
+        "filecoffee_rooms_created_total",
+        "counter",
+        "Rooms created",
+        ROOMS_CREATED.load(Ordering::Relaxed),
+    );
+    render_metric(
+        &mut out,
+        "filecoffee_rooms_joined_total",
+        "counter",
+        "Peers that joined an existing room",
+        ROOMS_JOINED.load(Ordering::Relaxed),
+    );
+    render_metric(
+        &mut out,
+        "filecoffee_rooms_expired_total",
+        "counter",
+        "Rooms removed after their TTL",
+        ROOMS_EXPIRED.load(Ordering::Relaxed),
+    );
+    render_metric(
+        &mut out,
+        "filecoffee_password_failures_total",
+        "counter",
+        "Join attempts rejected for a wrong password",
+        PASSWORD_FAILURES.load(Ordering::Relaxed),
+    );
+    render_metric(
+        &mut out,
+        "filecoffee_capacity_rejections_total",
+        "counter",
+        "Join attempts rejected because the room was full",
+        CAPACITY_REJECTIONS.load(Ordering::Relaxed),
+    );
+
+    let _ = writeln!(
+        out,
+        "# HELP filecoffee_signals_relayed_total Signals relayed between peers"
+    );
+    let _ = writeln!(out, "# TYPE filecoffee_signals_relayed_total counter");
+    for (signal_type, count) in SIGNAL_TYPES.iter().zip(&SIGNALS_RELAYED.counts) {
+        let _ = writeln!(
+            out,
+            "filecoffee_signals_relayed_total{{type=\"{signal_type}\"}} {}",
+            count.load(Ordering::Relaxed)
+        );
+    }
+
+    render_metric(
+        &mut out,
+        "filecoffee_outbound_queue_depth",
+        "gauge",
+        "Messages waiting in peer outbound queues",
+        OUTBOUND_QUEUE_DEPTH.load(Ordering::Relaxed) as u64,
+    );
+    render_metric(
+        &mut out,
+        "filecoffee_outbound_messages_dropped_total",
+        "counter",
+        "Lossy messages dropped from full outbound queues",
+        OUTBOUND_MESSAGES_DROPPED.load(Ordering::Relaxed),
+    );
+    render_metric(
+        &mut out,
+        "filecoffee_slow_consumer_disconnects_total",
+        "counter",
+        "Peers disconnected for not keeping up with their outbound queue",
+        SLOW_CONSUMER_DISCONNECTS.load(Ordering::Relaxed),
+    );
+
+    render_metric(
+        &mut out,
+        "filecoffee_relay_connections",
+        "gauge",
+        "Relay sockets open on this node",
+        RELAY_CONNECTIONS.load(Ordering::Relaxed) as u64,
+    );
+    render_metric(
+        &mut out,
+        "filecoffee_relay_bytes_total",
+        "counter",
+        "File data bytes relayed through this node",
+        RELAY_BYTES.load(Ordering::Relaxed),
+    );
+
+    ROOM_LIFETIME.render(
+        &mut out,
+        "filecoffee_room_lifetime_seconds",
+        "Time from room creation until it is deleted or expires",
+    );
+    TIME_TO_SECOND_PEER.render(
+        &mut out,
+        "filecoffee_time_to_second_peer_seconds",
+        "Time from room creation until a second peer joins",
+    );
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let histogram = Histogram::new(&[1.0, 5.0]);
+        histogram.observe(Duration::from_millis(500));
+        histogram.observe(Duration::from_secs(3));
+        histogram.observe(Duration::from_secs(60));
+
+        let mut out = String::new();
+        histogram.render(&mut out, "wait_seconds", "Time waited");
+        assert_eq!(
+            out,
+            "# HELP wait_seconds Time waited\n\
+             # TYPE wait_seconds histogram\n\
+             wait_seconds_bucket{le=\"1\"} 1\n\
+             wait_seconds_bucket{le=\"5\"} 2\n\
+             wait_seconds_bucket{le=\"+Inf\"} 3\n\
+             wait_seconds_sum 63.5\n\
+             wait_seconds_count 3\n"
+        );
+    }
+
+    #[test]
+    fn signals_are_counted_by_type() {
+        let counters = SignalCounters::new();
+        for data in [
+            serde_json::json!({ "type": "offer", "sdp": "" }),
+            serde_json::json!({ "type": "answer", "sdp": "" }),
+            serde_json::json!({ "candidate": "candidate:0 1 UDP" }),
+            serde_json::json!({ "type": "candidate" }),
+            serde_json::json!({ "type": "renegotiate" }),
+        ] {
+            counters.record(&data);
+        }
+        let counts: Vec<u64> = counters
+            .counts
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .collect();
+        assert_eq!(counts, [1, 1, 2, 1]);
+    }
+
+    #[test]
+    fn every_metric_is_described_and_well_formed() {
+        let out = render(7);
+        assert!(out.lines().any(|line| line == "filecoffee_active_rooms 7"));
+
+        let mut described = None;
+        for line in out.lines() {
+            if let Some(help) = line.strip_prefix("# HELP ") {
+                described = help.split(' ').next();
+            } else if let Some(kind) = line.strip_prefix("# TYPE ") {
+                let (name, kind) = kind.split_once(' ').unwrap();
+                assert_eq!(Some(name), described, "TYPE without HELP");
+                assert!(["counter", "gauge", "histogram"].contains(&kind));
+            } else {
+                let (series, value) = line.rsplit_once(' ').unwrap();
+                assert!(
+                    series.starts_with(described.unwrap()),
+                    "{line} is not described"
+                );
+                assert!(value.parse::<f64>().is_ok(), "{line} has no value");
+            }
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use crate::config::ConfigHandle;
+use crate::error::AppError;
+use std::sync::Arc;
+use tokio::sync::watch;
+use warp::Filter;
+use warp::Reply;
+use warp::filters::BoxedFilter;
+use warp::http::header::{
+    ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+    ACCESS_CONTROL_MAX_AGE, CONTENT_SECURITY_POLICY, HeaderMap, HeaderValue, REFERRER_POLICY,
+    STRICT_TRANSPORT_SECURITY, VARY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+};
+use warp::http::{Method, StatusCode};
+
+const ALLOWED_METHODS: &str = "GET, POST, OPTIONS";
+const ALLOWED_HEADERS: &str = "content-type";
+
+/// How long browsers may cache a preflight answer
+const PREFLIGHT_MAX_AGE_SECS: u32 = 600;
+
+/// Origins allowed to call the API and open WebSockets, from `ALLOWED_ORIGINS`.
+/// Entries are exact origins (`https://app.example.com`), wildcard subdomains
+/// (`https://*.example.com`, which does not match `https://example.com` itself)
+/// or `*` for any origin.
+#[derive(Debug, Default)]
+pub struct OriginPolicy {
+    any: bool,
+    exact: Vec<String>,
+    /// Scheme (`https://`) and the suffix after the `*` (`.example.com`)
+    wildcard: Vec<(String, String)>,
+}
+
+impl OriginPolicy {
+    pub fn new(allowed_origins: &[String]) -> Self {
+        let mut policy = Self::default();
+        for entry in allowed_origins {
+            let entry = entry.trim().trim_end_matches('/').to_ascii_lowercase();
+            if entry.is_empty() {
+                continue;
+            }
+            if entry == "*" {
+                policy.any = true;
+            } else if let Some((scheme, suffix)) = entry.split_once("://*") {
+                policy
+                    .wildcard
+                    .push((format!("{scheme}://"), suffix.to_string()));
+            } else {
+                policy.exact.push(entry);
+            }
+        }
+        policy
+    }
+
+    /// Whether every origin is allowed (`ALLOWED_ORIGINS=*`)
+    pub fn allows_any(&self) -> bool {
+        self.any
+    }
+
+    pub fn allows(&self, origin: &str) -> bool {
+        if self.any {
+            return true;
+        }
+        let origin = origin.to_ascii_lowercase();
+        if self.exact.contains(&origin) {
+            return true;
+        }
+        self.wildcard.iter().any(|(scheme, suffix)| {
+            origin
+                .strip_prefix(scheme.as_str())
+                .and_then(|host| host.strip_suffix(suffix.as_str()))
+                .is_some_and(|subdomain| {
+                    !subdomain.is_empty()
+                        && subdomain
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+                })
+        })
+    }
+}
+
+/// The policy of the current configuration, shared by the origin check and the CORS headers
+pub type SharedOriginPolicy = watch::Receiver<Arc<OriginPolicy>>;
+
+/// Build the origin policy, and rebuild it once per reload so requests never parse
+/// `ALLOWED_ORIGINS` themselves.
+pub fn origin_policy(config: &ConfigHandle) -> SharedOriginPolicy {
+    let mut config_updates = config.subscribe();
+    let policy = OriginPolicy::new(&config_updates.borrow_and_update().allowed_origins);
+    let (sender, receiver) = watch::channel(Arc::new(policy));
+
+    tokio::spawn(async move {
+        while config_updates.changed().await.is_ok() {
+            let policy = OriginPolicy::new(&config_updates.borrow_and_update().allowed_origins);
+            if sender.send(Arc::new(policy)).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Filter that rejects with `AppError::OriginNotAllowed` when a browser sends a disallowed
+/// `Origin`. Requests without one (non-browser clients) pass.
+pub fn allowed_origin(policy: SharedOriginPolicy) -> BoxedFilter<()> {
+    warp::header::optional::<String>("origin")
+        .and_then(move |origin: Option<String>| {
+            let policy = policy.borrow().clone();
+            async move {
+                match origin {
+                    Some(origin) if !policy.allows(&origin) => {
+                        tracing::warn!(origin = %origin, "Request from a disallowed origin");
+                        Err(warp::reject::custom(AppError::OriginNotAllowed))
+                    }
+                    _ => Ok(()),
+                }
+            }
+        })
+        .untuple_one()
+        .boxed()
+}
+
+/// OPTIONS on any path: CORS preflight, answered for allowed origins only
+pub fn preflight_route(
+    policy: SharedOriginPolicy,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = warp::Rejection> + Clone {
+    // Other methods get "not found" rather than `warp::options()`'s 405, so that
+    // rejections from the real routes take precedence
+    warp::method()
+        .and_then(|method: Method| async move {
+            if method == Method::OPTIONS {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one()
+        .and(warp::header::<String>("access-control-request-method"))
+        .and(allowed_origin(policy))
+        .map(|_method: String| {
+            let mut response = StatusCode::NO_CONTENT.into_response();
+            let headers = response.headers_mut();
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_METHODS,
+                HeaderValue::from_static(ALLOWED_METHODS),
+            );
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_HEADERS,
+                HeaderValue::from_static(ALLOWED_HEADERS),
+            );
+            headers.insert(
+                ACCESS_CONTROL_MAX_AGE,
+                HeaderValue::from(PREFLIGHT_MAX_AGE_SECS),
+            );
+            response
+        })
+}
+
+/// Add `Access-Control-Allow-Origin` for allowed origins and the security headers
+/// to every response of `routes`.
+pub fn with_cors<F, R>(
+    policy: SharedOriginPolicy,
+    routes: F,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = warp::Rejection> + Clone
+where
+    F: Filter<Extract = (R,), Error = std::convert::Infallible> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    warp::header::optional::<String>("origin").and(routes).map(
+        move |origin: Option<String>, reply: R| {
+            let mut response = reply.into_response();
+            let headers = response.headers_mut();
+            if let Some(origin) = origin.filter(|origin| policy.borrow().allows(origin))
+                && let Ok(value) = HeaderValue::from_str(&origin)
+            {
+                headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, value);
+            }
+            headers.append(VARY, HeaderValue::from_static("origin"));
+            security_headers(headers);
+            response
+        },
+    )
+}
+
+/// Headers hardening every HTTP response. The server only returns JSON and text,
+/// so nothing may be framed, sniffed or load sub-resources.
+fn security_headers(headers: &mut HeaderMap) {
+    headers.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+    headers.insert(X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+    headers.insert(REFERRER_POLICY, HeaderValue::from_static("no-referrer"));
+    headers.insert(
+        CONTENT_SECURITY_POLICY,
+        HeaderValue::from_static("default-src 'none'; frame-ancestors 'none'"),
+    );
+    headers.insert(
+        STRICT_TRANSPORT_SECURITY,
+        HeaderValue::from_static("max-age=63072000; includeSubDomains"),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(origins: &[&str]) -> OriginPolicy {
+        let origins: Vec<String> = origins.iter().map(|o| o.to_string()).collect();
+        OriginPolicy::new(&origins)
+    }
+
+    #[test]
+    fn exact_origins_ignore_case_and_trailing_slash() {
+        let policy = policy(&["https://App.example.com/", " "]);
+        assert!(policy.allows("https://app.example.com"));
+        assert!(policy.allows("HTTPS://APP.EXAMPLE.COM"));
+        assert!(!policy.allows("http://app.example.com"));
+        assert!(!policy.allows("https://app.example.com:8443"));
+        assert!(!policy.allows_any());
+    }
+
+    #[test]
+    fn wildcard_matches_subdomains_only() {
+        let policy = policy(&["https://*.example.com"]);
+        assert!(policy.allows("https://app.example.com"));
+        assert!(policy.allows("https://a.b-c.example.com"));
+        assert!(!policy.allows("https://example.com"));
+        assert!(!policy.allows("https://.example.com"));
+        assert!(!policy.allows("http://app.example.com"));
+        assert!(!policy.allows("https://evil.com/.example.com"));
+        assert!(!policy.allows("https://app.example.com.evil.com"));
+    }
+
+    #[test]
+    fn star_allows_any_origin() {
+        let policy = policy(&["https://app.example.com", "*"]);
+        assert!(policy.allows_any());
+        assert!(policy.allows("https://anything.test"));
+    }
+
+    #[test]
+    fn empty_list_allows_nothing() {
+        let policy = policy(&[]);
+        assert!(!policy.allows_any());
+        assert!(!policy.allows("https://app.example.com"));
+    }
+}
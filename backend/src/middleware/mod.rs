@@ -1 +1,3 @@
+pub mod cors;
 pub mod rate_limit;
+pub mod rejection;
@@ -0,0 +1,269 @@
+use crate::error::AppError;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Instant;
+use tokio::sync::Mutex;
+use warp::Filter;
+use warp::filters::BoxedFilter;
+
+/// Token bucket refilled continuously, allowing bursts of up to `capacity`.
+pub struct TokenBucket {
+    capacity: f64,
+    refill_per_sec: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: f64, refill_per_sec: f64) -> Self {
+        Self {
+            capacity,
+            refill_per_sec,
+            tokens: capacity,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Take one token, or return how many seconds until one is available.
+    pub fn try_take(&mut self) -> Result<(), f64> {
+        self.refill();
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err((1.0 - self.tokens) / self.refill_per_sec)
+        }
+    }
+
+    /// Take `amount` tokens even if that overdraws the bucket, and return how many
+    /// seconds the caller should wait for the balance to be paid back.
+    pub fn reserve(&mut self, amount: f64) -> f64 {
+        self.refill();
+        self.tokens -= amount;
+        if self.tokens >= 0.0 {
+            0.0
+        } else {
+            -self.tokens / self.refill_per_sec
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Whether the bucket would be full again by now
+    fn is_refilled(&self) -> bool {
+        let elapsed = self.last_refill.elapsed().as_secs_f64();
+        self.tokens + elapsed * self.refill_per_sec >= self.capacity
+    }
+}
+
+/// Per-IP token bucket rate limiter.
+/// Each client may burst up to `requests_per_minute` requests, refilled continuously.
+/// A limit of 0 disables rate limiting entirely.
+pub struct RateLimiter {
+    requests_per_minute: AtomicU32,
+    buckets: Mutex<HashMap<IpAddr, TokenBucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_minute: u32) -> Self {
+        Self {
+            requests_per_minute: AtomicU32::new(requests_per_minute),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Change the limit on a configuration reload. Clients start over with a full bucket.
+    pub async fn set_requests_per_minute(&self, requests_per_minute: u32) {
+        let mut buckets = self.buckets.lock().await;
+        if self
+            .requests_per_minute
+            .swap(requests_per_minute, Ordering::Relaxed)
+            != requests_per_minute
+        {
+            buckets.clear();
+        }
+    }
+
+    /// Consume one token for `ip`, or return how long the client has to wait.
+    pub async fn check(&self, ip: IpAddr) -> Result<(), AppError> {
+        let mut buckets = self.buckets.lock().await;
+        let requests_per_minute = self.requests_per_minute.load(Ordering::Relaxed);
+        if requests_per_minute == 0 {
+            return Ok(());
+        }
+
+        let bucket = buckets.entry(ip).or_insert_with(|| {
+            let capacity = requests_per_minute as f64;
+            TokenBucket::new(capacity, capacity / 60.0)
+        });
+
+        bucket.try_take().map_err(|wait_secs| {
+            let retry_after_secs = wait_secs.ceil() as u64;
+            tracing::warn!(client_ip = %ip, retry_after_secs, "Rate limit exceeded");
+            AppError::RateLimitExceeded { retry_after_secs }
+        })
+    }
+
+    /// Drop buckets that have fully refilled, so idle clients don't accumulate (called periodically).
+    pub async fn prune(&self) {
+        let mut buckets = self.buckets.lock().await;
+        buckets.retain(|_, bucket| !bucket.is_refilled());
+    }
+}
+
+/// Resolve the real client IP.
+/// `X-Forwarded-For` is only honoured when the direct peer is a trusted proxy; in that case
+/// the right-most address that isn't itself a trusted proxy is used.
+pub fn resolve_client_ip(
+    remote: Option<IpAddr>,
+    forwarded_for: Option<&str>,
+    trusted_proxies: &[IpAddr],
+) -> IpAddr {
+    let remote = remote.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+
+    if !trusted_proxies.contains(&remote) {
+        return remote;
+    }
+
+    forwarded_for
+        .into_iter()
+        .flat_map(|header| header.rsplit(','))
+        .filter_map(|entry| entry.trim().parse::<IpAddr>().ok())
+        .find(|ip| !trusted_proxies.contains(ip))
+        .unwrap_or(remote)
+}
+
+/// Filter extracting the client IP, honouring `X-Forwarded-For` from trusted proxies.
+pub fn client_ip(
+    trusted_proxies: Vec<IpAddr>,
+) -> impl Filter<Extract = (IpAddr,), Error = warp::Rejection> + Clone {
+    warp::addr::remote()
+        .and(warp::header::optional::<String>("x-forwarded-for"))
+        .map(
+            move |remote: Option<SocketAddr>, forwarded_for: Option<String>| {
+                resolve_client_ip(
+                    remote.map(|addr| addr.ip()),
+                    forwarded_for.as_deref(),
+                    &trusted_proxies,
+                )
+            },
+        )
+}
+
+/// Filter that rejects with `AppError::RateLimitExceeded` once the client runs out of tokens.
+pub fn rate_limit(limiter: Arc<RateLimiter>, trusted_proxies: Vec<IpAddr>) -> BoxedFilter<()> {
+    client_ip(trusted_proxies)
+        .and(warp::any().map(move || limiter.clone()))
+        .and_then(|ip: IpAddr, limiter: Arc<RateLimiter>| async move {
+            limiter.check(ip).await.map_err(warp::reject::custom)
+        })
+        .untuple_one()
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn bucket_allows_burst_then_reports_wait() {
+        let mut bucket = TokenBucket::new(3.0, 1.0);
+        for _ in 0..3 {
+            assert!(bucket.try_take().is_ok());
+        }
+
+        let wait = bucket.try_take().unwrap_err();
+        assert!(wait > 0.9 && wait <= 1.0, "wait was {wait}");
+    }
+
+    #[test]
+    fn bucket_reserve_overdraws_and_asks_to_wait() {
+        let mut bucket = TokenBucket::new(10.0, 5.0);
+        assert_eq!(bucket.reserve(10.0), 0.0);
+
+        let wait = bucket.reserve(10.0);
+        assert!(wait > 1.9 && wait <= 2.0, "wait was {wait}");
+        assert!(bucket.try_take().is_err());
+    }
+
+    #[tokio::test]
+    async fn limiter_tracks_clients_separately() {
+        let limiter = RateLimiter::new(2);
+        let (a, b) = (ip("10.0.0.1"), ip("10.0.0.2"));
+
+        assert!(limiter.check(a).await.is_ok());
+        assert!(limiter.check(a).await.is_ok());
+        assert!(matches!(
+            limiter.check(a).await,
+            Err(AppError::RateLimitExceeded {
+                retry_after_secs: 30
+            })
+        ));
+        assert!(limiter.check(b).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn limiter_zero_disables_limit() {
+        let limiter = RateLimiter::new(0);
+        for _ in 0..100 {
+            assert!(limiter.check(ip("10.0.0.1")).await.is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn limiter_new_limit_starts_over() {
+        let limiter = RateLimiter::new(1);
+        let client = ip("10.0.0.1");
+        assert!(limiter.check(client).await.is_ok());
+        assert!(limiter.check(client).await.is_err());
+
+        limiter.set_requests_per_minute(2).await;
+        assert!(limiter.check(client).await.is_ok());
+        assert!(limiter.check(client).await.is_ok());
+        assert!(limiter.check(client).await.is_err());
+    }
+
+    #[test]
+    fn client_ip_ignores_forwarded_for_from_untrusted_peer() {
+        let remote = ip("203.0.113.7");
+        let resolved = resolve_client_ip(Some(remote), Some("198.51.100.1"), &[ip("10.0.0.1")]);
+        assert_eq!(resolved, remote);
+    }
+
+    #[test]
+    fn client_ip_takes_rightmost_untrusted_forwarded_address() {
+        let proxies = [ip("10.0.0.1"), ip("10.0.0.2")];
+        let resolved = resolve_client_ip(
+            Some(ip("10.0.0.1")),
+            Some("1.1.1.1, 198.51.100.1, 10.0.0.2"),
+            &proxies,
+        );
+        assert_eq!(resolved, ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn client_ip_falls_back_to_proxy_without_usable_header() {
+        let proxy = ip("10.0.0.1");
+        assert_eq!(resolve_client_ip(Some(proxy), None, &[proxy]), proxy);
+        assert_eq!(
+            resolve_client_ip(Some(proxy), Some("garbage, 10.0.0.1"), &[proxy]),
+            proxy
+        );
+        assert_eq!(
+            resolve_client_ip(None, None, &[]),
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+        );
+    }
+}
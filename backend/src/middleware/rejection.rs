@@ -0,0 +1,152 @@
+use crate::error::{AppError, new_request_id};
+use std::convert::Infallible;
+use warp::Reply;
+use warp::http::header::{HeaderValue, RETRY_AFTER};
+use warp::reject::{
+    InvalidHeader, InvalidQuery, LengthRequired, MethodNotAllowed, MissingHeader, PayloadTooLarge,
+    UnsupportedMediaType,
+};
+
+/// Response header carrying the request id of an error
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Answer an HTTP request with the error as JSON, in the same shape as `ServerMessage::Error`.
+/// Sets `Retry-After` when the client should wait, and `X-Request-Id`.
+pub fn error_reply(e: &AppError) -> warp::reply::Response {
+    let request_id = new_request_id();
+    let message = e.to_message(request_id.clone());
+
+    let mut response =
+        warp::reply::with_status(warp::reply::json(&message), e.status_code()).into_response();
+    let headers = response.headers_mut();
+    if let Some(retry_after_secs) = e.retry_after_secs() {
+        headers.insert(RETRY_AFTER, HeaderValue::from(retry_after_secs));
+    }
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        headers.insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// Turn every rejection into a JSON error reply, so clients never see warp's plain text.
+pub async fn handle_rejection(err: warp::Rejection) -> Result<warp::reply::Response, Infallible> {
+    let e = if let Some(e) = err.find::<AppError>() {
+        return Ok(error_reply(e));
+    } else if err.is_not_found() {
+        AppError::NotFound
+    } else if err.find::<MethodNotAllowed>().is_some() {
+        AppError::MethodNotAllowed
+    } else if err.find::<PayloadTooLarge>().is_some() {
+        AppError::PayloadTooLarge
+    } else if let Some(cause) = err.find::<InvalidQuery>() {
+        AppError::BadRequest(cause.to_string())
+    } else if let Some(cause) = err.find::<MissingHeader>() {
+        AppError::BadRequest(cause.to_string())
+    } else if let Some(cause) = err.find::<InvalidHeader>() {
+        AppError::BadRequest(cause.to_string())
+    } else if let Some(cause) = err.find::<LengthRequired>() {
+        AppError::BadRequest(cause.to_string())
+    } else if let Some(cause) = err.find::<UnsupportedMediaType>() {
+        AppError::BadRequest(cause.to_string())
+    } else if let Some(cause) = err.find::<warp::body::BodyDeserializeError>() {
+        AppError::BadRequest(cause.to_string())
+    } else if let Some(cause) = err.find::<warp::ws::MissingConnectionUpgrade>() {
+        AppError::BadRequest(cause.to_string())
+    } else {
+        tracing::error!(rejection = ?err, "Unhandled rejection");
+        AppError::InternalError("Unexpected error".to_string())
+    };
+    Ok(error_reply(&e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ServerMessage, error_codes};
+    use warp::Filter;
+    use warp::http::StatusCode;
+
+    /// `GET /rooms` taking a body of at most 8 bytes, and `/limited` which is always rate limited
+    fn routes() -> impl Filter<Extract = (warp::reply::Response,), Error = Infallible> + Clone {
+        warp::path("rooms")
+            .and(warp::path::end())
+            .and(warp::get())
+            .and(warp::body::content_length_limit(8))
+            .map(|| warp::reply().into_response())
+            .or(warp::path("limited").and_then(|| async {
+                Err::<warp::reply::Response, _>(warp::reject::custom(AppError::RateLimitExceeded {
+                    retry_after_secs: 7,
+                }))
+            }))
+            .unify()
+            .recover(handle_rejection)
+            .unify()
+    }
+
+    /// Check the reply is an error with `code`, and return it
+    fn error(response: &warp::http::Response<impl AsRef<[u8]>>, code: &str) -> ServerMessage {
+        let message: ServerMessage = serde_json::from_slice(response.body().as_ref()).unwrap();
+        let ServerMessage::Error {
+            code: ref actual,
+            ref request_id,
+            ..
+        } = message
+        else {
+            panic!("not an error: {message:?}");
+        };
+        assert_eq!(actual, code);
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], request_id.as_str());
+        message
+    }
+
+    #[tokio::test]
+    async fn unknown_path_is_404() {
+        let response = warp::test::request()
+            .path("/nowhere")
+            .reply(&routes())
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        error(&response, error_codes::NOT_FOUND);
+        assert!(response.headers().get(RETRY_AFTER).is_none());
+    }
+
+    #[tokio::test]
+    async fn wrong_method_is_405() {
+        let response = warp::test::request()
+            .method("DELETE")
+            .path("/rooms")
+            .reply(&routes())
+            .await;
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        error(&response, error_codes::METHOD_NOT_ALLOWED);
+    }
+
+    #[tokio::test]
+    async fn oversized_body_is_413() {
+        let response = warp::test::request()
+            .path("/rooms")
+            .body("more than eight bytes")
+            .reply(&routes())
+            .await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        error(&response, error_codes::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn rate_limit_is_429_with_retry_after() {
+        let response = warp::test::request()
+            .path("/limited")
+            .reply(&routes())
+            .await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[RETRY_AFTER], "7");
+        let message = error(&response, error_codes::RATE_LIMITED);
+        assert!(matches!(
+            message,
+            ServerMessage::Error {
+                retry_after_secs: Some(7),
+                ..
+            }
+        ));
+    }
+}
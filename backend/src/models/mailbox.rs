@@ -0,0 +1,203 @@
+use crate::models::{PeerSender, ServerMessage};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use warp::ws::Message;
+
+struct Entry {
+    seq: u64,
+    received_at: Instant,
+    text: String,
+    lossy: bool,
+}
+
+struct MailboxState {
+    /// Connection signals are currently sent on
+    sender: PeerSender,
+    next_seq: u64,
+    entries: VecDeque<Entry>,
+}
+
+/// Numbers the signals sent to one peer and keeps the recent ones, so they can be
+/// replayed in order when the peer resumes its session on a new connection.
+pub struct Mailbox {
+    state: Mutex<MailboxState>,
+    capacity: usize,
+    retention: Duration,
+}
+
+impl Mailbox {
+    /// Create a mailbox sending on `sender`. Keeps up to `capacity` signals for `retention`.
+    pub fn new(sender: PeerSender, capacity: usize, retention: Duration, first_seq: u64) -> Self {
+        Self {
+            state: Mutex::new(MailboxState {
+                sender,
+                next_seq: first_seq,
+                entries: VecDeque::with_capacity(capacity),
+            }),
+            capacity,
+            retention,
+        }
+    }
+
+    /// Number a signal, keep it for replay and send it on the current connection.
+    /// Lossy signals may be dropped by a full outbound queue but are still kept here.
+    pub fn deliver(&self, from: Uuid, data: serde_json::Value, lossy: bool) {
+        let mut state = self.state.lock().unwrap();
+        let seq = state.next_seq;
+        state.next_seq += 1;
+
+        let msg = ServerMessage::Signal { from, data, seq };
+        let Ok(text) = serde_json::to_string(&msg) else {
+            return;
+        };
+
+        let message = Message::text(text.clone());
+        let _ = if lossy {
+            state.sender.send_lossy(message)
+        } else {
+            state.sender.send(message)
+        };
+
+        if self.capacity == 0 {
+            return;
+        }
+        self.prune(&mut state);
+        if state.entries.len() >= self.capacity {
+            state.entries.pop_front();
+        }
+        state.entries.push_back(Entry {
+            seq,
+            received_at: Instant::now(),
+            text,
+            lossy,
+        });
+    }
+
+    /// Switch to a new connection, first resending the kept signals after `last_seq`
+    /// (all of them when `None`), and number new signals after `last_seq`.
+    /// Returns the number of signals resent.
+    pub fn reattach(&self, sender: PeerSender, last_seq: Option<u64>) -> usize {
+        let mut state = self.state.lock().unwrap();
+        self.prune(&mut state);
+
+        let mut replayed = 0;
+        for entry in state
+            .entries
+            .iter()
+            .filter(|entry| last_seq.is_none_or(|last| entry.seq > last))
+        {
+            let message = Message::text(entry.text.clone());
+            let _ = if entry.lossy {
+                sender.send_lossy(message)
+            } else {
+                sender.send(message)
+            };
+            replayed += 1;
+        }
+
+        // The client may have received later signals from a mailbox on another node
+        if let Some(last) = last_seq {
+            state.next_seq = state.next_seq.max(last + 1);
+        }
+        state.sender = sender;
+        replayed
+    }
+
+    /// Forget signals kept for longer than the retention window
+    fn prune(&self, state: &mut MailboxState) {
+        while state
+            .entries
+            .front()
+            .is_some_and(|entry| entry.received_at.elapsed() > self.retention)
+        {
+            state.entries.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::OverflowPolicy;
+    use crate::models::peer_channel;
+    use crate::models::peer_sender::PeerReceiver;
+    use serde_json::json;
+
+    fn mailbox(capacity: usize, retention: Duration) -> (Mailbox, PeerReceiver) {
+        let (sender, receiver) = peer_channel(64, OverflowPolicy::Disconnect);
+        (Mailbox::new(sender, capacity, retention, 1), receiver)
+    }
+
+    /// Sequence numbers of the signals queued on `receiver`, closing it first
+    async fn received_seqs(sender: PeerSender, mut receiver: PeerReceiver) -> Vec<u64> {
+        drop(sender);
+        let mut seqs = Vec::new();
+        while let Some(message) = receiver.recv().await {
+            let msg: ServerMessage = serde_json::from_str(message.to_str().unwrap()).unwrap();
+            if let ServerMessage::Signal { seq, .. } = msg {
+                seqs.push(seq);
+            }
+        }
+        seqs
+    }
+
+    fn deliver(mailbox: &Mailbox, count: usize) {
+        for n in 0..count {
+            mailbox.deliver(Uuid::nil(), json!({ "n": n }), false);
+        }
+    }
+
+    #[tokio::test]
+    async fn reattach_replays_missed_signals_then_follows_new_connection() {
+        let (mailbox, _old) = mailbox(8, Duration::from_secs(60));
+        deliver(&mailbox, 3);
+
+        let (sender, receiver) = peer_channel(64, OverflowPolicy::Disconnect);
+        assert_eq!(mailbox.reattach(sender.clone(), Some(1)), 2);
+        deliver(&mailbox, 1);
+
+        drop(mailbox);
+        assert_eq!(received_seqs(sender, receiver).await, [2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn reattach_without_last_seq_replays_everything_kept() {
+        let (mailbox, _old) = mailbox(2, Duration::from_secs(60));
+        deliver(&mailbox, 3);
+
+        let (sender, receiver) = peer_channel(64, OverflowPolicy::Disconnect);
+        assert_eq!(mailbox.reattach(sender.clone(), None), 2);
+
+        drop(mailbox);
+        assert_eq!(received_seqs(sender, receiver).await, [2, 3]);
+    }
+
+    #[test]
+    fn expired_or_unkept_signals_are_not_replayed() {
+        let (expiring, _receiver) = mailbox(8, Duration::ZERO);
+        deliver(&expiring, 2);
+        std::thread::sleep(Duration::from_millis(1));
+        let (sender, _new) = peer_channel(64, OverflowPolicy::Disconnect);
+        assert_eq!(expiring.reattach(sender, None), 0);
+
+        let (unkept, _receiver) = mailbox(0, Duration::from_secs(60));
+        deliver(&unkept, 2);
+        let (sender, _new) = peer_channel(64, OverflowPolicy::Disconnect);
+        assert_eq!(unkept.reattach(sender, None), 0);
+    }
+
+    #[tokio::test]
+    async fn numbering_continues_after_last_seq_seen_elsewhere() {
+        let (mailbox, _old) = mailbox(8, Duration::from_secs(60));
+        deliver(&mailbox, 1);
+
+        let (sender, receiver) = peer_channel(64, OverflowPolicy::Disconnect);
+        assert_eq!(mailbox.reattach(sender.clone(), Some(10)), 0);
+        deliver(&mailbox, 1);
+
+        drop(mailbox);
+        assert_eq!(received_seqs(sender, receiver).await, [11]);
+    }
+}
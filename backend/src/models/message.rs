@@ -1,4 +1,6 @@
+use crate::ice::IceServer;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 /// Messages sent FROM client TO server
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -6,37 +8,226 @@ use serde::{Deserialize, Serialize};
 pub enum ClientMessage {
     CreateRoom {
         password: Option<String>,
+        /// Custom room name to use instead of a generated slug
+        #[serde(default)]
+        room_name: Option<String>,
+        /// Hold joins until the owner approves them; the server default applies when omitted
+        #[serde(default)]
+        require_approval: Option<bool>,
     },
     JoinRoom {
         room_id: String,
         password: Option<String>,
+        /// Share token from the room link, required when the room has one
+        #[serde(default)]
+        token: Option<String>,
+        /// Shown to the room owner when the room requires approval
+        #[serde(default)]
+        display_name: Option<String>,
     },
     Signal {
         data: serde_json::Value,
+        /// Deliver only to this peer; broadcast to the whole room when omitted
+        #[serde(default)]
+        to: Option<Uuid>,
+    },
+    /// Re-bind a new connection to a peer whose connection dropped, within the grace period
+    ResumeSession {
+        room_id: String,
+        peer_id: Uuid,
+        resume_token: String,
+        /// Highest signal `seq` received; later signals still buffered are resent
+        #[serde(default)]
+        last_seq: Option<u64>,
+    },
+    /// Fall back to relaying file data through the server when WebRTC cannot connect.
+    /// Both peers receive `RelayReady`.
+    RequestRelay {
+        to: Uuid,
+    },
+    /// Application-level keepalive, for clients that cannot see WebSocket ping frames
+    Ping,
+    // Host controls, authorized by the owner token from `RoomCreated`
+    KickPeer {
+        owner_token: String,
+        peer_id: Uuid,
+    },
+    LockRoom {
+        owner_token: String,
+        locked: bool,
+    },
+    /// Change the room password; `None` or empty removes it
+    SetPassword {
+        owner_token: String,
+        password: Option<String>,
+    },
+    CloseRoom {
+        owner_token: String,
+    },
+    /// Approve or reject a join request received as `JoinRequest`
+    AnswerJoinRequest {
+        owner_token: String,
+        request_id: Uuid,
+        approve: bool,
     },
-    Ping, // Add Ping/Pong for heartbeat in the future
 }
 
 /// Messages sent FROM server TO client
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type")]
 pub enum ServerMessage {
-    RoomCreated { room_id: String },
-    RoomJoined,
-    PeerJoined { peer_count: usize }, // we are letting the client know how many peers are connected
-    PeerLeft { peer_count: usize },
-    Signal { data: serde_json::Value },
-    Error { code: String, message: String },
-    RoomExists { exists: bool, has_password: bool },
-    Pong, // Add Ping/Pong for heartbeat in the future
+    RoomCreated {
+        room_id: String,
+        /// Unguessable token to put in the share link next to the room id
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        share_token: Option<String>,
+        /// Secret authorizing host controls (kick, lock, password, close)
+        owner_token: String,
+        /// The creator's own peer id
+        peer_id: Uuid,
+        /// Secret for `ResumeSession` after the connection drops
+        resume_token: String,
+        /// STUN/TURN servers, with TURN credentials scoped to this room
+        ice_servers: Vec<IceServer>,
+    },
+    RoomJoined {
+        /// The joiner's own peer id
+        peer_id: Uuid,
+        /// Peers already in the room
+        peers: Vec<Uuid>,
+        /// Secret for `ResumeSession` after the connection drops
+        resume_token: String,
+        /// STUN/TURN servers, with TURN credentials scoped to this room
+        ice_servers: Vec<IceServer>,
+    },
+    /// The new connection took over the peer's place in the room.
+    /// Other peers see no leave or join.
+    SessionResumed {
+        peer_id: Uuid,
+        /// Other peers in the room
+        peers: Vec<Uuid>,
+        /// Replaces the token used to resume
+        resume_token: String,
+        ice_servers: Vec<IceServer>,
+    },
+    // we are letting the client know how many peers are connected
+    PeerJoined {
+        peer_id: Uuid,
+        peer_count: usize,
+    },
+    PeerLeft {
+        peer_id: Uuid,
+        peer_count: usize,
+    },
+    Signal {
+        /// Peer that sent the signal
+        from: Uuid,
+        data: serde_json::Value,
+        /// Per-receiver sequence number, assigned on delivery. Signals resent after
+        /// `ResumeSession` keep theirs, so clients can drop duplicates.
+        #[serde(default)]
+        seq: u64,
+    },
+    Error {
+        code: String,
+        message: String,
+        /// Seconds the client should wait before retrying, when applicable
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        retry_after_secs: Option<u64>,
+        /// Identifies the error in the server logs; quote it when reporting a problem
+        #[serde(default)]
+        request_id: String,
+    },
+    /// The room requires approval; the owner has been asked to let the receiver in
+    JoinPending {
+        request_id: Uuid,
+    },
+    /// Someone asks to join the receiver's room. Sent to the owner only.
+    JoinRequest {
+        request_id: Uuid,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        display_name: Option<String>,
+        /// Short code derived from the joiner's address, to recognise repeated requests
+        fingerprint: String,
+    },
+    /// A join request went away unanswered (the joiner left or it timed out)
+    JoinRequestCancelled {
+        request_id: Uuid,
+    },
+    JoinRejected {
+        reason: String,
+    },
+    /// The receiver may connect to `/ws/relay/:room?token=...` to exchange data with `peer_id`
+    RelayReady {
+        /// The other end of the relay
+        peer_id: Uuid,
+        /// Authorizes one relay connection, for a limited time
+        token: String,
+        /// Bytes the room may relay in total (0 = unlimited)
+        max_bytes: u64,
+        /// Relay throughput per room (0 = unlimited)
+        max_bytes_per_sec: u64,
+    },
+    /// Sent on the relay socket once both ends are connected; data can flow from here on
+    RelayConnected {
+        peer_id: Uuid,
+    },
+    RoomExists {
+        exists: bool,
+        has_password: bool,
+    },
+    /// The receiver was removed from the room by its owner; the socket closes next
+    Kicked,
+    /// The owner locked or unlocked the room against new joins
+    RoomLocked {
+        locked: bool,
+    },
+    /// The owner changed or removed the room password
+    PasswordChanged {
+        has_password: bool,
+    },
+    /// The owner closed the room; the socket closes next
+    RoomClosed,
+    /// Someone entered a wrong password for the room the receiver is in
+    PasswordAttemptFailed {
+        failed_attempts: u32,
+    },
+    /// This node is shutting down. Rooms keep working until the receiver leaves, but
+    /// new sessions should reconnect after `reconnect_after` seconds (to another node).
+    ServerShutdown {
+        reconnect_after: u64,
+    },
+    Pong,
 }
 
 /// Error codes for structured error handling
 pub mod error_codes {
     pub const ROOM_NOT_FOUND: &str = "ROOM_NOT_FOUND";
     pub const INVALID_PASSWORD: &str = "INVALID_PASSWORD";
+    pub const PASSWORD_ATTEMPTS_EXCEEDED: &str = "PASSWORD_ATTEMPTS_EXCEEDED";
     pub const ROOM_FULL: &str = "ROOM_FULL";
+    pub const TOO_MANY_JOIN_REQUESTS: &str = "TOO_MANY_JOIN_REQUESTS";
+    pub const ROOM_LOCKED: &str = "ROOM_LOCKED";
+    pub const NOT_ROOM_OWNER: &str = "NOT_ROOM_OWNER";
+    pub const INVALID_ROOM_NAME: &str = "INVALID_ROOM_NAME";
+    pub const ROOM_NAME_TAKEN: &str = "ROOM_NAME_TAKEN";
     pub const NOT_IN_ROOM: &str = "NOT_IN_ROOM";
+    pub const SESSION_EXPIRED: &str = "SESSION_EXPIRED";
+    pub const PEER_NOT_FOUND: &str = "PEER_NOT_FOUND";
+    pub const RELAY_DISABLED: &str = "RELAY_DISABLED";
+    pub const INVALID_RELAY_TOKEN: &str = "INVALID_RELAY_TOKEN";
+    pub const ORIGIN_NOT_ALLOWED: &str = "ORIGIN_NOT_ALLOWED";
     pub const INVALID_MESSAGE: &str = "INVALID_MESSAGE";
+    pub const MESSAGE_TOO_LARGE: &str = "MESSAGE_TOO_LARGE";
+    pub const MESSAGE_RATE_EXCEEDED: &str = "MESSAGE_RATE_EXCEEDED";
     pub const RATE_LIMITED: &str = "RATE_LIMITED";
+    pub const SLOW_CONSUMER: &str = "SLOW_CONSUMER";
+    pub const SERVER_SHUTTING_DOWN: &str = "SERVER_SHUTTING_DOWN";
+    pub const UNAUTHORIZED: &str = "UNAUTHORIZED";
+    pub const INVALID_CONFIG: &str = "INVALID_CONFIG";
+    pub const NOT_FOUND: &str = "NOT_FOUND";
+    pub const METHOD_NOT_ALLOWED: &str = "METHOD_NOT_ALLOWED";
+    pub const PAYLOAD_TOO_LARGE: &str = "PAYLOAD_TOO_LARGE";
+    pub const BAD_REQUEST: &str = "BAD_REQUEST";
+    pub const INTERNAL_ERROR: &str = "INTERNAL_ERROR";
 }
@@ -1,5 +1,9 @@
+pub mod mailbox;
 pub mod message;
+pub mod peer_sender;
 pub mod room;
 
+pub use mailbox::Mailbox;
 pub use message::{ClientMessage, ServerMessage, error_codes};
-pub use room::{Peer, PeerSender, Room};
+pub use peer_sender::{PeerSender, peer_channel};
+pub use room::{Peer, Room};
@@ -0,0 +1,309 @@
+use crate::config::OverflowPolicy;
+use crate::error::{AppError, new_request_id};
+use crate::metrics;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use warp::ws::Message;
+
+/// Close code for a peer that cannot keep up (RFC 6455 "try again later")
+const CLOSE_SLOW_CONSUMER: u16 = 1013;
+
+/// Returned when sending to a peer whose queue has been closed
+#[derive(Debug)]
+pub struct PeerClosed;
+
+struct Queued {
+    message: Message,
+    /// Can be discarded to make room when the queue overflows
+    lossy: bool,
+}
+
+struct QueueState {
+    messages: VecDeque<Queued>,
+    closed: bool,
+}
+
+struct Shared {
+    state: Mutex<QueueState>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    senders: AtomicUsize,
+    /// Wakes the receiver when a message is queued or the queue closes
+    readable: Notify,
+    /// Wakes `PeerSender::closed` waiters
+    closed: Notify,
+}
+
+impl Shared {
+    fn close(&self, state: &mut QueueState) {
+        state.closed = true;
+        self.readable.notify_one();
+        self.closed.notify_waiters();
+    }
+}
+
+/// Bounded outbound queue feeding a peer's WebSocket.
+/// When the queue is full, the configured `OverflowPolicy` decides whether the
+/// oldest lossy message is dropped or the peer is disconnected as a slow consumer.
+pub struct PeerSender {
+    shared: Arc<Shared>,
+}
+
+/// Receiving half, drained by the task writing to the WebSocket
+pub struct PeerReceiver {
+    shared: Arc<Shared>,
+}
+
+/// Create a bounded outbound queue for one peer
+pub fn peer_channel(capacity: usize, policy: OverflowPolicy) -> (PeerSender, PeerReceiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(QueueState {
+            messages: VecDeque::with_capacity(capacity),
+            closed: false,
+        }),
+        capacity: capacity.max(1),
+        policy,
+        senders: AtomicUsize::new(1),
+        readable: Notify::new(),
+        closed: Notify::new(),
+    });
+
+    (
+        PeerSender {
+            shared: shared.clone(),
+        },
+        PeerReceiver { shared },
+    )
+}
+
+impl PeerSender {
+    /// Queue a message that must be delivered
+    pub fn send(&self, message: Message) -> Result<(), PeerClosed> {
+        self.push(message, false)
+    }
+
+    /// Queue a message that may be discarded first if the peer falls behind (e.g. ICE candidates)
+    pub fn send_lossy(&self, message: Message) -> Result<(), PeerClosed> {
+        self.push(message, true)
+    }
+
+    /// Queue a close frame after the pending messages and refuse anything sent afterwards
+    pub fn close(&self, code: u16, reason: &str) {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.closed {
+            return;
+        }
+
+        state.messages.push_back(Queued {
+            message: Message::close_with(code, reason.to_string()),
+            lossy: false,
+        });
+        metrics::OUTBOUND_QUEUE_DEPTH.fetch_add(1, Ordering::Relaxed);
+        self.shared.close(&mut state);
+    }
+
+    /// Whether the queue is closed: the peer is disconnected or being disconnected
+    pub fn is_closed(&self) -> bool {
+        self.shared.state.lock().unwrap().closed
+    }
+
+    /// Resolves once the queue is closed, e.g. after a slow consumer disconnect
+    pub async fn closed(&self) {
+        loop {
+            let notified = self.shared.closed.notified();
+            if self.shared.state.lock().unwrap().closed {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    fn push(&self, message: Message, lossy: bool) -> Result<(), PeerClosed> {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.closed {
+            return Err(PeerClosed);
+        }
+
+        if state.messages.len() >= self.shared.capacity {
+            let dropped = match self.shared.policy {
+                OverflowPolicy::DropOldestCandidate => state
+                    .messages
+                    .iter()
+                    .position(|queued| queued.lossy)
+                    .and_then(|index| state.messages.remove(index)),
+                OverflowPolicy::Disconnect => None,
+            };
+
+            match dropped {
+                Some(_) => {
+                    metrics::OUTBOUND_QUEUE_DEPTH.fetch_sub(1, Ordering::Relaxed);
+                    metrics::OUTBOUND_MESSAGES_DROPPED.fetch_add(1, Ordering::Relaxed);
+                }
+                None => {
+                    self.disconnect_slow_consumer(&mut state);
+                    return Err(PeerClosed);
+                }
+            }
+        }
+
+        state.messages.push_back(Queued { message, lossy });
+        metrics::OUTBOUND_QUEUE_DEPTH.fetch_add(1, Ordering::Relaxed);
+        self.shared.readable.notify_one();
+        Ok(())
+    }
+
+    /// Replace whatever is queued with a `SLOW_CONSUMER` error and a close frame
+    fn disconnect_slow_consumer(&self, state: &mut QueueState) {
+        tracing::warn!(
+            queue_depth = state.messages.len(),
+            "Peer outbound queue full, disconnecting slow consumer"
+        );
+        metrics::OUTBOUND_QUEUE_DEPTH.fetch_sub(state.messages.len(), Ordering::Relaxed);
+        metrics::SLOW_CONSUMER_DISCONNECTS.fetch_add(1, Ordering::Relaxed);
+        state.messages.clear();
+
+        let error = AppError::SlowConsumer.to_message(new_request_id());
+        if let Ok(text) = serde_json::to_string(&error) {
+            state.messages.push_back(Queued {
+                message: Message::text(text),
+                lossy: false,
+            });
+        }
+        state.messages.push_back(Queued {
+            message: Message::close_with(CLOSE_SLOW_CONSUMER, "Slow consumer"),
+            lossy: false,
+        });
+        metrics::OUTBOUND_QUEUE_DEPTH.fetch_add(state.messages.len(), Ordering::Relaxed);
+
+        self.shared.close(state);
+    }
+}
+
+impl Clone for PeerSender {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for PeerSender {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            let mut state = self.shared.state.lock().unwrap();
+            self.shared.close(&mut state);
+        }
+    }
+}
+
+impl PeerReceiver {
+    /// Wait for the next message. Returns `None` once the queue is closed and drained.
+    pub async fn recv(&mut self) -> Option<Message> {
+        loop {
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                if let Some(queued) = state.messages.pop_front() {
+                    metrics::OUTBOUND_QUEUE_DEPTH.fetch_sub(1, Ordering::Relaxed);
+                    return Some(queued.message);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.shared.readable.notified().await;
+        }
+    }
+}
+
+impl Drop for PeerReceiver {
+    fn drop(&mut self) {
+        // The socket is gone: discard anything still queued and refuse new messages
+        let mut state = self.shared.state.lock().unwrap();
+        metrics::OUTBOUND_QUEUE_DEPTH.fetch_sub(state.messages.len(), Ordering::Relaxed);
+        state.messages.clear();
+        self.shared.close(&mut state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(message: &Message) -> &str {
+        message.to_str().unwrap_or_default()
+    }
+
+    /// Everything queued so far, without waiting for more
+    fn drain(receiver: &mut PeerReceiver) -> Vec<Message> {
+        let mut state = receiver.shared.state.lock().unwrap();
+        state
+            .messages
+            .drain(..)
+            .map(|queued| queued.message)
+            .collect()
+    }
+
+    #[test]
+    fn drop_oldest_candidate_makes_room() {
+        let (sender, mut receiver) = peer_channel(2, OverflowPolicy::DropOldestCandidate);
+        sender.send_lossy(Message::text("candidate")).unwrap();
+        sender.send(Message::text("offer")).unwrap();
+        sender.send(Message::text("answer")).unwrap();
+
+        let queued = drain(&mut receiver);
+        assert_eq!(
+            queued.iter().map(text).collect::<Vec<_>>(),
+            ["offer", "answer"]
+        );
+        assert!(!sender.is_closed());
+    }
+
+    #[test]
+    fn drop_oldest_candidate_disconnects_without_candidates() {
+        let (sender, mut receiver) = peer_channel(2, OverflowPolicy::DropOldestCandidate);
+        sender.send(Message::text("offer")).unwrap();
+        sender.send(Message::text("answer")).unwrap();
+
+        assert!(sender.send(Message::text("more")).is_err());
+        assert!(sender.is_closed());
+        let queued = drain(&mut receiver);
+        assert!(text(&queued[0]).contains("SLOW_CONSUMER"));
+        assert!(queued[1].is_close());
+    }
+
+    #[test]
+    fn disconnect_policy_never_drops() {
+        let (sender, mut receiver) = peer_channel(1, OverflowPolicy::Disconnect);
+        sender.send_lossy(Message::text("candidate")).unwrap();
+
+        assert!(sender.send_lossy(Message::text("candidate")).is_err());
+        assert!(sender.send(Message::text("late")).is_err());
+        let queued = drain(&mut receiver);
+        assert_eq!(queued.len(), 2);
+        assert!(text(&queued[0]).contains("SLOW_CONSUMER"));
+    }
+
+    #[tokio::test]
+    async fn close_delivers_pending_messages_first() {
+        let (sender, mut receiver) = peer_channel(4, OverflowPolicy::Disconnect);
+        sender.send(Message::text("bye")).unwrap();
+        sender.close(1000, "done");
+        sender.closed().await;
+
+        assert!(sender.send(Message::text("late")).is_err());
+        assert_eq!(text(&receiver.recv().await.unwrap()), "bye");
+        assert!(receiver.recv().await.unwrap().is_close());
+        assert!(receiver.recv().await.is_none());
+    }
+
+    #[test]
+    fn dropping_receiver_closes_sender() {
+        let (sender, receiver) = peer_channel(4, OverflowPolicy::Disconnect);
+        drop(receiver);
+        assert!(sender.is_closed());
+        assert!(sender.send(Message::text("gone")).is_err());
+    }
+}
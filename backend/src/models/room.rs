@@ -1,28 +1,26 @@
+use crate::models::{Mailbox, PeerSender};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Instant;
-use tokio::sync::RwLock;
-use tokio::sync::mpsc;
+use tokio::sync::{RwLock, oneshot};
 use uuid::Uuid;
-use warp::ws::Message;
-
-/// Type alias for a sender that can send WebSocket messages
-pub type PeerSender = mpsc::UnboundedSender<Message>;
 
 /// Represents a peer in a room
 #[derive(Clone)]
 pub struct Peer {
     pub id: Uuid,
     pub sender: PeerSender,
-    pub joined_at: Instant,
+    /// Signals for this peer, kept across reconnects
+    pub mailbox: Arc<Mailbox>,
 }
 
 impl Peer {
-    pub fn new(sender: PeerSender) -> Self {
+    pub fn new(id: Uuid, sender: PeerSender, mailbox: Arc<Mailbox>) -> Self {
         Self {
-            id: Uuid::new_v4(),
+            id,
             sender,
-            joined_at: Instant::now(),
+            mailbox,
         }
     }
 }
@@ -32,8 +30,20 @@ impl Peer {
 pub struct Room {
     /// Unique room identifier (slug or UUID)
     id: String,
-    /// Optional password hash
-    password_hash: Option<String>,
+    /// Optional password hash, changeable by the owner
+    password_hash: Arc<RwLock<Option<String>>>,
+    /// Unguessable token that must accompany the room id to look up or join the room
+    share_token: Option<String>,
+    /// Token given to the creator, authorizing host controls
+    owner_token: Option<String>,
+    /// Peer id the creator joins with, receiving join requests
+    owner_peer_id: Option<Uuid>,
+    /// Joins wait for the owner's approval
+    require_approval: bool,
+    /// Join requests held on this node, resolved with the owner's answer
+    join_requests: Arc<RwLock<HashMap<Uuid, oneshot::Sender<bool>>>>,
+    /// Locked rooms accept no further joins
+    locked: Arc<AtomicBool>,
     /// Connected peers, keyed by UUID to avoid index shifting issues
     peers: Arc<RwLock<HashMap<Uuid, Peer>>>,
     /// When the room was created
@@ -44,11 +54,24 @@ pub struct Room {
 
 impl Room {
     /// Create a new room. Password should already be hashed!
-    pub fn new(id: String, password_hash: Option<String>) -> Self {
+    pub fn new(
+        id: String,
+        password_hash: Option<String>,
+        share_token: Option<String>,
+        owner_token: Option<String>,
+        owner_peer_id: Option<Uuid>,
+        require_approval: bool,
+    ) -> Self {
         let now = Instant::now();
         Self {
             id,
-            password_hash,
+            password_hash: Arc::new(RwLock::new(password_hash)),
+            share_token,
+            owner_token,
+            owner_peer_id,
+            require_approval,
+            join_requests: Arc::new(RwLock::new(HashMap::new())),
+            locked: Arc::new(AtomicBool::new(false)),
             peers: Arc::new(RwLock::new(HashMap::new())),
             created_at: now,
             last_activity: Arc::new(RwLock::new(now)),
@@ -59,12 +82,93 @@ impl Room {
         &self.id
     }
 
-    pub fn password_hash(&self) -> Option<&str> {
-        self.password_hash.as_deref()
+    pub async fn password_hash(&self) -> Option<String> {
+        self.password_hash.read().await.clone()
+    }
+
+    pub async fn set_password_hash(&self, password_hash: Option<String>) {
+        *self.password_hash.write().await = password_hash;
+    }
+
+    pub async fn has_password(&self) -> bool {
+        self.password_hash.read().await.is_some()
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    pub fn set_locked(&self, locked: bool) {
+        self.locked.store(locked, Ordering::Relaxed);
+    }
+
+    pub fn owner_token(&self) -> Option<&str> {
+        self.owner_token.as_deref()
+    }
+
+    /// Check a token presented for a host control
+    pub fn is_owner_token(&self, token: &str) -> bool {
+        self.owner_token
+            .as_deref()
+            .is_some_and(|expected| constant_time_eq(expected.as_bytes(), token.as_bytes()))
+    }
+
+    pub fn owner_peer_id(&self) -> Option<Uuid> {
+        self.owner_peer_id
     }
 
-    pub fn has_password(&self) -> bool {
-        self.password_hash.is_some()
+    pub fn requires_approval(&self) -> bool {
+        self.require_approval
+    }
+
+    /// Hold a join request until the owner answers it.
+    /// Returns `None` if `max_pending` requests are already waiting.
+    pub async fn add_join_request(
+        &self,
+        request_id: Uuid,
+        max_pending: usize,
+    ) -> Option<oneshot::Receiver<bool>> {
+        let mut join_requests = self.join_requests.write().await;
+        if join_requests.len() >= max_pending {
+            return None;
+        }
+
+        let (tx, rx) = oneshot::channel();
+        join_requests.insert(request_id, tx);
+        Some(rx)
+    }
+
+    /// Pass the owner's answer to a join request held on this node.
+    /// Returns false if the request is not (or no longer) waiting here.
+    pub async fn resolve_join_request(&self, request_id: Uuid, approved: bool) -> bool {
+        match self.join_requests.write().await.remove(&request_id) {
+            Some(tx) => tx.send(approved).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Drop a join request without an answer. Returns false if it was already resolved.
+    pub async fn cancel_join_request(&self, request_id: Uuid) -> bool {
+        self.join_requests
+            .write()
+            .await
+            .remove(&request_id)
+            .is_some()
+    }
+
+    pub fn share_token(&self) -> Option<&str> {
+        self.share_token.as_deref()
+    }
+
+    /// Check the share token presented by a client. Rooms without a token accept anything.
+    pub fn accepts_share_token(&self, token: Option<&str>) -> bool {
+        match (&self.share_token, token) {
+            (None, _) => true,
+            (Some(expected), Some(token)) => {
+                constant_time_eq(expected.as_bytes(), token.as_bytes())
+            }
+            (Some(_), None) => false,
+        }
     }
 
     pub fn created_at(&self) -> Instant {
@@ -79,3 +183,8 @@ impl Room {
         &self.last_activity
     }
 }
+
+/// Compare two byte strings without leaking the position of the first difference
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,91 @@
+use crate::config::ConfigHandle;
+use crate::error::AppError;
+use crate::models::room::constant_time_eq;
+use warp::Filter;
+
+/// POST /api/admin/reload
+/// Re-read the configuration, like SIGHUP, and answer which changed settings were applied.
+/// Requires `Authorization: Bearer <ADMIN_TOKEN>`; answers 404 while `ADMIN_TOKEN` is unset.
+pub fn admin_routes(
+    config: ConfigHandle,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "admin" / "reload")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and_then(move |authorization: Option<String>| {
+            let config = config.clone();
+            async move {
+                let Some(admin_token) = config.current().admin_token.clone() else {
+                    return Err(warp::reject::not_found());
+                };
+                let authorized = authorization
+                    .as_deref()
+                    .and_then(|value| value.strip_prefix("Bearer "))
+                    .is_some_and(|token| {
+                        constant_time_eq(token.as_bytes(), admin_token.expose().as_bytes())
+                    });
+                if !authorized {
+                    tracing::warn!("Admin request with a missing or invalid token");
+                    return Err(warp::reject::custom(AppError::Unauthorized));
+                }
+
+                // Reading the config file blocks, so keep it off the async workers
+                let report = tokio::task::spawn_blocking(move || config.reload())
+                    .await
+                    .map_err(|e| warp::reject::custom(AppError::InternalError(e.to_string())))?
+                    .map_err(|e| warp::reject::custom(AppError::InvalidConfig(e.0.join("; "))))?;
+                Ok(warp::reply::json(&report))
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::TempFile;
+    use crate::config::{CommandLine, Config};
+    use crate::middleware::rejection::handle_rejection;
+    use warp::http::StatusCode;
+
+    fn route(
+        file: &TempFile,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = std::convert::Infallible> + Clone + 'static
+    {
+        let cli = CommandLine::parse([format!("--config={}", file.path().display())]).unwrap();
+        let config = ConfigHandle::new(Config::load(&cli).unwrap(), cli);
+        admin_routes(config).recover(handle_rejection)
+    }
+
+    async fn reload(file: &TempFile, authorization: &str) -> (StatusCode, serde_json::Value) {
+        let response = warp::test::request()
+            .method("POST")
+            .path("/api/admin/reload")
+            .header("authorization", authorization)
+            .reply(&route(file))
+            .await;
+        let body = serde_json::from_slice(response.body()).unwrap();
+        (response.status(), body)
+    }
+
+    #[tokio::test]
+    async fn reload_needs_the_admin_token() {
+        let file = TempFile::new("admin_token = \"letmein\"\n");
+        let (status, body) = reload(&file, "Bearer wrong").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], "UNAUTHORIZED");
+
+        let (status, body) = reload(&file, "Bearer letmein").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            serde_json::json!({ "applied": [], "restart_required": [] })
+        );
+    }
+
+    #[tokio::test]
+    async fn reload_is_hidden_without_an_admin_token() {
+        let file = TempFile::new("");
+        let (status, _) = reload(&file, "Bearer anything").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
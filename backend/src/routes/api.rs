@@ -1,33 +1,60 @@
+use crate::config::ConfigHandle;
 use crate::handlers::ws_handler::check_room_handler;
 use crate::ice;
+use crate::middleware::rate_limit::{RateLimiter, rate_limit};
 use crate::services::RoomService;
+use serde::Deserialize;
 use std::sync::Arc;
 use warp::Filter;
+use warp::filters::BoxedFilter;
 
 pub fn api_routes(
+    config: ConfigHandle,
     room_service: Arc<RoomService>,
+    rate_limiter: Arc<RateLimiter>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    check_rooms_route(room_service)
-        .or(ice_servers_route())
+    let limit = rate_limit(rate_limiter, config.current().trusted_proxies.clone());
+
+    check_rooms_route(room_service, limit.clone())
+        .or(ice_servers_route(config, limit))
         .or(health_route())
 }
 
-/// GET /api/rooms/:id
+/// Query string of GET /api/rooms/:id
+#[derive(Deserialize)]
+struct CheckRoomQuery {
+    /// Share token from the room link
+    token: Option<String>,
+}
+
+/// GET /api/rooms/:id?token=...
 fn check_rooms_route(
     room_service: Arc<RoomService>,
+    limit: BoxedFilter<()>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("api" / "rooms" / String)
         .and(warp::get())
+        .and(limit)
+        .and(warp::query::<CheckRoomQuery>())
         .and(warp::any().map(move || room_service.clone()))
-        .and_then(|room_id, room_svc| check_room_handler(room_id, room_svc))
+        .and_then(|room_id, query: CheckRoomQuery, room_svc| {
+            check_room_handler(room_id, query.token, room_svc)
+        })
 }
 
 /// GET /api/ice-servers
-fn ice_servers_route() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path!("api" / "ice-servers").and(warp::get()).map(|| {
-        let config = ice::get_ice_servers();
-        warp::reply::json(&config)
-    })
+/// Only STUN servers: TURN credentials are issued over the WebSocket once a peer is in a room.
+fn ice_servers_route(
+    config: ConfigHandle,
+    limit: BoxedFilter<()>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "ice-servers")
+        .and(warp::get())
+        .and(limit)
+        .map(move || {
+            let ice_config = ice::get_stun_servers(&config.current());
+            warp::reply::json(&ice_config)
+        })
 }
 
 /// GET /health
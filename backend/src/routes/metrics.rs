@@ -0,0 +1,62 @@
+use crate::metrics;
+use crate::services::RoomService;
+use std::sync::Arc;
+use warp::Filter;
+
+/// GET /metrics
+/// When `enabled` is false the route answers 404, e.g. on the public port while an admin port serves it.
+pub fn metrics_route(
+    room_service: Arc<RoomService>,
+    enabled: bool,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("metrics")
+        .and(warp::get())
+        .and(warp::any().map(move || room_service.clone()))
+        .and_then(move |room_svc: Arc<RoomService>| async move {
+            if !enabled {
+                return Err(warp::reject::not_found());
+            }
+
+            let body = metrics::render(room_svc.room_count().await);
+            Ok(warp::reply::with_header(
+                body,
+                "content-type",
+                "text/plain; version=0.0.4",
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::rejection::handle_rejection;
+    use crate::services::room::tests::service;
+    use crate::store::InMemoryRoomStore;
+    use warp::http::StatusCode;
+
+    fn room_service() -> Arc<RoomService> {
+        Arc::new(service(Arc::new(InMemoryRoomStore::new())))
+    }
+
+    #[tokio::test]
+    async fn serves_prometheus_text() {
+        let response = warp::test::request()
+            .path("/metrics")
+            .reply(&metrics_route(room_service(), true))
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/plain; version=0.0.4"
+        );
+        let body = std::str::from_utf8(response.body()).unwrap();
+        assert!(body.lines().any(|line| line == "filecoffee_active_rooms 0"));
+    }
+
+    #[tokio::test]
+    async fn disabled_route_is_not_found() {
+        let route = metrics_route(room_service(), false).recover(handle_rejection);
+        let response = warp::test::request().path("/metrics").reply(&route).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
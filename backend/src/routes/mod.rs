@@ -1,5 +1,11 @@
+pub mod admin;
 pub mod api;
+pub mod metrics;
+pub mod relay;
 pub mod ws;
 
+pub use admin::admin_routes;
 pub use api::api_routes;
+pub use metrics::metrics_route;
+pub use relay::relay_route;
 pub use ws::ws_route;
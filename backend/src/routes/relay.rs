@@ -0,0 +1,101 @@
+use crate::error::AppError;
+use crate::metrics;
+use crate::middleware::rejection::error_reply;
+use crate::services::{RelayService, RelayTicket, RoomService};
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use tokio::sync::mpsc;
+use warp::Filter;
+use warp::Reply;
+use warp::filters::BoxedFilter;
+use warp::ws::{Message, WebSocket};
+
+/// Largest relayed frame; clients send file data in chunks well below this
+const RELAY_MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+/// Frames waiting to be written to a relay socket. When full, the partner's
+/// reads pause, which pushes back on the sender over TCP.
+const RELAY_QUEUE_CAPACITY: usize = 16;
+
+/// Query string of /ws/relay/:room
+#[derive(Deserialize)]
+struct RelayQuery {
+    /// Token from `RelayReady`
+    token: String,
+}
+
+/// GET /ws/relay/:room?token=...
+/// Binary WebSocket piping file data to the partner named in the token.
+pub fn relay_route(
+    room_service: Arc<RoomService>,
+    relay_service: Arc<RelayService>,
+    origin_check: BoxedFilter<()>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("ws" / "relay" / String)
+        .and(origin_check)
+        .and(warp::query::<RelayQuery>())
+        .and(warp::ws())
+        .and(warp::any().map(move || room_service.clone()))
+        .and(warp::any().map(move || relay_service.clone()))
+        .and_then(
+            |room_id: String,
+             query: RelayQuery,
+             ws: warp::ws::Ws,
+             room_svc: Arc<RoomService>,
+             relay_svc: Arc<RelayService>| async move {
+                // The token may outlive the peer's membership of the room
+                let ticket = match relay_svc.redeem_token(&room_id, &query.token).await {
+                    Ok(ticket) if room_svc.has_peer(&room_id, ticket.peer_id).await => ticket,
+                    Ok(_) => return Ok(error_reply(&AppError::InvalidRelayToken)),
+                    Err(e) => return Ok(error_reply(&e)),
+                };
+
+                Ok::<_, warp::Rejection>(
+                    ws.max_message_size(RELAY_MAX_FRAME_SIZE)
+                        .max_frame_size(RELAY_MAX_FRAME_SIZE)
+                        .on_upgrade(move |socket| handle_relay(socket, room_id, ticket, relay_svc))
+                        .into_response(),
+                )
+            },
+        )
+}
+
+async fn handle_relay(
+    ws: WebSocket,
+    room_id: String,
+    ticket: RelayTicket,
+    relay_service: Arc<RelayService>,
+) {
+    metrics::RELAY_CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+
+    let (mut ws_tx, mut ws_rx) = ws.split();
+    let (tx, mut rx) = mpsc::channel::<Message>(RELAY_QUEUE_CAPACITY);
+
+    tokio::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            let is_close = message.is_close();
+            if ws_tx.send(message).await.is_err() || is_close {
+                break;
+            }
+        }
+    });
+
+    let connection_id = relay_service.attach(&room_id, ticket, tx).await;
+
+    while let Some(Ok(msg)) = ws_rx.next().await {
+        if msg.is_close() {
+            break;
+        }
+        if !msg.is_binary() && !msg.is_text() {
+            continue;
+        }
+        if !relay_service.forward(&room_id, ticket, msg).await {
+            break;
+        }
+    }
+
+    relay_service.detach(&room_id, ticket, connection_id).await;
+    metrics::RELAY_CONNECTIONS.fetch_sub(1, Ordering::Relaxed);
+}
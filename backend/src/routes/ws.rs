@@ -1,39 +1,89 @@
-use crate::handlers::ws_handler::{PeerContext, cleanup_peer, handle_client_message};
-use crate::models;
-use crate::models::ClientMessage;
-use crate::services::{RoomService, SignalingService};
-use futures::{SinkExt, StreamExt};
+use crate::config::{Config, ConfigHandle};
+use crate::error::AppError;
+use crate::handlers::ws_handler::{
+    PeerContext, cleanup_peer, handle_client_message, send_error, send_message,
+};
+use crate::metrics;
+use crate::middleware::rate_limit::{RateLimiter, TokenBucket, client_ip};
+use crate::models::{ClientMessage, peer_channel};
+use crate::services::{RelayService, RoomService, SignalingService};
+use futures::{FutureExt, SinkExt, StreamExt};
+use std::net::IpAddr;
 use std::sync::Arc;
-use tokio::sync::{RwLock, mpsc};
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+use tokio::sync::RwLock;
+use warp::filters::BoxedFilter;
 use warp::{
     Filter,
     ws::{Message, WebSocket},
 };
 
+/// Messages over `ws_max_message_size` are answered with an error, but anything this many
+/// times larger is refused by the WebSocket transport itself and drops the connection.
+const TRANSPORT_SIZE_FACTOR: usize = 4;
+
+/// Close code for messages that violate policy (RFC 6455)
+const CLOSE_POLICY_VIOLATION: u16 = 1008;
+
+/// Close code for a server going down (RFC 6455)
+const CLOSE_GOING_AWAY: u16 = 1001;
+
 pub fn ws_route(
+    config: ConfigHandle,
     room_service: Arc<RoomService>,
     signaling_service: Arc<SignalingService>,
+    relay_service: Arc<RelayService>,
+    rate_limiter: Arc<RateLimiter>,
+    origin_check: BoxedFilter<()>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let trusted_proxies = config.current().trusted_proxies.clone();
+
+    // Browsers let any page open a WebSocket, so the origin is checked before upgrading.
+    // Each connection keeps the configuration it was opened with.
     warp::path("ws")
+        .and(warp::path::end())
+        .and(origin_check)
         .and(warp::ws())
+        .and(warp::any().map(move || config.current()))
+        .map(|ws: warp::ws::Ws, config: Arc<Config>| {
+            let transport_limit = config.ws_max_message_size * TRANSPORT_SIZE_FACTOR;
+            let ws = ws
+                .max_message_size(transport_limit)
+                .max_frame_size(transport_limit);
+            (ws, config)
+        })
+        .untuple_one()
+        .and(client_ip(trusted_proxies))
         .and(warp::any().map(move || room_service.clone()))
         .and(warp::any().map(move || signaling_service.clone()))
-        .map(|ws: warp::ws::Ws, room_svc, sig_svc| {
-            ws.on_upgrade(move |socket| handle_connection(socket, room_svc, sig_svc))
-        })
+        .and(warp::any().map(move || relay_service.clone()))
+        .and(warp::any().map(move || rate_limiter.clone()))
+        .map(
+            |ws: warp::ws::Ws, config, ip, room_svc, sig_svc, relay_svc, limiter| {
+                ws.on_upgrade(move |socket| {
+                    handle_connection(socket, ip, config, room_svc, sig_svc, relay_svc, limiter)
+                })
+            },
+        )
 }
 
 async fn handle_connection(
     ws: WebSocket,
+    client_ip: IpAddr,
+    config: Arc<Config>,
     room_service: Arc<RoomService>,
     signaling_service: Arc<SignalingService>,
+    relay_service: Arc<RelayService>,
+    rate_limiter: Arc<RateLimiter>,
 ) {
-    tracing::debug!("New WebSocket connection");
+    tracing::debug!(client_ip = %client_ip, "New WebSocket connection");
+    metrics::CONNECTED_PEERS.fetch_add(1, Ordering::Relaxed);
 
     let (mut ws_tx, mut ws_rx) = ws.split();
-    let (tx, mut rx) = mpsc::unbounded_channel::<Message>();
+    let (tx, mut rx) = peer_channel(config.ws_outbound_queue_capacity, config.ws_overflow_policy);
 
-    let peer_context = Arc::new(RwLock::new(PeerContext::new()));
+    let peer_context = Arc::new(RwLock::new(PeerContext::new(client_ip)));
     let peer_context_clone = peer_context.clone();
     let room_service_clone = room_service.clone();
     let signaling_service_clone = signaling_service.clone();
@@ -47,16 +97,87 @@ async fn handle_connection(
         }
     });
 
+    // Ping on every heartbeat; a socket silent for longer than the timeout is dead
+    let mut heartbeat = tokio::time::interval(config.heartbeat_interval());
+    let max_silence = config.heartbeat_timeout();
+
+    // Per-connection message budget allowing a one second burst (0 disables it)
+    let messages_per_sec = config.ws_max_messages_per_sec as f64;
+    let mut message_budget =
+        (messages_per_sec > 0.0).then(|| TokenBucket::new(messages_per_sec, messages_per_sec));
+
+    // Told once when the node starts draining, including if it already has
+    let mut draining = room_service.draining();
+    let mut shutdown_sent = false;
+
     // Process incoming messages
-    while let Some(result) = ws_rx.next().await {
-        let msg = match result {
-            Ok(msg) => msg,
-            Err(e) => {
-                tracing::error!(error = %e, "WebSocket error");
+    loop {
+        let msg = tokio::select! {
+            result = ws_rx.next() => match result {
+                Some(Ok(msg)) => msg,
+                Some(Err(e)) => {
+                    tracing::error!(error = %e, "WebSocket error");
+                    break;
+                }
+                None => break,
+            },
+            _ = tx.closed() => {
+                // Outbound queue overflowed, the peer is being disconnected
                 break;
             }
+            _ = heartbeat.tick() => {
+                let last_seen = peer_context.read().await.last_seen;
+                if last_seen.elapsed() > max_silence {
+                    tracing::info!(client_ip = %client_ip, "WebSocket heartbeat timed out");
+                    let _ = tx.send(Message::close());
+                    break;
+                }
+                let _ = tx.send_lossy(Message::ping(Vec::new()));
+                continue;
+            }
+            true = draining.wait_for(|draining| *draining).map(|result| result.is_ok()), if !shutdown_sent => {
+                shutdown_sent = true;
+                send_message(&tx, room_service.server_shutdown());
+
+                // Connections outside a room have nothing in flight and can go right away
+                let ctx = peer_context.read().await;
+                if ctx.room_id.is_none() && ctx.join_request.is_none() {
+                    let _ = tx.send(Message::close_with(CLOSE_GOING_AWAY, "Server shutting down"));
+                    break;
+                }
+                continue;
+            }
         };
 
+        // Any frame, including pongs, proves the connection is alive
+        peer_context.write().await.last_seen = Instant::now();
+
+        if !msg.is_text() && !msg.is_binary() {
+            continue;
+        }
+
+        if message_budget
+            .as_mut()
+            .is_some_and(|budget| budget.try_take().is_err())
+        {
+            tracing::warn!(client_ip = %client_ip, "WebSocket message rate exceeded, disconnecting");
+            send_error(&tx, &AppError::MessageRateExceeded);
+            let _ = tx.send(Message::close_with(
+                CLOSE_POLICY_VIOLATION,
+                "Message rate exceeded",
+            ));
+            break;
+        }
+
+        if msg.as_bytes().len() > config.ws_max_message_size {
+            tracing::warn!(client_ip = %client_ip, size = msg.as_bytes().len(), "WebSocket message too large");
+            let e = AppError::MessageTooLarge {
+                max_bytes: config.ws_max_message_size,
+            };
+            send_error(&tx, &e);
+            continue;
+        }
+
         if let Ok(text) = msg.to_str() {
             match serde_json::from_str::<ClientMessage>(text) {
                 Ok(client_msg) => {
@@ -65,17 +186,15 @@ async fn handle_connection(
                         &tx,
                         &room_service,
                         &signaling_service,
+                        &relay_service,
+                        &rate_limiter,
                         &peer_context,
                     )
                     .await;
                 }
                 Err(e) => {
                     tracing::warn!(error = %e, "Failed to parse message");
-                    let error = models::ServerMessage::Error {
-                        code: models::error_codes::INVALID_MESSAGE.to_string(),
-                        message: "Invalid message format".to_string(),
-                    };
-                    let _ = tx.send(Message::text(serde_json::to_string(&error).unwrap()));
+                    send_error(&tx, &AppError::InvalidMessage(e.to_string()));
                 }
             }
         }
@@ -83,8 +202,140 @@ async fn handle_connection(
 
     cleanup_peer(
         peer_context_clone,
+        &tx,
         &room_service_clone,
         &signaling_service_clone,
     )
     .await;
+    metrics::CONNECTED_PEERS.fetch_sub(1, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::InProcessBus;
+    use crate::config::CommandLine;
+    use crate::models::{ServerMessage, error_codes};
+    use crate::services::room::tests::service;
+    use crate::store::InMemoryRoomStore;
+    use warp::test::WsClient;
+
+    /// The WebSocket route with these command-line settings
+    fn route(args: &[&str]) -> impl Filter<Extract = impl warp::Reply> + Clone + 'static {
+        route_to(args, Arc::new(service(Arc::new(InMemoryRoomStore::new()))))
+    }
+
+    fn route_to(
+        args: &[&str],
+        room_service: Arc<RoomService>,
+    ) -> impl Filter<Extract = impl warp::Reply> + Clone + 'static {
+        let cli = CommandLine::parse(args.iter().map(|a| a.to_string())).unwrap();
+        let config = ConfigHandle::new(Config::load(&cli).unwrap(), cli);
+        ws_route(
+            config.clone(),
+            room_service,
+            Arc::new(SignalingService::new(Arc::new(InProcessBus::new()))),
+            Arc::new(RelayService::new(config)),
+            Arc::new(RateLimiter::new(0)),
+            warp::any().boxed(),
+        )
+    }
+
+    /// Next message from the server, skipping heartbeat pings
+    async fn next_message(client: &mut WsClient) -> ServerMessage {
+        loop {
+            let message = client.recv().await.expect("connection open");
+            if let Ok(text) = message.to_str() {
+                return serde_json::from_str(text).unwrap();
+            }
+        }
+    }
+
+    fn error_code(message: ServerMessage) -> String {
+        match message {
+            ServerMessage::Error { code, .. } => code,
+            other => panic!("expected an error, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_messages_are_refused_without_disconnecting() {
+        let route = route(&["--ws-max-message-size=64", "--ws-max-messages-per-sec=0"]);
+        let mut client = warp::test::ws().path("/ws").handshake(route).await.unwrap();
+
+        client.send_text("x".repeat(65)).await;
+        assert_eq!(
+            error_code(next_message(&mut client).await),
+            error_codes::MESSAGE_TOO_LARGE
+        );
+
+        client.send_text(r#"{"type":"Ping"}"#).await;
+        assert!(matches!(
+            next_message(&mut client).await,
+            ServerMessage::Pong
+        ));
+    }
+
+    #[tokio::test]
+    async fn flooding_the_message_budget_disconnects() {
+        let route = route(&["--ws-max-messages-per-sec=1"]);
+        let mut client = warp::test::ws().path("/ws").handshake(route).await.unwrap();
+
+        client.send_text(r#"{"type":"Ping"}"#).await;
+        assert!(matches!(
+            next_message(&mut client).await,
+            ServerMessage::Pong
+        ));
+        client.send_text(r#"{"type":"Ping"}"#).await;
+        assert_eq!(
+            error_code(next_message(&mut client).await),
+            error_codes::MESSAGE_RATE_EXCEEDED
+        );
+        client.recv_closed().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn draining_closes_idle_connections_and_keeps_rooms_open() {
+        let rooms = Arc::new(service(Arc::new(InMemoryRoomStore::new())));
+        let route = route_to(&[], rooms.clone());
+        let mut idle = warp::test::ws()
+            .path("/ws")
+            .handshake(route.clone())
+            .await
+            .unwrap();
+        let mut in_room = warp::test::ws().path("/ws").handshake(route).await.unwrap();
+
+        in_room
+            .send_text(r#"{"type":"CreateRoom","password":null}"#)
+            .await;
+        assert!(matches!(
+            next_message(&mut in_room).await,
+            ServerMessage::RoomCreated { .. }
+        ));
+
+        rooms.start_draining();
+        for client in [&mut idle, &mut in_room] {
+            assert!(matches!(
+                next_message(client).await,
+                ServerMessage::ServerShutdown { .. }
+            ));
+        }
+        idle.recv_closed().await.unwrap();
+
+        // The room keeps working, but no new ones are created
+        in_room.send_text(r#"{"type":"Ping"}"#).await;
+        assert!(matches!(
+            next_message(&mut in_room).await,
+            ServerMessage::Pong
+        ));
+        let mut late = warp::test::ws()
+            .path("/ws")
+            .handshake(route_to(&[], rooms))
+            .await
+            .unwrap();
+        assert!(matches!(
+            next_message(&mut late).await,
+            ServerMessage::ServerShutdown { .. }
+        ));
+    }
 }
@@ -1,5 +1,9 @@
+pub mod password_guard;
+pub mod relay;
 pub mod room;
 pub mod signaling;
 
-pub use room::RoomService;
-pub use signaling::SignalingService;
\ No newline at end of file
+pub use password_guard::PasswordGuard;
+pub use relay::{RelayService, RelayTicket};
+pub use room::{JoinOutcome, ResumedSession, RoomService, SessionState};
+pub use signaling::SignalingService;
@@ -0,0 +1,208 @@
+use crate::config::{Config, ConfigHandle};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Failed password attempts against one room or from one source
+struct Failures {
+    count: u32,
+    /// No attempt is accepted before this instant
+    blocked_until: Instant,
+}
+
+/// Tracks failed room passwords per room and per client IP.
+/// Every failure doubles the wait before the next attempt (up to a maximum). After
+/// `password_lockout_threshold` failures the client IP is locked out entirely; a room
+/// only ever gets the backoff, so a stranger guessing cannot lock its real joiners out.
+/// Counters live on this node only, like the rate limiter.
+pub struct PasswordGuard {
+    /// Read on every attempt, so reloaded thresholds apply right away
+    config: ConfigHandle,
+    rooms: Mutex<HashMap<String, Failures>>,
+    sources: Mutex<HashMap<IpAddr, Failures>>,
+}
+
+impl PasswordGuard {
+    pub fn new(config: ConfigHandle) -> Self {
+        Self {
+            config,
+            rooms: Mutex::new(HashMap::new()),
+            sources: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Check whether a password attempt is allowed, or return how long to wait.
+    pub async fn check(&self, room_id: &str, source: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let room_wait = remaining(self.rooms.lock().await.get(room_id), now);
+        let source_wait = remaining(self.sources.lock().await.get(&source), now);
+
+        match room_wait.max(source_wait) {
+            wait if wait.is_zero() => Ok(()),
+            wait => Err(wait),
+        }
+    }
+
+    /// Record a wrong password. Returns the number of failures against the room so far.
+    pub async fn record_failure(&self, room_id: &str, source: IpAddr) -> u32 {
+        let config = self.config.current();
+        let room_failures = record(
+            &mut *self.rooms.lock().await,
+            room_id.to_string(),
+            |count| backoff(&config, count),
+        );
+        record(&mut *self.sources.lock().await, source, |count| {
+            if config.password_lockout_threshold > 0 && count >= config.password_lockout_threshold {
+                config.password_lockout()
+            } else {
+                backoff(&config, count)
+            }
+        });
+        room_failures
+    }
+
+    /// Forget the failures of a source that got the password right.
+    pub async fn record_success(&self, room_id: &str, source: IpAddr) {
+        self.rooms.lock().await.remove(room_id);
+        self.sources.lock().await.remove(&source);
+    }
+
+    /// Drop entries whose backoff or lockout is long over.
+    pub async fn prune(&self) {
+        let now = Instant::now();
+        let lockout = self.config.current().password_lockout();
+        let expired = |failures: &Failures| now.duration_since(failures.blocked_until) > lockout;
+        self.rooms
+            .lock()
+            .await
+            .retain(|_, failures| !expired(failures));
+        self.sources
+            .lock()
+            .await
+            .retain(|_, failures| !expired(failures));
+    }
+}
+
+/// Count a failure and block further attempts for `wait(count)`
+fn record<K: Eq + Hash>(
+    entries: &mut HashMap<K, Failures>,
+    key: K,
+    wait: impl FnOnce(u32) -> Duration,
+) -> u32 {
+    let now = Instant::now();
+    let failures = entries.entry(key).or_insert(Failures {
+        count: 0,
+        blocked_until: now,
+    });
+    failures.count += 1;
+    failures.blocked_until = now + wait(failures.count);
+    failures.count
+}
+
+/// Wait after the `count`th failure: the base, doubled for every further failure
+fn backoff(config: &Config, count: u32) -> Duration {
+    let exponent = (count - 1).min(31);
+    config
+        .password_backoff_base()
+        .saturating_mul(1 << exponent)
+        .min(config.password_backoff_max())
+}
+
+fn remaining(failures: Option<&Failures>, now: Instant) -> Duration {
+    failures.map_or(Duration::ZERO, |failures| {
+        failures.blocked_until.saturating_duration_since(now)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CommandLine;
+    use crate::config::tests::TempFile;
+
+    const ROOM: &str = "hot-espresso-42";
+
+    fn source(n: u8) -> IpAddr {
+        IpAddr::from([192, 0, 2, n])
+    }
+
+    fn settings(lockout_threshold: u32) -> String {
+        format!(
+            "password_backoff_base_ms = 1000\n\
+             password_backoff_max_secs = 4\n\
+             password_lockout_threshold = {lockout_threshold}\n\
+             password_lockout_secs = 100\n"
+        )
+    }
+
+    fn guard(file: &TempFile) -> (PasswordGuard, ConfigHandle) {
+        let cli = CommandLine::parse([format!("--config={}", file.path().display())]).unwrap();
+        let config = ConfigHandle::new(Config::load(&cli).unwrap(), cli);
+        (PasswordGuard::new(config.clone()), config)
+    }
+
+    /// Wait asked of `source`, rounded up to whole seconds
+    async fn wait_secs(guard: &PasswordGuard, room_id: &str, source: IpAddr) -> u64 {
+        match guard.check(room_id, source).await {
+            Ok(()) => 0,
+            Err(wait) => wait.as_secs_f64().ceil() as u64,
+        }
+    }
+
+    #[tokio::test]
+    async fn backoff_doubles_up_to_the_maximum() {
+        let file = TempFile::new(&settings(0));
+        let (guard, _) = guard(&file);
+
+        assert_eq!(wait_secs(&guard, ROOM, source(1)).await, 0);
+        for expected in [1, 2, 4, 4, 4] {
+            guard.record_failure(ROOM, source(1)).await;
+            assert_eq!(wait_secs(&guard, ROOM, source(1)).await, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn lockout_hits_the_source_and_not_the_room() {
+        let file = TempFile::new(&settings(3));
+        let (guard, _) = guard(&file);
+
+        for attempt in 1..=3 {
+            assert_eq!(guard.record_failure(ROOM, source(1)).await, attempt);
+        }
+        assert_eq!(wait_secs(&guard, ROOM, source(1)).await, 100);
+        assert_eq!(wait_secs(&guard, "other-room", source(1)).await, 100);
+
+        // Others only wait out the room's backoff
+        assert_eq!(wait_secs(&guard, ROOM, source(2)).await, 4);
+        assert_eq!(wait_secs(&guard, "other-room", source(2)).await, 0);
+        assert_eq!(guard.record_failure(ROOM, source(2)).await, 4);
+        assert_eq!(wait_secs(&guard, ROOM, source(2)).await, 4);
+    }
+
+    #[tokio::test]
+    async fn success_clears_the_backoff() {
+        let file = TempFile::new(&settings(3));
+        let (guard, _) = guard(&file);
+
+        guard.record_failure(ROOM, source(1)).await;
+        guard.record_success(ROOM, source(1)).await;
+        assert_eq!(wait_secs(&guard, ROOM, source(1)).await, 0);
+        assert_eq!(guard.record_failure(ROOM, source(1)).await, 1);
+    }
+
+    #[tokio::test]
+    async fn reloaded_thresholds_apply_to_the_next_failure() {
+        let file = TempFile::new(&settings(0));
+        let (guard, config) = guard(&file);
+
+        guard.record_failure(ROOM, source(1)).await;
+        assert_eq!(wait_secs(&guard, ROOM, source(1)).await, 1);
+
+        std::fs::write(file.path(), settings(2)).unwrap();
+        config.reload().unwrap();
+        guard.record_failure(ROOM, source(1)).await;
+        assert_eq!(wait_secs(&guard, ROOM, source(1)).await, 100);
+    }
+}
@@ -0,0 +1,427 @@
+use crate::config::{Config, ConfigHandle, Secret};
+use crate::error::AppError;
+use crate::metrics;
+use crate::middleware::rate_limit::TokenBucket;
+use crate::models::ServerMessage;
+use crate::services::RoomService;
+use crate::slug_generator::generate_token;
+use base64::{Engine as _, engine::general_purpose};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{Mutex, mpsc};
+use uuid::Uuid;
+use warp::ws::Message;
+
+type HmacSha1 = Hmac<Sha1>;
+
+/// How long a relay token can be used to connect. An open relay is not cut off when it expires.
+const RELAY_TOKEN_TTL_SECS: u64 = 300;
+
+/// Normal closure (RFC 6455)
+const CLOSE_NORMAL: u16 = 1000;
+
+/// Close code for a relay that hit the room's byte cap (RFC 6455 policy violation)
+const CLOSE_POLICY_VIOLATION: u16 = 1008;
+
+/// One end of a relay, as authorized by its token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RelayTicket {
+    pub peer_id: Uuid,
+    /// The only peer this end may send to
+    pub partner_id: Uuid,
+}
+
+/// Outbound side of a relay socket
+#[derive(Clone)]
+struct RelayLink {
+    /// Tells a replaced link apart from the one that replaced it
+    connection_id: Uuid,
+    sender: mpsc::Sender<Message>,
+}
+
+impl RelayLink {
+    fn close(&self, code: u16, reason: &'static str) {
+        let _ = self.sender.try_send(Message::close_with(code, reason));
+    }
+}
+
+/// Relay state of one room. Kept while the room exists, so the byte cap
+/// cannot be reset by reconnecting.
+struct RelayRoom {
+    /// Keyed by the ticket of each end, so a peer can relay to several partners at once
+    links: HashMap<RelayTicket, RelayLink>,
+    bytes_relayed: u64,
+    bandwidth: Option<TokenBucket>,
+}
+
+/// Service piping file data between two peers of a room when WebRTC cannot connect.
+/// Data only passes through memory. Both ends of a relay must reach the same node.
+pub struct RelayService {
+    config: ConfigHandle,
+    /// Signs tokens when `RELAY_SECRET` is unset. Rotating the secret invalidates outstanding tokens.
+    fallback_secret: Secret,
+    rooms: Mutex<HashMap<String, RelayRoom>>,
+    /// Tokens already used to connect, with their expiration
+    redeemed_tokens: Mutex<HashMap<String, u64>>,
+}
+
+impl RelayService {
+    pub fn new(config: ConfigHandle) -> Self {
+        Self {
+            config,
+            fallback_secret: Secret::new("RELAY_SECRET", generate_token()),
+            rooms: Mutex::new(HashMap::new()),
+            redeemed_tokens: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The configuration as of now; it may be reloaded between calls.
+    fn config(&self) -> Arc<Config> {
+        self.config.current()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config().relay_enabled
+    }
+
+    /// `RelayReady` for `peer_id`, with a token to connect its end of a relay to `partner_id`.
+    pub fn relay_ready(&self, room_id: &str, peer_id: Uuid, partner_id: Uuid) -> ServerMessage {
+        let config = self.config();
+        ServerMessage::RelayReady {
+            peer_id: partner_id,
+            token: self.issue_token(room_id, peer_id, partner_id),
+            max_bytes: config.relay_max_bytes,
+            max_bytes_per_sec: config.relay_max_bytes_per_sec,
+        }
+    }
+
+    /// Issue a token letting `peer_id` connect a relay to `partner_id` in the room.
+    fn issue_token(&self, room_id: &str, peer_id: Uuid, partner_id: Uuid) -> String {
+        let expiration = unix_time() + RELAY_TOKEN_TTL_SECS;
+        // The nonce keeps tokens issued within the same second apart, as each connects once
+        let nonce = Uuid::new_v4().simple();
+        let claims = format!("{expiration}.{peer_id}.{partner_id}.{nonce}");
+        let signature = self.sign(room_id, &claims).finalize().into_bytes();
+        format!(
+            "{claims}.{}",
+            general_purpose::URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+
+    /// Check a relay token for the room and return what it authorizes.
+    /// Each token connects once; using it again fails.
+    pub async fn redeem_token(&self, room_id: &str, token: &str) -> Result<RelayTicket, AppError> {
+        if !self.config().relay_enabled {
+            return Err(AppError::RelayDisabled);
+        }
+
+        let (claims, signature) = token.rsplit_once('.').ok_or(AppError::InvalidRelayToken)?;
+        let signature = general_purpose::URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| AppError::InvalidRelayToken)?;
+        self.sign(room_id, claims)
+            .verify_slice(&signature)
+            .map_err(|_| AppError::InvalidRelayToken)?;
+
+        let mut parts = claims.split('.');
+        let expiration: u64 = parts
+            .next()
+            .and_then(|v| v.parse().ok())
+            .ok_or(AppError::InvalidRelayToken)?;
+        let peer_id = parts.next().and_then(|v| v.parse().ok());
+        let partner_id = parts.next().and_then(|v| v.parse().ok());
+        let ticket = match (peer_id, partner_id) {
+            (Some(peer_id), Some(partner_id)) if expiration >= unix_time() => RelayTicket {
+                peer_id,
+                partner_id,
+            },
+            _ => return Err(AppError::InvalidRelayToken),
+        };
+
+        let mut redeemed = self.redeemed_tokens.lock().await;
+        if redeemed.insert(token.to_string(), expiration).is_some() {
+            return Err(AppError::InvalidRelayToken);
+        }
+        Ok(ticket)
+    }
+
+    /// Register a relay socket, replacing any earlier one of the same peer to the same partner.
+    /// Both ends get `RelayConnected` once the partner is connected too.
+    /// Returns the connection id to pass to `detach`.
+    pub async fn attach(
+        &self,
+        room_id: &str,
+        ticket: RelayTicket,
+        sender: mpsc::Sender<Message>,
+    ) -> Uuid {
+        let link = RelayLink {
+            connection_id: Uuid::new_v4(),
+            sender,
+        };
+        let connection_id = link.connection_id;
+
+        let config = self.config();
+        let mut rooms = self.rooms.lock().await;
+        let room = rooms
+            .entry(room_id.to_string())
+            .or_insert_with(|| RelayRoom {
+                links: HashMap::new(),
+                bytes_relayed: 0,
+                bandwidth: (config.relay_max_bytes_per_sec > 0).then(|| {
+                    let rate = config.relay_max_bytes_per_sec as f64;
+                    TokenBucket::new(rate, rate)
+                }),
+            });
+
+        if let Some(previous) = room.links.insert(ticket, link.clone()) {
+            previous.close(CLOSE_NORMAL, "Relay replaced by a new connection");
+        }
+        if let Some(partner) = room.links.get(&ticket.reversed()) {
+            send_connected(partner, ticket.peer_id);
+            send_connected(&link, ticket.partner_id);
+        }
+
+        tracing::info!(room_id = %room_id, peer_id = %ticket.peer_id, "Relay connected");
+        connection_id
+    }
+
+    /// Pass a frame on to the partner, waiting as long as the room's bandwidth cap asks.
+    /// Frames sent before the partner connects are dropped. Once the room's byte cap
+    /// is reached, both ends are disconnected and `false` is returned.
+    pub async fn forward(&self, room_id: &str, ticket: RelayTicket, message: Message) -> bool {
+        let len = message.as_bytes().len() as u64;
+
+        let (partner, wait_secs) = {
+            let mut rooms = self.rooms.lock().await;
+            let Some(room) = rooms.get_mut(room_id) else {
+                return false;
+            };
+            let Some(partner) = room.links.get(&ticket.reversed()).cloned() else {
+                return true;
+            };
+
+            let max_bytes = self.config().relay_max_bytes;
+            if max_bytes > 0 && room.bytes_relayed + len > max_bytes {
+                tracing::warn!(room_id = %room_id, max_bytes, "Relay byte limit reached");
+                for link in room.links.values() {
+                    link.close(CLOSE_POLICY_VIOLATION, "Relay byte limit reached");
+                }
+                return false;
+            }
+            room.bytes_relayed += len;
+
+            let wait_secs = room
+                .bandwidth
+                .as_mut()
+                .map_or(0.0, |bucket| bucket.reserve(len as f64));
+            (partner, wait_secs)
+        };
+
+        if wait_secs > 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(wait_secs)).await;
+        }
+
+        // A full partner queue holds this end back instead of buffering without bound
+        if partner.sender.send(message).await.is_ok() {
+            metrics::RELAY_BYTES.fetch_add(len, Ordering::Relaxed);
+        }
+        true
+    }
+
+    /// Unregister a relay socket, unless it has been replaced already.
+    pub async fn detach(&self, room_id: &str, ticket: RelayTicket, connection_id: Uuid) {
+        let mut rooms = self.rooms.lock().await;
+        if let Some(room) = rooms.get_mut(room_id)
+            && room
+                .links
+                .get(&ticket)
+                .is_some_and(|link| link.connection_id == connection_id)
+        {
+            room.links.remove(&ticket);
+            tracing::info!(room_id = %room_id, peer_id = %ticket.peer_id, "Relay disconnected");
+        }
+    }
+
+    /// Close relays to or from peers that left their room, forget rooms that are gone
+    /// and expired tokens (called periodically).
+    pub async fn prune(&self, room_service: &RoomService) {
+        let now = unix_time();
+        self.redeemed_tokens
+            .lock()
+            .await
+            .retain(|_, expiration| *expiration >= now);
+
+        let room_ids: Vec<String> = self.rooms.lock().await.keys().cloned().collect();
+
+        for room_id in room_ids {
+            if room_service.get_room(&room_id).await.is_none() {
+                if let Some(room) = self.rooms.lock().await.remove(&room_id) {
+                    for link in room.links.values() {
+                        link.close(CLOSE_NORMAL, "Room closed");
+                    }
+                }
+                continue;
+            }
+
+            let peer_ids = room_service.peer_ids(&room_id).await;
+            if let Some(room) = self.rooms.lock().await.get_mut(&room_id) {
+                room.links.retain(|ticket, link| {
+                    let in_room =
+                        peer_ids.contains(&ticket.peer_id) && peer_ids.contains(&ticket.partner_id);
+                    if !in_room {
+                        link.close(CLOSE_NORMAL, "Left the room");
+                    }
+                    in_room
+                });
+            }
+        }
+    }
+
+    fn sign(&self, room_id: &str, claims: &str) -> HmacSha1 {
+        let secret = self
+            .config()
+            .relay_secret
+            .clone()
+            .unwrap_or_else(|| self.fallback_secret.clone());
+        let mut mac = HmacSha1::new_from_slice(secret.expose().as_bytes())
+            .expect("HMAC can take key of any size");
+        mac.update(room_id.as_bytes());
+        mac.update(b":");
+        mac.update(claims.as_bytes());
+        mac
+    }
+}
+
+impl RelayTicket {
+    /// The partner's end of the same relay
+    fn reversed(self) -> Self {
+        Self {
+            peer_id: self.partner_id,
+            partner_id: self.peer_id,
+        }
+    }
+}
+
+fn send_connected(link: &RelayLink, peer_id: Uuid) {
+    let msg = ServerMessage::RelayConnected { peer_id };
+    if let Ok(text) = serde_json::to_string(&msg) {
+        let _ = link.sender.try_send(Message::text(text));
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CommandLine;
+
+    const ROOM: &str = "hot-espresso-42";
+
+    fn relay(args: &[&str]) -> RelayService {
+        let cli = CommandLine::parse(args.iter().map(|a| a.to_string())).unwrap();
+        RelayService::new(ConfigHandle::new(Config::load(&cli).unwrap(), cli))
+    }
+
+    fn token(relay: &RelayService, peer_id: Uuid, partner_id: Uuid) -> String {
+        match relay.relay_ready(ROOM, peer_id, partner_id) {
+            ServerMessage::RelayReady { token, .. } => token,
+            other => panic!("expected RelayReady, got {other:?}"),
+        }
+    }
+
+    fn is_close(message: Option<Message>) -> bool {
+        message.is_some_and(|message| message.is_close())
+    }
+
+    #[tokio::test]
+    async fn tokens_connect_once_to_their_own_room() {
+        let relay = relay(&["--relay-enabled=true"]);
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let first = token(&relay, a, b);
+        assert!(matches!(
+            relay.redeem_token("other-room", &first).await,
+            Err(AppError::InvalidRelayToken)
+        ));
+        let ticket = relay.redeem_token(ROOM, &first).await.unwrap();
+        assert_eq!(
+            ticket,
+            RelayTicket {
+                peer_id: a,
+                partner_id: b
+            }
+        );
+        assert!(matches!(
+            relay.redeem_token(ROOM, &first).await,
+            Err(AppError::InvalidRelayToken)
+        ));
+
+        // A new token works, and its claims cannot be changed under the signature
+        let second = token(&relay, a, b);
+        let forged = second.replacen(&a.to_string(), &Uuid::new_v4().to_string(), 1);
+        assert!(relay.redeem_token(ROOM, &forged).await.is_err());
+        assert!(relay.redeem_token(ROOM, &second).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn tokens_are_refused_while_relay_is_disabled() {
+        let relay = relay(&["--relay-enabled=false"]);
+        let token = token(&relay, Uuid::new_v4(), Uuid::new_v4());
+        assert!(matches!(
+            relay.redeem_token(ROOM, &token).await,
+            Err(AppError::RelayDisabled)
+        ));
+    }
+
+    #[tokio::test]
+    async fn byte_cap_closes_both_ends() {
+        let relay = relay(&[
+            "--relay-enabled=true",
+            "--relay-max-bytes=10",
+            "--relay-max-bytes-per-sec=0",
+        ]);
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let ticket = RelayTicket {
+            peer_id: a,
+            partner_id: b,
+        };
+        let (a_tx, mut a_rx) = mpsc::channel(8);
+        let (b_tx, mut b_rx) = mpsc::channel(8);
+
+        // Nothing reaches a partner that is not connected yet, nor counts against the cap
+        relay.attach(ROOM, ticket, a_tx).await;
+        assert!(
+            relay
+                .forward(ROOM, ticket, Message::binary(vec![0; 8]))
+                .await
+        );
+        relay.attach(ROOM, ticket.reversed(), b_tx).await;
+        assert!(a_rx.recv().await.unwrap().is_text(), "RelayConnected");
+        assert!(b_rx.recv().await.unwrap().is_text(), "RelayConnected");
+
+        assert!(
+            relay
+                .forward(ROOM, ticket, Message::binary(vec![1; 6]))
+                .await
+        );
+        assert_eq!(b_rx.recv().await.unwrap().as_bytes(), [1; 6]);
+
+        assert!(
+            !relay
+                .forward(ROOM, ticket.reversed(), Message::binary(vec![2; 6]))
+                .await
+        );
+        assert!(is_close(a_rx.recv().await));
+        assert!(is_close(b_rx.recv().await));
+    }
+}
@@ -1,31 +1,114 @@
-use crate::config::Config;
+use crate::config::{Config, ConfigHandle};
 use crate::error::AppError;
-use crate::models::{Peer, PeerSender, Room};
-use crate::slug_generator::generate_slug;
+use crate::ice::{self, IceServer};
+use crate::metrics;
+use crate::models::room::constant_time_eq;
+use crate::models::{Mailbox, Peer, PeerSender, Room, ServerMessage};
+use crate::services::PasswordGuard;
+use crate::slug_generator::{SlugGenerator, generate_token};
 use crate::store::RoomStore;
 use argon2::password_hash::SaltString;
 use argon2::{Argon2, PasswordHasher};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use std::net::IpAddr;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::sync::{oneshot, watch};
 use uuid::Uuid;
 
+/// Longest display name passed on to the room owner, in characters
+const MAX_DISPLAY_NAME_CHARS: usize = 32;
+
+/// Close code for a connection whose session was resumed on another one (application range)
+const CLOSE_SESSION_REPLACED: u16 = 4000;
+
+/// Who holds a peer's session, judged by the resume token a connection was given
+pub enum SessionState {
+    /// The token is still current: nobody resumed the session
+    Current,
+    /// Another connection resumed the session with this token
+    Replaced,
+    /// The session was revoked (kicked, room closed or peer gone)
+    Revoked,
+}
+
+/// A session taken over by a new connection
+pub struct ResumedSession {
+    /// Token for the next reconnect
+    pub resume_token: String,
+    /// The peer's signals; `Mailbox::reattach` resends those the client missed
+    pub mailbox: Arc<Mailbox>,
+}
+
+/// Result of a join attempt that passed the room's checks
+pub enum JoinOutcome {
+    Joined {
+        peer_id: Uuid,
+        peer_count: usize,
+    },
+    /// The room requires approval. `decision` resolves with the owner's answer,
+    /// or errors if the request is dropped unanswered.
+    Pending {
+        request_id: Uuid,
+        display_name: Option<String>,
+        fingerprint: String,
+        decision: oneshot::Receiver<bool>,
+    },
+}
+
 /// Service for room management operations.
 /// Contains ALL business logic related to rooms.
 pub struct RoomService {
     store: Arc<dyn RoomStore + Send + Sync>,
-    config: Arc<Config>,
+    config: ConfigHandle,
+    password_guard: PasswordGuard,
+    slug_generator: SlugGenerator,
+    /// Keys join fingerprints; random per process, so they cannot be reversed offline
+    fingerprint_key: String,
+    /// Set once this node starts shutting down
+    draining: watch::Sender<bool>,
 }
 
 impl RoomService {
-    pub fn new(store: Arc<dyn RoomStore + Send + Sync>, config: Arc<Config>) -> Self {
-        Self { store, config }
+    pub fn new(
+        store: Arc<dyn RoomStore + Send + Sync>,
+        config: ConfigHandle,
+        slug_generator: SlugGenerator,
+    ) -> Self {
+        Self {
+            store,
+            slug_generator,
+            password_guard: PasswordGuard::new(config.clone()),
+            fingerprint_key: generate_token(),
+            config,
+            draining: watch::Sender::new(false),
+        }
+    }
+
+    /// The configuration as of now; it may be reloaded between calls.
+    fn config(&self) -> Arc<Config> {
+        self.config.current()
     }
 
-    /// Create a new room with an optional password.
-    /// Returns the room ID on success.
-    pub async fn create_room(&self, password: Option<String>) -> Result<String, AppError> {
-        // Generate unique room ID
-        let room_id = self.generate_unique_room_id().await?;
+    /// Create a new room with an optional password, named `room_name` or a generated slug.
+    /// Returns the room on success, carrying its share token when those are enabled.
+    pub async fn create_room(
+        &self,
+        password: Option<String>,
+        room_name: Option<String>,
+        require_approval: Option<bool>,
+    ) -> Result<Room, AppError> {
+        if self.is_draining() {
+            return Err(AppError::ServerShuttingDown {
+                retry_after_secs: self.config().shutdown_reconnect_after_secs,
+            });
+        }
+
+        let custom_name = room_name
+            .map(|name| self.validate_room_name(&name))
+            .transpose()?;
 
         // Hash password if provided
         let password_hash = match password {
@@ -35,27 +118,92 @@ impl RoomService {
 
         let has_password = password_hash.is_some();
 
-        let room = Room::new(room_id.clone(), password_hash);
-        self.store.insert(room).await;
+        let config = self.config();
+        let share_token = config.room_share_tokens.then(generate_token);
+        let owner_token = generate_token();
+        let owner_peer_id = Uuid::new_v4();
+        let require_approval = require_approval.unwrap_or(config.room_require_approval);
+        let build = |room_id: String| {
+            Room::new(
+                room_id,
+                password_hash.clone(),
+                share_token.clone(),
+                Some(owner_token.clone()),
+                Some(owner_peer_id),
+                require_approval,
+            )
+        };
+
+        let room = match custom_name {
+            Some(name) => {
+                let room = build(name.clone());
+                if !self.store.insert(room.clone()).await {
+                    return Err(AppError::RoomNameTaken(name));
+                }
+                room
+            }
+            None => self.insert_with_generated_id(build).await?,
+        };
 
-        tracing::info!(room_id = %room_id, has_password, "Room created");
+        metrics::ROOMS_CREATED.fetch_add(1, Ordering::Relaxed);
+        tracing::info!(room_id = %room.id(), has_password, require_approval, "Room created");
 
-        Ok(room_id)
+        Ok(room)
     }
 
-    /// Generate a unique slug, falling back to UUID if needed.
-    async fn generate_unique_room_id(&self) -> Result<String, AppError> {
-        for _ in 0..self.config.slug_max_attempts {
-            let candidate = generate_slug();
-            if self.store.get(&candidate).await.is_none() {
-                return Ok(candidate);
+    /// Insert a room under a generated slug, drawing a new one while the slug is taken
+    /// (possibly by a room created concurrently), falling back to UUID if needed.
+    async fn insert_with_generated_id(
+        &self,
+        build: impl Fn(String) -> Room,
+    ) -> Result<Room, AppError> {
+        for _ in 0..self.config().slug_max_attempts {
+            let room = build(self.slug_generator.generate());
+            if self.store.insert(room.clone()).await {
+                return Ok(room);
             }
         }
 
         // Fallback to UUID (virtually no collision risk)
         let uuid = Uuid::new_v4().to_string();
         tracing::warn!("Slug collision limit reached, using UUID: {}", uuid);
-        Ok(uuid)
+        let room = build(uuid);
+        if self.store.insert(room.clone()).await {
+            Ok(room)
+        } else {
+            Err(AppError::InternalError("Failed to store room".to_string()))
+        }
+    }
+
+    /// Check a custom room name against the naming policy. Returns the normalized name.
+    fn validate_room_name(&self, name: &str) -> Result<String, AppError> {
+        let name = name.trim().to_ascii_lowercase();
+        let config = self.config();
+        let (min_len, max_len) = (config.room_name_min_len, config.room_name_max_len);
+
+        if name.len() < min_len || name.len() > max_len {
+            return Err(AppError::InvalidRoomName(format!(
+                "must be {min_len} to {max_len} characters long"
+            )));
+        }
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            || name.starts_with('-')
+            || name.ends_with('-')
+            || name.contains("--")
+        {
+            return Err(AppError::InvalidRoomName(
+                "use letters, digits and single hyphens between them".to_string(),
+            ));
+        }
+        if config.room_name_reserved.contains(&name) || self.slug_generator.is_blocked(&name) {
+            return Err(AppError::InvalidRoomName(format!(
+                "{name} is not available"
+            )));
+        }
+
+        Ok(name)
     }
 
     /// Hash a password using Argon2.
@@ -83,79 +231,416 @@ impl RoomService {
             .is_ok()
     }
 
-    /// Join an existing room. Validates password and room capacity.
+    /// Join an existing room. Validates share token, password and room capacity.
+    /// Wrong passwords are throttled per room and per `client_ip`.
+    /// In rooms requiring approval the join is held as a request for the owner instead.
     pub async fn join_room(
         &self,
         room_id: &str,
+        share_token: Option<&str>,
         password: Option<String>,
+        display_name: Option<String>,
         peer_sender: PeerSender,
-    ) -> Result<(Uuid, usize), AppError> {
+        client_ip: IpAddr,
+    ) -> Result<JoinOutcome, AppError> {
         let room = self
             .store
             .get(room_id)
             .await
+            .filter(|room| room.accepts_share_token(share_token))
             .ok_or_else(|| AppError::RoomNotFound(room_id.to_string()))?;
 
+        if room.is_locked() {
+            return Err(AppError::RoomLocked);
+        }
+
         // Validate password if the room has one
-        if let Some(hash) = room.password_hash() {
+        if let Some(hash) = room.password_hash().await {
+            if let Err(wait) = self.password_guard.check(room_id, client_ip).await {
+                return Err(AppError::PasswordAttemptsExceeded {
+                    retry_after_secs: wait.as_secs_f64().ceil() as u64,
+                });
+            }
+
             let provided = password.unwrap_or_default();
-            if !self.verify_password(&provided, hash) {
-                tracing::warn!(room_id = %room_id, "Invalid password attempt");
-                return Err(AppError::InvalidPassword);
+            if !self.verify_password(&provided, &hash) {
+                let failed_attempts = self.password_guard.record_failure(room_id, client_ip).await;
+                metrics::PASSWORD_FAILURES.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!(room_id = %room_id, client_ip = %client_ip, failed_attempts, "Invalid password attempt");
+                return Err(AppError::InvalidPassword { failed_attempts });
             }
+
+            self.password_guard.record_success(room_id, client_ip).await;
         }
 
-        // Check room capacity
-        let mut peers = room.peers().write().await;
-        if peers.len() >= self.config.room_max_peers {
-            tracing::warn!(room_id = %room_id, "Room capacity exceeded");
-            return Err(AppError::RoomCapacityExceeded);
+        if room.requires_approval() {
+            let request_id = Uuid::new_v4();
+            let decision = room
+                .add_join_request(request_id, self.config().join_request_max_pending)
+                .await
+                .ok_or(AppError::TooManyJoinRequests)?;
+
+            tracing::info!(room_id = %room_id, request_id = %request_id, "Join request waiting for approval");
+            return Ok(JoinOutcome::Pending {
+                request_id,
+                display_name: clean_display_name(display_name),
+                fingerprint: join_fingerprint(&self.fingerprint_key, room_id, client_ip),
+                decision,
+            });
+        }
+
+        let peer = self.new_peer(Uuid::new_v4(), peer_sender, 1);
+        let (peer_id, peer_count) = self.add_peer(&room, peer).await?;
+        Ok(JoinOutcome::Joined {
+            peer_id,
+            peer_count,
+        })
+    }
+
+    /// Add the creator to its new room, under the room's owner peer id.
+    /// If that fails the room is removed again, unless someone else got in first.
+    pub async fn join_as_owner(
+        &self,
+        room: &Room,
+        peer_sender: PeerSender,
+    ) -> Result<(Uuid, usize), AppError> {
+        let peer_id = room.owner_peer_id().unwrap_or_else(Uuid::new_v4);
+        let joined = self
+            .add_peer(room, self.new_peer(peer_id, peer_sender, 1))
+            .await;
+        if joined.is_err() && self.store.peer_ids(room.id()).await.is_empty() {
+            self.store.remove(room.id()).await;
+            tracing::warn!(room_id = %room.id(), "Room removed, its owner could not join");
+        }
+        joined
+    }
+
+    /// Admit a joiner whose request the owner approved. The request id becomes its peer id.
+    /// A joiner whose connection closed while the answer was on its way is not admitted.
+    pub async fn admit_join_request(
+        &self,
+        room_id: &str,
+        request_id: Uuid,
+        peer_sender: PeerSender,
+    ) -> Result<(Uuid, usize), AppError> {
+        if peer_sender.is_closed() {
+            return Err(AppError::PeerNotFound(request_id));
+        }
+        let room = self
+            .store
+            .get(room_id)
+            .await
+            .ok_or_else(|| AppError::RoomNotFound(room_id.to_string()))?;
+        self.add_peer(&room, self.new_peer(request_id, peer_sender, 1))
+            .await
+    }
+
+    /// Drop a join request held on this node without an answer.
+    /// Returns false if it was already answered or dropped.
+    pub async fn cancel_join_request(&self, room_id: &str, request_id: Uuid) -> bool {
+        match self.store.get_local(room_id).await {
+            Some(room) => room.cancel_join_request(request_id).await,
+            None => false,
         }
+    }
+
+    /// How long a join request may wait for the owner's answer.
+    pub fn join_request_timeout(&self) -> Duration {
+        self.config().join_request_timeout()
+    }
 
-        // Add peer
-        let peer = Peer::new(peer_sender);
+    /// Create a peer whose signals are numbered from `first_seq`.
+    fn new_peer(&self, peer_id: Uuid, peer_sender: PeerSender, first_seq: u64) -> Peer {
+        let config = self.config();
+        let mailbox = Mailbox::new(
+            peer_sender.clone(),
+            config.signal_mailbox_size,
+            config.signal_mailbox_retention(),
+            first_seq,
+        );
+        Peer::new(peer_id, peer_sender, Arc::new(mailbox))
+    }
+
+    /// Add a peer to a room, checking room capacity.
+    async fn add_peer(&self, room: &Room, peer: Peer) -> Result<(Uuid, usize), AppError> {
+        let room_id = room.id();
         let peer_id = peer.id;
-        peers.insert(peer_id, peer);
-        let peer_count = peers.len();
-        drop(peers);
+        let Some(peer_count) = self
+            .store
+            .try_add_peer(room_id, peer, self.config().room_max_peers)
+            .await
+        else {
+            metrics::CAPACITY_REJECTIONS.fetch_add(1, Ordering::Relaxed);
+            tracing::warn!(room_id = %room_id, "Room capacity exceeded");
+            return Err(AppError::RoomCapacityExceeded);
+        };
 
         // Update last activity
-        *room.last_activity().write().await = Instant::now();
+        self.store.touch(room_id).await;
+
+        if peer_count > 1 {
+            metrics::ROOMS_JOINED.fetch_add(1, Ordering::Relaxed);
+        }
+        if peer_count == 2 {
+            metrics::TIME_TO_SECOND_PEER.observe(room.created_at().elapsed());
+        }
 
         tracing::info!(room_id = %room_id, peer_id = %peer_id, peer_count, "Peer joined room");
 
         Ok((peer_id, peer_count))
     }
 
-    /// Remove a peer from a room. Returns true if the room was deleted (empty).
-    pub async fn leave_room(&self, room_id: &str, peer_id: Uuid) -> Result<bool, AppError> {
-        let room = match self.store.get(room_id).await {
-            Some(r) => r,
-            None => return Ok(false), // Room already gone
+    /// Hand out a new resume token for a peer, replacing any previous one.
+    pub async fn issue_resume_token(&self, room_id: &str, peer_id: Uuid) -> String {
+        let token = generate_token();
+        self.store
+            .set_resume_token(room_id, peer_id, Some(token.clone()))
+            .await;
+        token
+    }
+
+    /// Bind a new connection to a peer still holding its place in the room.
+    /// `last_seq` is the last signal the client received, to continue numbering from
+    /// when the peer's mailbox stayed on another node.
+    pub async fn resume_session(
+        &self,
+        room_id: &str,
+        peer_id: Uuid,
+        resume_token: &str,
+        peer_sender: PeerSender,
+        last_seq: Option<u64>,
+    ) -> Result<ResumedSession, AppError> {
+        let valid = self
+            .store
+            .resume_token(room_id, peer_id)
+            .await
+            .is_some_and(|expected| constant_time_eq(expected.as_bytes(), resume_token.as_bytes()));
+        if !valid || !self.has_peer(room_id, peer_id).await {
+            tracing::info!(room_id = %room_id, peer_id = %peer_id, "Session resume refused");
+            return Err(AppError::SessionExpired);
+        }
+
+        // Keep the mailbox if the peer was connected to this node
+        let local_mailbox = match self.store.get_local(room_id).await {
+            Some(room) => room
+                .peers()
+                .read()
+                .await
+                .get(&peer_id)
+                .map(|peer| peer.mailbox.clone()),
+            None => None,
         };
+        let peer = match local_mailbox {
+            Some(mailbox) => Peer::new(peer_id, peer_sender, mailbox),
+            None => self.new_peer(peer_id, peer_sender, last_seq.map_or(1, |seq| seq + 1)),
+        };
+        let mailbox = peer.mailbox.clone();
+
+        if let Some(previous) = self.store.rebind_peer(room_id, peer).await {
+            // The old connection may not have noticed it is dead yet
+            previous.sender.close(
+                CLOSE_SESSION_REPLACED,
+                "Session resumed on another connection",
+            );
+        }
+        self.store.touch(room_id).await;
+
+        tracing::info!(room_id = %room_id, peer_id = %peer_id, "Session resumed");
+        Ok(ResumedSession {
+            resume_token: self.issue_resume_token(room_id, peer_id).await,
+            mailbox,
+        })
+    }
+
+    /// Check whether the session a connection was given is still its own.
+    pub async fn session_state(
+        &self,
+        room_id: &str,
+        peer_id: Uuid,
+        resume_token: &str,
+    ) -> SessionState {
+        match self.store.resume_token(room_id, peer_id).await {
+            Some(current) if constant_time_eq(current.as_bytes(), resume_token.as_bytes()) => {
+                SessionState::Current
+            }
+            Some(_) => SessionState::Replaced,
+            None => SessionState::Revoked,
+        }
+    }
+
+    /// How long a dropped peer keeps its place in the room.
+    pub fn session_grace(&self) -> Duration {
+        self.config().session_grace()
+    }
 
-        let mut peers = room.peers().write().await;
-        peers.remove(&peer_id);
-        let is_empty = peers.is_empty();
-        let remaining_count = peers.len();
-        drop(peers);
+    /// Remove a peer from a room. Returns the number of peers left (0 means the room was deleted).
+    /// The room is only deleted once the store confirms it is empty.
+    pub async fn leave_room(&self, room_id: &str, peer_id: Uuid) -> Result<usize, AppError> {
+        let remaining_count = self
+            .store
+            .remove_peer(room_id, peer_id)
+            .await
+            .ok_or_else(|| AppError::InternalError("Room store unavailable".to_string()))?;
+        self.store.set_resume_token(room_id, peer_id, None).await;
 
-        if is_empty {
+        if remaining_count == 0 {
+            self.observe_room_lifetime(room_id).await;
             self.store.remove(room_id).await;
             tracing::info!(room_id = %room_id, "Room deleted (empty)");
         } else {
             tracing::info!(room_id = %room_id, peer_id = %peer_id, remaining = remaining_count, "Peer left room");
         }
 
-        Ok(is_empty)
+        Ok(remaining_count)
+    }
+
+    /// Whether the peers of a room are told about wrong password attempts.
+    pub fn notify_password_failures(&self) -> bool {
+        self.config().password_notify_creator
+    }
+
+    /// Forget password failures whose backoff or lockout has long expired.
+    pub async fn prune_password_failures(&self) {
+        self.password_guard.prune().await;
+    }
+
+    /// Get the ids of the peers in a room, on any node.
+    pub async fn peer_ids(&self, room_id: &str) -> Vec<Uuid> {
+        self.store.peer_ids(room_id).await
+    }
+
+    /// Get the ids of the peers in a room, excluding `peer_id`.
+    pub async fn other_peer_ids(&self, room_id: &str, peer_id: Uuid) -> Vec<Uuid> {
+        let mut peer_ids = self.store.peer_ids(room_id).await;
+        peer_ids.retain(|id| *id != peer_id);
+        peer_ids
+    }
+
+    /// Check that a peer is in a room, on any node.
+    pub async fn has_peer(&self, room_id: &str, peer_id: Uuid) -> bool {
+        self.store.peer_ids(room_id).await.contains(&peer_id)
+    }
+
+    /// Check that a peer is in a room, asking shared storage only when the peer is
+    /// not connected to this node. For hot paths such as relaying signals.
+    pub async fn has_peer_cached(&self, room_id: &str, peer_id: Uuid) -> bool {
+        if let Some(room) = self.store.get_local(room_id).await
+            && room.peers().read().await.contains_key(&peer_id)
+        {
+            return true;
+        }
+        self.has_peer(room_id, peer_id).await
+    }
+
+    /// ICE servers for a peer of the room, with TURN credentials bound to it.
+    pub fn ice_servers(&self, room_id: &str, peer_id: Uuid) -> Vec<IceServer> {
+        ice::get_ice_servers(&self.config(), room_id, peer_id).ice_servers
     }
 
     /// Get room info for existence check.
-    pub async fn get_room_info(&self, room_id: &str) -> Option<(bool, bool)> {
-        self.store
+    /// Rooms with a share token are only reported when the matching token is given.
+    pub async fn get_room_info(
+        &self,
+        room_id: &str,
+        share_token: Option<&str>,
+    ) -> Option<(bool, bool)> {
+        let room = self
+            .store
+            .get(room_id)
+            .await
+            .filter(|room| room.accepts_share_token(share_token))?;
+        Some((true, room.has_password().await))
+    }
+
+    /// Get a room for a host control, checking the owner token.
+    async fn owned_room(&self, room_id: &str, owner_token: &str) -> Result<Room, AppError> {
+        let room = self
+            .store
             .get(room_id)
             .await
-            .map(|room| (true, room.has_password()))
+            .ok_or_else(|| AppError::RoomNotFound(room_id.to_string()))?;
+
+        if !room.is_owner_token(owner_token) {
+            tracing::warn!(room_id = %room_id, "Host control with invalid owner token");
+            return Err(AppError::NotRoomOwner);
+        }
+        Ok(room)
+    }
+
+    /// Authorize the owner kicking a peer. The peer is removed when the node holding its
+    /// connection delivers `SignalingService::notify_kicked` and closes it.
+    pub async fn kick_peer(
+        &self,
+        room_id: &str,
+        owner_token: &str,
+        peer_id: Uuid,
+    ) -> Result<Room, AppError> {
+        let room = self.owned_room(room_id, owner_token).await?;
+        if !self.has_peer(room_id, peer_id).await {
+            return Err(AppError::PeerNotFound(peer_id));
+        }
+        // A kicked peer must not come back through `ResumeSession`
+        self.store.set_resume_token(room_id, peer_id, None).await;
+
+        tracing::info!(room_id = %room_id, peer_id = %peer_id, "Peer kicked by owner");
+        Ok(room)
+    }
+
+    /// Authorize the owner answering a join request. The answer reaches the node holding
+    /// the request through `SignalingService::answer_join_request`.
+    pub async fn answer_join_request(
+        &self,
+        room_id: &str,
+        owner_token: &str,
+        request_id: Uuid,
+        approve: bool,
+    ) -> Result<Room, AppError> {
+        let room = self.owned_room(room_id, owner_token).await?;
+        tracing::info!(room_id = %room_id, request_id = %request_id, approve, "Join request answered by owner");
+        Ok(room)
+    }
+
+    /// Lock or unlock the room against new joins.
+    pub async fn set_locked(
+        &self,
+        room_id: &str,
+        owner_token: &str,
+        locked: bool,
+    ) -> Result<Room, AppError> {
+        let room = self.owned_room(room_id, owner_token).await?;
+        self.store.set_locked(room_id, locked).await;
+        tracing::info!(room_id = %room_id, locked, "Room lock changed by owner");
+        Ok(room)
+    }
+
+    /// Change or remove (with `None` or an empty password) the room password.
+    /// Returns whether the room now has a password.
+    pub async fn set_password(
+        &self,
+        room_id: &str,
+        owner_token: &str,
+        password: Option<String>,
+    ) -> Result<(Room, bool), AppError> {
+        let room = self.owned_room(room_id, owner_token).await?;
+        let password_hash = match password {
+            Some(pwd) if !pwd.is_empty() => Some(self.hash_password(&pwd)?),
+            _ => None,
+        };
+        let has_password = password_hash.is_some();
+
+        self.store.set_password_hash(room_id, password_hash).await;
+        tracing::info!(room_id = %room_id, has_password, "Room password changed by owner");
+        Ok((room, has_password))
+    }
+
+    /// Close the room for everyone. It is locked right away and removed once
+    /// the last peer has disconnected.
+    pub async fn close_room(&self, room_id: &str, owner_token: &str) -> Result<Room, AppError> {
+        let room = self.owned_room(room_id, owner_token).await?;
+        self.store.set_locked(room_id, true).await;
+        self.store.clear_resume_tokens(room_id).await;
+        tracing::info!(room_id = %room_id, "Room closed by owner");
+        Ok(room)
     }
 
     /// Clean up stale rooms (called periodically).
@@ -163,13 +648,485 @@ impl RoomService {
         let stale_room_ids = self.store.get_stale_room_ids(max_age).await;
 
         for room_id in stale_room_ids {
+            self.observe_room_lifetime(&room_id).await;
             self.store.remove(&room_id).await;
+            metrics::ROOMS_EXPIRED.fetch_add(1, Ordering::Relaxed);
             tracing::info!(room_id = %room_id, "Stale room cleaned up");
         }
     }
 
+    /// Record the lifetime of a room about to be removed, if this node has it.
+    async fn observe_room_lifetime(&self, room_id: &str) {
+        if let Some(room) = self.store.get_local(room_id).await {
+            metrics::ROOM_LIFETIME.observe(room.created_at().elapsed());
+        }
+    }
+
+    /// Stop creating rooms and tell every connection on this node to reconnect elsewhere.
+    /// Rooms already open keep working until their peers leave.
+    pub fn start_draining(&self) {
+        if !self.draining.send_replace(true) {
+            tracing::info!("Draining connections for shutdown");
+        }
+    }
+
+    pub fn is_draining(&self) -> bool {
+        *self.draining.borrow()
+    }
+
+    /// Watch for the start of draining, for connections to send `ServerShutdown`.
+    pub fn draining(&self) -> watch::Receiver<bool> {
+        self.draining.subscribe()
+    }
+
+    /// `ServerShutdown` telling a client when to reconnect.
+    pub fn server_shutdown(&self) -> ServerMessage {
+        ServerMessage::ServerShutdown {
+            reconnect_after: self.config().shutdown_reconnect_after_secs,
+        }
+    }
+
+    /// Number of open rooms, for metrics.
+    pub async fn room_count(&self) -> usize {
+        self.store.count().await
+    }
+
     /// Get room for signaling operations.
     pub async fn get_room(&self, room_id: &str) -> Option<Room> {
         self.store.get(room_id).await
     }
+
+    /// Get the room's handle on this node, for delivering messages to local peers.
+    pub async fn get_local_room(&self, room_id: &str) -> Option<Room> {
+        self.store.get_local(room_id).await
+    }
+}
+
+/// Trim a joiner's display name to something safe to show the owner.
+fn clean_display_name(display_name: Option<String>) -> Option<String> {
+    let name: String = display_name?
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_DISPLAY_NAME_CHARS)
+        .collect();
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Short code derived from the joiner's address, e.g. "3f2a-91c0".
+/// An HMAC under `key`, so the address cannot be found by hashing candidates, and
+/// scoped to the room, so it cannot link a joiner across rooms.
+fn join_fingerprint(key: &str, room_id: &str, client_ip: IpAddr) -> String {
+    let mut mac =
+        Hmac::<Sha1>::new_from_slice(key.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(format!("{room_id}:{client_ip}").as_bytes());
+    let digest = mac.finalize().into_bytes();
+    format!(
+        "{:02x}{:02x}-{:02x}{:02x}",
+        digest[0], digest[1], digest[2], digest[3]
+    )
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::{CommandLine, OverflowPolicy};
+    use crate::models::peer_channel;
+    use crate::store::InMemoryRoomStore;
+    use async_trait::async_trait;
+
+    const CLIENT_IP: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(192, 0, 2, 1));
+
+    pub fn service(store: Arc<dyn RoomStore + Send + Sync>) -> RoomService {
+        let cli = CommandLine::parse(Vec::<String>::new()).unwrap();
+        let config = Config::load(&cli).unwrap();
+        let slug_generator = SlugGenerator::from_config(&config).unwrap();
+        RoomService::new(store, ConfigHandle::new(config, cli), slug_generator)
+    }
+
+    /// Sender whose receiver is gone, for peers whose messages nobody reads.
+    /// Its queue is closed, like that of a connection that went away.
+    pub fn sender() -> PeerSender {
+        peer_channel(8, OverflowPolicy::Disconnect).0
+    }
+
+    /// Create a room and join it as its owner. Returns the room and the owner's peer id.
+    async fn owned_room(rooms: &RoomService) -> (Room, Uuid) {
+        let room = rooms.create_room(None, None, Some(false)).await.unwrap();
+        let (owner_id, _) = rooms.join_as_owner(&room, sender()).await.unwrap();
+        (room, owner_id)
+    }
+
+    async fn join(
+        rooms: &RoomService,
+        room: &Room,
+        password: Option<&str>,
+    ) -> Result<Uuid, AppError> {
+        let outcome = rooms
+            .join_room(
+                room.id(),
+                room.share_token(),
+                password.map(str::to_string),
+                None,
+                sender(),
+                CLIENT_IP,
+            )
+            .await?;
+        match outcome {
+            JoinOutcome::Joined { peer_id, .. } => Ok(peer_id),
+            JoinOutcome::Pending { .. } => panic!("join held for approval"),
+        }
+    }
+
+    /// Memory store in which every room is full
+    struct FullStore(InMemoryRoomStore);
+
+    #[async_trait]
+    impl RoomStore for FullStore {
+        async fn insert(&self, room: Room) -> bool {
+            self.0.insert(room).await
+        }
+        async fn get(&self, id: &str) -> Option<Room> {
+            self.0.get(id).await
+        }
+        async fn remove(&self, id: &str) {
+            self.0.remove(id).await
+        }
+        async fn try_add_peer(&self, _id: &str, _peer: Peer, _max_peers: usize) -> Option<usize> {
+            None
+        }
+        async fn remove_peer(&self, id: &str, peer_id: Uuid) -> Option<usize> {
+            self.0.remove_peer(id, peer_id).await
+        }
+        async fn rebind_peer(&self, id: &str, peer: Peer) -> Option<Peer> {
+            self.0.rebind_peer(id, peer).await
+        }
+        async fn peer_ids(&self, id: &str) -> Vec<Uuid> {
+            self.0.peer_ids(id).await
+        }
+        async fn set_password_hash(&self, id: &str, password_hash: Option<String>) {
+            self.0.set_password_hash(id, password_hash).await
+        }
+        async fn set_locked(&self, id: &str, locked: bool) {
+            self.0.set_locked(id, locked).await
+        }
+        async fn set_resume_token(&self, id: &str, peer_id: Uuid, token: Option<String>) {
+            self.0.set_resume_token(id, peer_id, token).await
+        }
+        async fn resume_token(&self, id: &str, peer_id: Uuid) -> Option<String> {
+            self.0.resume_token(id, peer_id).await
+        }
+        async fn clear_resume_tokens(&self, id: &str) {
+            self.0.clear_resume_tokens(id).await
+        }
+        async fn touch(&self, id: &str) {
+            self.0.touch(id).await
+        }
+        async fn get_stale_room_ids(&self, max_age: Duration) -> Vec<String> {
+            self.0.get_stale_room_ids(max_age).await
+        }
+        async fn count(&self) -> usize {
+            self.0.count().await
+        }
+    }
+
+    #[tokio::test]
+    async fn room_is_removed_when_its_owner_cannot_join() {
+        let rooms = service(Arc::new(FullStore(InMemoryRoomStore::new())));
+        let room = rooms.create_room(None, None, None).await.unwrap();
+
+        assert!(matches!(
+            rooms.join_as_owner(&room, sender()).await,
+            Err(AppError::RoomCapacityExceeded)
+        ));
+        assert!(rooms.get_room(room.id()).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn room_is_kept_when_someone_else_got_in_first() {
+        let rooms = service(Arc::new(InMemoryRoomStore::new()));
+        let room = rooms.create_room(None, None, Some(false)).await.unwrap();
+        join(&rooms, &room, None).await.unwrap();
+        join(&rooms, &room, None).await.unwrap();
+
+        assert!(rooms.join_as_owner(&room, sender()).await.is_err());
+        assert_eq!(rooms.peer_ids(room.id()).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn host_controls_refuse_a_wrong_owner_token() {
+        let rooms = service(Arc::new(InMemoryRoomStore::new()));
+        let (room, owner_id) = owned_room(&rooms).await;
+        let room_id = room.id();
+        let guest_id = join(&rooms, &room, None).await.unwrap();
+        let wrong = "not-the-owner-token";
+
+        let refused = |result: Result<(), AppError>| matches!(result, Err(AppError::NotRoomOwner));
+        assert!(refused(
+            rooms.kick_peer(room_id, wrong, guest_id).await.map(drop)
+        ));
+        assert!(refused(
+            rooms.set_locked(room_id, wrong, true).await.map(drop)
+        ));
+        assert!(refused(
+            rooms
+                .set_password(room_id, wrong, Some("secret".into()))
+                .await
+                .map(drop)
+        ));
+        assert!(refused(rooms.close_room(room_id, wrong).await.map(drop)));
+        assert!(refused(rooms.close_room(room_id, "").await.map(drop)));
+
+        // Nothing changed
+        let stored = rooms.get_room(room_id).await.unwrap();
+        assert!(!stored.is_locked());
+        assert!(!stored.has_password().await);
+        let mut peers = rooms.peer_ids(room_id).await;
+        peers.sort();
+        let mut expected = vec![owner_id, guest_id];
+        expected.sort();
+        assert_eq!(peers, expected);
+    }
+
+    #[tokio::test]
+    async fn host_controls_apply_with_the_owner_token() {
+        let rooms = service(Arc::new(InMemoryRoomStore::new()));
+        let (room, owner_id) = owned_room(&rooms).await;
+        let (room_id, owner_token) = (room.id(), room.owner_token().unwrap());
+
+        let guest_id = join(&rooms, &room, None).await.unwrap();
+        let guest_token = rooms.issue_resume_token(room_id, guest_id).await;
+        rooms
+            .kick_peer(room_id, owner_token, guest_id)
+            .await
+            .unwrap();
+        assert!(matches!(
+            rooms.session_state(room_id, guest_id, &guest_token).await,
+            SessionState::Revoked
+        ));
+        rooms.leave_room(room_id, guest_id).await.unwrap();
+        assert!(matches!(
+            rooms.kick_peer(room_id, owner_token, guest_id).await,
+            Err(AppError::PeerNotFound(_))
+        ));
+
+        rooms.set_locked(room_id, owner_token, true).await.unwrap();
+        assert!(matches!(
+            join(&rooms, &room, None).await,
+            Err(AppError::RoomLocked)
+        ));
+        rooms.set_locked(room_id, owner_token, false).await.unwrap();
+
+        let (_, has_password) = rooms
+            .set_password(room_id, owner_token, Some("secret".into()))
+            .await
+            .unwrap();
+        assert!(has_password);
+        assert!(matches!(
+            join(&rooms, &room, None).await,
+            Err(AppError::InvalidPassword { .. })
+        ));
+
+        let owner_token_issued = rooms.issue_resume_token(room_id, owner_id).await;
+        rooms.close_room(room_id, owner_token).await.unwrap();
+        assert!(rooms.get_room(room_id).await.unwrap().is_locked());
+        assert!(matches!(
+            rooms
+                .session_state(room_id, owner_id, &owner_token_issued)
+                .await,
+            SessionState::Revoked
+        ));
+    }
+
+    #[test]
+    fn join_fingerprints_depend_on_the_key_and_the_room() {
+        let (ip, other_ip) = (CLIENT_IP, IpAddr::from([192, 0, 2, 2]));
+        let fingerprint = join_fingerprint("key", "room", ip);
+
+        assert_eq!(fingerprint.len(), 9);
+        assert_eq!(fingerprint, join_fingerprint("key", "room", ip));
+        assert_ne!(fingerprint, join_fingerprint("key", "room", other_ip));
+        assert_ne!(fingerprint, join_fingerprint("key", "other-room", ip));
+        assert_ne!(fingerprint, join_fingerprint("other-key", "room", ip));
+    }
+
+    #[tokio::test]
+    async fn resuming_replaces_the_old_connection_and_token() {
+        let rooms = service(Arc::new(InMemoryRoomStore::new()));
+        let room = rooms.create_room(None, None, Some(false)).await.unwrap();
+        let (old_tx, _old_rx) = peer_channel(8, OverflowPolicy::Disconnect);
+        let (peer_id, _) = rooms.join_as_owner(&room, old_tx.clone()).await.unwrap();
+        let room_id = room.id();
+        let old_token = rooms.issue_resume_token(room_id, peer_id).await;
+        assert!(matches!(
+            rooms.session_state(room_id, peer_id, &old_token).await,
+            SessionState::Current
+        ));
+
+        let resumed = rooms
+            .resume_session(room_id, peer_id, &old_token, sender(), None)
+            .await
+            .unwrap();
+        assert_ne!(resumed.resume_token, old_token);
+        assert!(old_tx.is_closed(), "the old connection is closed");
+        assert!(matches!(
+            rooms.session_state(room_id, peer_id, &old_token).await,
+            SessionState::Replaced
+        ));
+        assert!(matches!(
+            rooms
+                .session_state(room_id, peer_id, &resumed.resume_token)
+                .await,
+            SessionState::Current
+        ));
+
+        // Each token resumes once
+        assert!(matches!(
+            rooms
+                .resume_session(room_id, peer_id, &old_token, sender(), None)
+                .await,
+            Err(AppError::SessionExpired)
+        ));
+        assert_eq!(rooms.peer_ids(room_id).await, vec![peer_id]);
+    }
+
+    #[tokio::test]
+    async fn revoked_sessions_cannot_be_resumed() {
+        let rooms = service(Arc::new(InMemoryRoomStore::new()));
+        let (room, owner_id) = owned_room(&rooms).await;
+        let (room_id, owner_token) = (room.id(), room.owner_token().unwrap());
+        let guest_id = join(&rooms, &room, None).await.unwrap();
+        let guest_token = rooms.issue_resume_token(room_id, guest_id).await;
+        let owner_resume_token = rooms.issue_resume_token(room_id, owner_id).await;
+
+        rooms
+            .kick_peer(room_id, owner_token, guest_id)
+            .await
+            .unwrap();
+        assert!(matches!(
+            rooms
+                .resume_session(room_id, guest_id, &guest_token, sender(), None)
+                .await,
+            Err(AppError::SessionExpired)
+        ));
+
+        rooms.close_room(room_id, owner_token).await.unwrap();
+        assert!(matches!(
+            rooms
+                .session_state(room_id, owner_id, &owner_resume_token)
+                .await,
+            SessionState::Revoked
+        ));
+        assert!(matches!(
+            rooms
+                .resume_session(room_id, owner_id, &owner_resume_token, sender(), None)
+                .await,
+            Err(AppError::SessionExpired)
+        ));
+    }
+
+    #[tokio::test]
+    async fn share_token_is_needed_to_find_or_join_a_room() {
+        let rooms = service(Arc::new(InMemoryRoomStore::new()));
+        let room = rooms.create_room(None, None, Some(false)).await.unwrap();
+        let (room_id, token) = (room.id(), room.share_token().unwrap());
+
+        for wrong in [None, Some("not-the-token")] {
+            assert!(rooms.get_room_info(room_id, wrong).await.is_none());
+            assert!(matches!(
+                rooms
+                    .join_room(room_id, wrong, None, None, sender(), CLIENT_IP)
+                    .await,
+                Err(AppError::RoomNotFound(_))
+            ));
+        }
+        assert_eq!(
+            rooms.get_room_info(room_id, Some(token)).await,
+            Some((true, false))
+        );
+        join(&rooms, &room, None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rooms_have_no_share_token_when_disabled() {
+        let cli = CommandLine::parse(["--room-share-tokens=false".to_string()]).unwrap();
+        let config = Config::load(&cli).unwrap();
+        let slug_generator = SlugGenerator::from_config(&config).unwrap();
+        let rooms = RoomService::new(
+            Arc::new(InMemoryRoomStore::new()),
+            ConfigHandle::new(config, cli),
+            slug_generator,
+        );
+
+        let room = rooms.create_room(None, None, Some(false)).await.unwrap();
+        assert!(room.share_token().is_none());
+        assert!(rooms.get_room_info(room.id(), None).await.is_some());
+        join(&rooms, &room, None).await.unwrap();
+    }
+
+    #[test]
+    fn room_names_are_normalized_and_checked() {
+        let rooms = service(Arc::new(InMemoryRoomStore::new()));
+        assert_eq!(
+            rooms.validate_room_name("  Team-Sync-42 ").unwrap(),
+            "team-sync-42"
+        );
+
+        for name in [
+            "ab",
+            &"a".repeat(49),
+            "team_sync",
+            "team sync",
+            "-team",
+            "team-",
+            "team--sync",
+            "caffè",
+            "admin",
+            "Metrics",
+            "nazi-room",
+            "sh-itake",
+        ] {
+            assert!(
+                matches!(
+                    rooms.validate_room_name(name),
+                    Err(AppError::InvalidRoomName(_))
+                ),
+                "{name} was accepted"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_room_names_are_claimed_once() {
+        let rooms = service(Arc::new(InMemoryRoomStore::new()));
+        let room = rooms
+            .create_room(None, Some("Team-Sync".into()), None)
+            .await
+            .unwrap();
+        assert_eq!(room.id(), "team-sync");
+
+        assert!(matches!(
+            rooms
+                .create_room(None, Some("team-sync".into()), None)
+                .await,
+            Err(AppError::RoomNameTaken(name)) if name == "team-sync"
+        ));
+    }
+
+    #[tokio::test]
+    async fn draining_refuses_new_rooms_but_not_joins() {
+        let rooms = service(Arc::new(InMemoryRoomStore::new()));
+        let room = rooms.create_room(None, None, Some(false)).await.unwrap();
+        let draining = rooms.draining();
+        assert!(!rooms.is_draining());
+
+        rooms.start_draining();
+        assert!(rooms.is_draining());
+        assert!(draining.has_changed().unwrap());
+        assert!(matches!(
+            rooms.create_room(None, None, None).await,
+            Err(AppError::ServerShuttingDown { retry_after_secs }) if retry_after_secs == rooms.config().shutdown_reconnect_after_secs
+        ));
+        join(&rooms, &room, None).await.unwrap();
+    }
 }
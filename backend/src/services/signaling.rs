@@ -1,14 +1,24 @@
+use crate::bus::{BusMessage, RoomEvent, SignalingBus};
+use crate::metrics;
 use crate::models::{Room, ServerMessage};
+use crate::services::RoomService;
+use std::sync::Arc;
 use uuid::Uuid;
 use warp::ws::Message;
 
+/// Normal closure (RFC 6455)
+const CLOSE_NORMAL: u16 = 1000;
+
 /// Services for WebRTC signaling operations.
 /// Handles broadcasting signals between peers.
-pub struct SignalingService;
+/// Messages go through the signaling bus, so they reach peers connected to any node.
+pub struct SignalingService {
+    bus: Arc<dyn SignalingBus>,
+}
 
 impl SignalingService {
-    pub fn new() -> Self {
-        Self
+    pub fn new(bus: Arc<dyn SignalingBus>) -> Self {
+        Self { bus }
     }
 
     /// Broadcast a signal to all peers except the sender.
@@ -18,44 +28,216 @@ impl SignalingService {
         sender_id: Uuid,
         signal_data: serde_json::Value,
     ) {
-        let signal_msg = ServerMessage::Signal { data: signal_data };
-        let msg_text = serde_json::to_string(&signal_msg).unwrap_or_default();
+        metrics::SIGNALS_RELAYED.record(&signal_data);
+        let signal_msg = ServerMessage::Signal {
+            from: sender_id,
+            data: signal_data,
+            seq: 0,
+        };
+        self.publish(room, None, Some(sender_id), signal_msg).await;
+    }
 
-        let peers = room.peers().read().await;
-        for (peer_id, peer) in peers.iter() {
-            if *peer_id != sender_id {
-                let _ = peer.sender.send(Message::text(msg_text.clone()));
-            }
-        }
+    /// Send a signal to a single peer of the room.
+    pub async fn send_signal(
+        &self,
+        room: &Room,
+        sender_id: Uuid,
+        target_id: Uuid,
+        signal_data: serde_json::Value,
+    ) {
+        metrics::SIGNALS_RELAYED.record(&signal_data);
+        let signal_msg = ServerMessage::Signal {
+            from: sender_id,
+            data: signal_data,
+            seq: 0,
+        };
+        self.publish(room, Some(target_id), None, signal_msg).await;
     }
 
     /// Notify all peers that someone joined.
     pub async fn broadcast_peer_joined(&self, room: &Room, new_peer_id: Uuid, peer_count: usize) {
-        let msg = ServerMessage::PeerJoined { peer_count };
-        let msg_text = serde_json::to_string(&msg).unwrap_or_default();
-
-        let peers = room.peers().read().await;
-        for (peer_id, peer) in peers.iter() {
-            if *peer_id != new_peer_id {
-                let _ = peer.sender.send(Message::text(msg_text.clone()));
-            }
-        }
+        let msg = ServerMessage::PeerJoined {
+            peer_id: new_peer_id,
+            peer_count,
+        };
+        self.publish(room, None, Some(new_peer_id), msg).await;
     }
 
     /// Notify all remaining peers that someone left.
-    pub async fn broadcast_peer_left(&self, room: &Room, peer_count: usize) {
-        let msg = ServerMessage::PeerLeft { peer_count };
-        let msg_text = serde_json::to_string(&msg).unwrap_or_default();
+    pub async fn broadcast_peer_left(&self, room: &Room, left_peer_id: Uuid, peer_count: usize) {
+        let msg = ServerMessage::PeerLeft {
+            peer_id: left_peer_id,
+            peer_count,
+        };
+        self.publish(room, None, None, msg).await;
+    }
 
-        let peers = room.peers().read().await;
-        for (_, peer) in peers.iter() {
-            let _ = peer.sender.send(Message::text(msg_text.clone()));
+    /// Tell a peer it was kicked. The node holding its connection closes it.
+    pub async fn notify_kicked(&self, room: &Room, peer_id: Uuid) {
+        self.publish(room, Some(peer_id), None, ServerMessage::Kicked)
+            .await;
+    }
+
+    /// Notify all peers that the room was locked or unlocked.
+    pub async fn broadcast_room_locked(&self, room: &Room, locked: bool) {
+        let msg = ServerMessage::RoomLocked { locked };
+        self.publish(room, None, None, msg).await;
+    }
+
+    /// Notify all peers that the room password changed.
+    pub async fn broadcast_password_changed(&self, room: &Room, has_password: bool) {
+        let msg = ServerMessage::PasswordChanged { has_password };
+        self.publish(room, None, None, msg).await;
+    }
+
+    /// Notify all peers that the room was closed. Their connections are closed after it.
+    pub async fn broadcast_room_closed(&self, room: &Room) {
+        self.publish(room, None, None, ServerMessage::RoomClosed)
+            .await;
+    }
+
+    /// Ask the room owner to approve a join request.
+    pub async fn notify_join_request(
+        &self,
+        room: &Room,
+        request_id: Uuid,
+        display_name: Option<String>,
+        fingerprint: String,
+    ) {
+        let Some(owner_peer_id) = room.owner_peer_id() else {
+            return;
+        };
+        let msg = ServerMessage::JoinRequest {
+            request_id,
+            display_name,
+            fingerprint,
+        };
+        self.publish(room, Some(owner_peer_id), None, msg).await;
+    }
+
+    /// Tell the room owner a join request went away unanswered.
+    pub async fn notify_join_request_cancelled(&self, room: &Room, request_id: Uuid) {
+        let Some(owner_peer_id) = room.owner_peer_id() else {
+            return;
+        };
+        let msg = ServerMessage::JoinRequestCancelled { request_id };
+        self.publish(room, Some(owner_peer_id), None, msg).await;
+    }
+
+    /// Pass the owner's answer to the node holding the join request.
+    pub async fn answer_join_request(&self, room: &Room, request_id: Uuid, approved: bool) {
+        let msg = BusMessage::JoinRequestAnswered {
+            request_id,
+            approved,
+        };
+        self.publish_event(room, None, None, msg).await;
+    }
+
+    /// Hand a peer its end of a relay requested by another peer.
+    pub async fn notify_relay_ready(&self, room: &Room, peer_id: Uuid, relay_ready: ServerMessage) {
+        self.publish(room, Some(peer_id), None, relay_ready).await;
+    }
+
+    /// Warn the peers of a room that someone is guessing its password.
+    pub async fn notify_password_failure(&self, room: &Room, failed_attempts: u32) {
+        let msg = ServerMessage::PasswordAttemptFailed { failed_attempts };
+        self.publish(room, None, None, msg).await;
+    }
+
+    async fn publish(
+        &self,
+        room: &Room,
+        to: Option<Uuid>,
+        exclude: Option<Uuid>,
+        message: ServerMessage,
+    ) {
+        self.publish_event(room, to, exclude, BusMessage::Client(message))
+            .await;
+    }
+
+    async fn publish_event(
+        &self,
+        room: &Room,
+        to: Option<Uuid>,
+        exclude: Option<Uuid>,
+        message: BusMessage,
+    ) {
+        self.bus
+            .publish(RoomEvent {
+                room_id: room.id().to_string(),
+                to,
+                exclude,
+                message,
+            })
+            .await;
+    }
+
+    /// Deliver bus events to the peers connected to this node. Runs until the bus closes.
+    pub async fn run_delivery(&self, room_service: Arc<RoomService>) {
+        let mut events = self.bus.subscribe().await;
+
+        while let Some(event) = events.recv().await {
+            let Some(room) = room_service.get_local_room(&event.room_id).await else {
+                continue;
+            };
+
+            let message = match event.message {
+                BusMessage::Client(message) => message,
+                // Join requests are not peers yet; their answer resolves the waiting join instead
+                BusMessage::JoinRequestAnswered {
+                    request_id,
+                    approved,
+                } => {
+                    room.resolve_join_request(request_id, approved).await;
+                    continue;
+                }
+            };
+
+            let msg_text = serde_json::to_string(&message).unwrap_or_default();
+            let lossy = is_lossy(&message);
+
+            let peers = room.peers().read().await;
+            for (peer_id, peer) in peers.iter() {
+                let addressed = event.to.is_none_or(|to| to == *peer_id);
+                if addressed && Some(*peer_id) != event.exclude {
+                    // Signals are numbered per receiver and kept for replay after a reconnect
+                    if let ServerMessage::Signal { from, data, .. } = &message {
+                        peer.mailbox.deliver(*from, data.clone(), lossy);
+                        continue;
+                    }
+
+                    let msg = Message::text(msg_text.clone());
+                    let _ = if lossy {
+                        peer.sender.send_lossy(msg)
+                    } else {
+                        peer.sender.send(msg)
+                    };
+                    if let Some(reason) = close_reason(&message) {
+                        peer.sender.close(CLOSE_NORMAL, reason);
+                    }
+                }
+            }
         }
     }
 }
 
-impl Default for SignalingService {
-    fn default() -> Self {
-        Self::new()
+/// Messages after which the receiving peer's connection is closed
+fn close_reason(message: &ServerMessage) -> Option<&'static str> {
+    match message {
+        ServerMessage::Kicked => Some("Removed by the room owner"),
+        ServerMessage::RoomClosed => Some("Room closed"),
+        _ => None,
+    }
+}
+
+/// ICE candidates may be dropped under backpressure: WebRTC copes with missing
+/// candidates, but not with a missing offer or answer.
+fn is_lossy(message: &ServerMessage) -> bool {
+    match message {
+        ServerMessage::Signal { data, .. } => {
+            data.get("type").and_then(|t| t.as_str()) == Some("candidate")
+                || data.get("candidate").is_some()
+        }
+        _ => false,
     }
 }
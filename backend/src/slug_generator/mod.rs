@@ -0,0 +1,343 @@
+mod words;
+
+use crate::config::Config;
+use base64::{Engine as _, engine::general_purpose};
+use rand::Rng;
+use rand::seq::SliceRandom;
+use std::fs;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Attempts at drawing a slug that avoids the blocklist before giving up
+const MAX_BLOCKED_RETRIES: usize = 100;
+
+#[derive(Error, Debug)]
+pub enum SlugConfigError {
+    #[error("Unknown slug locale: {0}")]
+    UnknownLocale(String),
+
+    #[error("Failed to read word list {path}: {source}")]
+    WordList {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("Word list {0} has no usable words")]
+    EmptyWordList(String),
+
+    #[error("Invalid slug template: {0}")]
+    InvalidTemplate(String),
+}
+
+/// One piece of a slug template such as `{adj}-{noun}-{num:4}`
+#[derive(Debug, Clone, PartialEq)]
+enum TemplatePart {
+    Literal(String),
+    Adjective,
+    Noun,
+    Number { digits: u32 },
+}
+
+/// Generates room slugs from a template and word lists.
+/// Words and combinations containing a blocklisted term are never produced.
+pub struct SlugGenerator {
+    template: Vec<TemplatePart>,
+    adjectives: Vec<String>,
+    nouns: Vec<String>,
+    blocklist: Vec<String>,
+}
+
+impl SlugGenerator {
+    /// Build the generator from `SLUG_TEMPLATE`, the locale's embedded word lists
+    /// (or the configured word list files) and the blocklist.
+    pub fn from_config(config: &Config) -> Result<Self, SlugConfigError> {
+        let embedded = words::for_locale(&config.slug_locale)
+            .ok_or_else(|| SlugConfigError::UnknownLocale(config.slug_locale.clone()))?;
+
+        let mut blocklist: Vec<String> = words::BLOCKLIST.iter().map(|w| w.to_string()).collect();
+        if let Some(path) = &config.slug_blocklist_file {
+            blocklist.extend(read_word_file(path)?);
+        }
+
+        let adjectives = match &config.slug_adjectives_file {
+            Some(path) => read_word_file(path)?,
+            None => embedded.adjectives.iter().map(|w| w.to_string()).collect(),
+        };
+        let nouns = match &config.slug_nouns_file {
+            Some(path) => read_word_file(path)?,
+            None => embedded.nouns.iter().map(|w| w.to_string()).collect(),
+        };
+
+        let generator = Self {
+            template: parse_template(&config.slug_template)?,
+            adjectives: remove_blocked(adjectives, &blocklist),
+            nouns: remove_blocked(nouns, &blocklist),
+            blocklist,
+        };
+
+        if generator.adjectives.is_empty() && generator.uses(&TemplatePart::Adjective) {
+            return Err(SlugConfigError::EmptyWordList("adjectives".to_string()));
+        }
+        if generator.nouns.is_empty() && generator.uses(&TemplatePart::Noun) {
+            return Err(SlugConfigError::EmptyWordList("nouns".to_string()));
+        }
+
+        Ok(generator)
+    }
+
+    /// Generate a slug, e.g. "hot-espresso-4217" for `{adj}-{noun}-{num:4}`
+    pub fn generate(&self) -> String {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..MAX_BLOCKED_RETRIES {
+            let slug: String = self
+                .template
+                .iter()
+                .map(|part| match part {
+                    TemplatePart::Literal(text) => text.clone(),
+                    TemplatePart::Adjective => self
+                        .adjectives
+                        .choose(&mut rng)
+                        .cloned()
+                        .unwrap_or_default(),
+                    TemplatePart::Noun => self.nouns.choose(&mut rng).cloned().unwrap_or_default(),
+                    TemplatePart::Number { digits } => {
+                        let n = rng.gen_range(0..10u64.pow(*digits));
+                        format!("{:0width$}", n, width = *digits as usize)
+                    }
+                })
+                .collect();
+
+            if !self.is_blocked(&slug) {
+                return slug;
+            }
+        }
+
+        // Only reachable with a blocklist matching nearly every combination
+        Uuid::new_v4().to_string()
+    }
+
+    /// Bits of entropy of a generated slug, i.e. log2 of the number of possible slugs
+    pub fn entropy_bits(&self) -> f64 {
+        self.template
+            .iter()
+            .map(|part| match part {
+                TemplatePart::Literal(_) => 0.0,
+                TemplatePart::Adjective => (self.adjectives.len() as f64).log2(),
+                TemplatePart::Noun => (self.nouns.len() as f64).log2(),
+                TemplatePart::Number { digits } => *digits as f64 * 10f64.log2(),
+            })
+            .sum()
+    }
+
+    fn uses(&self, part: &TemplatePart) -> bool {
+        self.template.contains(part)
+    }
+
+    /// Check the slug with separators removed, so terms spanning two words are caught too
+    pub fn is_blocked(&self, slug: &str) -> bool {
+        let joined: String = slug.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+        self.blocklist
+            .iter()
+            .any(|term| joined.contains(term.as_str()))
+    }
+}
+
+/// Parse a template made of literal text and the placeholders `{adj}`, `{noun}`,
+/// `{num}` (3 digits) and `{num:N}`.
+fn parse_template(template: &str) -> Result<Vec<TemplatePart>, SlugConfigError> {
+    let invalid =
+        |reason: &str| SlugConfigError::InvalidTemplate(format!("{reason} in {template:?}"));
+    let mut parts = Vec::new();
+    let mut rest = template;
+
+    while !rest.is_empty() {
+        let Some(start) = rest.find('{') else {
+            parts.push(TemplatePart::Literal(rest.to_string()));
+            break;
+        };
+        if start > 0 {
+            parts.push(TemplatePart::Literal(rest[..start].to_string()));
+        }
+
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| invalid("unclosed placeholder"))?
+            + start;
+        let part = match &rest[start + 1..end] {
+            "adj" => TemplatePart::Adjective,
+            "noun" => TemplatePart::Noun,
+            "num" => TemplatePart::Number { digits: 3 },
+            other => match other.strip_prefix("num:").map(str::parse::<u32>) {
+                Some(Ok(digits @ 1..=18)) => TemplatePart::Number { digits },
+                _ => return Err(invalid(&format!("unknown placeholder {{{other}}}"))),
+            },
+        };
+        parts.push(part);
+        rest = &rest[end + 1..];
+    }
+
+    let literal_ok = |text: &String| {
+        text.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+    if parts
+        .iter()
+        .any(|part| matches!(part, TemplatePart::Literal(text) if !literal_ok(text)))
+    {
+        return Err(invalid("literal text must be URL-safe"));
+    }
+    if parts
+        .iter()
+        .all(|part| matches!(part, TemplatePart::Literal(_)))
+    {
+        return Err(invalid("no placeholder"));
+    }
+
+    Ok(parts)
+}
+
+/// Read a word list file: one word per line, blank lines and `#` comments ignored.
+/// Words must be ASCII letters and digits so room names stay URL-safe.
+fn read_word_file(path: &str) -> Result<Vec<String>, SlugConfigError> {
+    let contents = fs::read_to_string(path).map_err(|source| SlugConfigError::WordList {
+        path: path.to_string(),
+        source,
+    })?;
+
+    let mut words: Vec<String> = contents
+        .lines()
+        .map(|line| line.trim().to_ascii_lowercase())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter(|word| word.chars().all(|c| c.is_ascii_alphanumeric()))
+        .collect();
+    words.sort();
+    words.dedup();
+
+    if words.is_empty() {
+        return Err(SlugConfigError::EmptyWordList(path.to_string()));
+    }
+    Ok(words)
+}
+
+fn remove_blocked(words: Vec<String>, blocklist: &[String]) -> Vec<String> {
+    words
+        .into_iter()
+        .filter(|word| !blocklist.iter().any(|term| word.contains(term.as_str())))
+        .collect()
+}
+
+/// Generate an unguessable token for share links and room ownership (128 random bits, URL-safe)
+pub fn generate_token() -> String {
+    let bytes: [u8; 16] = rand::thread_rng().r#gen();
+    general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generator(template: &str, blocklist: &[&str]) -> SlugGenerator {
+        let blocklist: Vec<String> = blocklist.iter().map(|w| w.to_string()).collect();
+        SlugGenerator {
+            template: parse_template(template).unwrap(),
+            adjectives: remove_blocked(vec!["hot".into(), "badcold".into()], &blocklist),
+            nouns: vec!["mocha".into()],
+            blocklist,
+        }
+    }
+
+    #[test]
+    fn parses_placeholders_and_literals() {
+        assert_eq!(
+            parse_template("{adj}-{noun}_{num}.{num:5}").unwrap(),
+            vec![
+                TemplatePart::Adjective,
+                TemplatePart::Literal("-".into()),
+                TemplatePart::Noun,
+                TemplatePart::Literal("_".into()),
+                TemplatePart::Number { digits: 3 },
+                TemplatePart::Literal(".".into()),
+                TemplatePart::Number { digits: 5 },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        for template in [
+            "{adj}-{noun",
+            "{verb}",
+            "{num:0}",
+            "{num:19}",
+            "{adj}/{noun}",
+            "plain-text",
+        ] {
+            assert!(parse_template(template).is_err(), "{template} was accepted");
+        }
+    }
+
+    #[test]
+    fn numbers_are_zero_padded() {
+        let generator = generator("{num:6}", &[]);
+        for _ in 0..200 {
+            let slug = generator.generate();
+            assert_eq!(slug.len(), 6, "{slug}");
+            assert!(slug.chars().all(|c| c.is_ascii_digit()));
+        }
+    }
+
+    #[test]
+    fn generates_from_template() {
+        let slug = generator("{adj}-{noun}-{num:2}", &["bad"]).generate();
+        let parts: Vec<_> = slug.split('-').collect();
+        assert_eq!(parts[..2], ["hot", "mocha"]);
+        assert_eq!(parts[2].len(), 2);
+    }
+
+    #[test]
+    fn blocklist_catches_terms_across_separators() {
+        let generator = generator("{adj}-{noun}", &["tmo"]);
+        assert!(generator.is_blocked("hot-mocha"));
+        assert!(!generator.is_blocked("hot-latte"));
+    }
+
+    #[test]
+    fn blocked_words_are_never_drawn() {
+        let generator = generator("{adj}", &["bad"]);
+        assert_eq!(generator.adjectives, ["hot"]);
+        for _ in 0..20 {
+            assert_eq!(generator.generate(), "hot");
+        }
+    }
+
+    #[test]
+    fn entropy_counts_words_and_digits() {
+        let generator = generator("{adj}-{noun}-{num:3}", &[]);
+        let expected = 1.0 + 0.0 + 3.0 * 10f64.log2();
+        assert!((generator.entropy_bits() - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn configured_words_and_digits_set_the_entropy() {
+        let entropy = |args: &[&str]| {
+            let config = crate::config::tests::load(args).unwrap();
+            SlugGenerator::from_config(&config).unwrap().entropy_bits()
+        };
+        let default = entropy(&[]);
+
+        // Guessable default slugs are only safe behind share tokens
+        let config = crate::config::tests::load(&[]).unwrap();
+        assert!(default >= 32.0 || config.room_share_tokens);
+        assert!((entropy(&["--slug-digits=6"]) - default - 2.0 * 10f64.log2()).abs() < 1e-9);
+        assert!(entropy(&["--slug-words=3"]) > default);
+    }
+
+    #[test]
+    fn tokens_carry_128_url_safe_bits() {
+        let token = generate_token();
+        let bytes = general_purpose::URL_SAFE_NO_PAD.decode(&token).unwrap();
+        assert_eq!(bytes.len(), 16);
+        assert_ne!(token, generate_token());
+    }
+}
@@ -0,0 +1,97 @@
+//! Embedded word lists, one set per locale.
+//! Words are plain ASCII (umlauts and accents transliterated) so room names
+//! stay URL-safe and easy to dictate over the phone.
+
+pub struct WordList {
+    pub adjectives: &'static [&'static str],
+    pub nouns: &'static [&'static str],
+}
+
+pub const EN: WordList = WordList {
+    adjectives: &[
+        "hot", "cold", "iced", "dark", "light", "sweet", "bitter", "frothy", "milky", "roasted",
+        "decaf", "strong", "smooth", "creamy", "fresh", "bold", "rich", "steaming", "foamy",
+        "tasty",
+    ],
+    nouns: &[
+        "coffee",
+        "bean",
+        "espresso",
+        "latte",
+        "mocha",
+        "cappuccino",
+        "brew",
+        "roast",
+        "cup",
+        "mug",
+        "barista",
+        "aroma",
+        "steam",
+        "filter",
+        "press",
+        "macchiato",
+        "americano",
+        "cortado",
+        "grind",
+        "pour",
+    ],
+};
+
+pub const DE: WordList = WordList {
+    adjectives: &[
+        "heiss", "kalt", "dunkel", "hell", "suess", "herb", "mild", "stark", "frisch", "cremig",
+        "warm", "lecker", "zart", "edel", "fein", "voll", "rund", "klar", "wach", "bunt",
+    ],
+    nouns: &[
+        "kaffee", "bohne", "espresso", "latte", "mokka", "tasse", "becher", "kanne", "filter",
+        "muehle", "aroma", "dampf", "milch", "zucker", "keks", "kuchen", "barista", "schaum",
+        "krug", "pause",
+    ],
+};
+
+pub const PT: WordList = WordList {
+    adjectives: &[
+        "quente",
+        "frio",
+        "escuro",
+        "claro",
+        "doce",
+        "amargo",
+        "forte",
+        "suave",
+        "cremoso",
+        "fresco",
+        "gelado",
+        "torrado",
+        "intenso",
+        "leve",
+        "rico",
+        "puro",
+        "novo",
+        "bom",
+        "macio",
+        "aromatico",
+    ],
+    nouns: &[
+        "cafe", "grao", "expresso", "galao", "bica", "chavena", "caneca", "bule", "filtro",
+        "moinho", "aroma", "vapor", "leite", "acucar", "bolo", "pastel", "torrada", "barista",
+        "espuma", "pingado",
+    ],
+};
+
+/// Look up the embedded word list for a locale such as `de` or `pt-BR`
+pub fn for_locale(locale: &str) -> Option<&'static WordList> {
+    let language = locale.split(['-', '_']).next().unwrap_or_default();
+    match language.to_ascii_lowercase().as_str() {
+        "en" => Some(&EN),
+        "de" => Some(&DE),
+        "pt" => Some(&PT),
+        _ => None,
+    }
+}
+
+/// Terms that must not appear anywhere in a room name, including across word boundaries
+pub const BLOCKLIST: &[&str] = &[
+    "fuck", "shit", "cunt", "dick", "cock", "piss", "slut", "whore", "bitch", "nazi", "fick",
+    "scheiss", "hure", "arsch", "fotze", "merda", "porra", "caralho", "puta", "foda", "buceta",
+];
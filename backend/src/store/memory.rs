@@ -1,18 +1,23 @@
-use crate::models::Room;
+use crate::models::{Peer, Room};
 use crate::store::RoomStore;
 use async_trait::async_trait;
 use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
+use uuid::Uuid;
 
 pub struct InMemoryRoomStore {
     rooms: RwLock<HashMap<String, Room>>,
+    /// Resume tokens by room, then peer
+    resume_tokens: RwLock<HashMap<String, HashMap<Uuid, String>>>,
 }
 
 impl InMemoryRoomStore {
     pub fn new() -> Self {
         Self {
             rooms: RwLock::new(HashMap::new()),
+            resume_tokens: RwLock::new(HashMap::new()),
         }
     }
 }
@@ -25,9 +30,15 @@ impl Default for InMemoryRoomStore {
 
 #[async_trait]
 impl RoomStore for InMemoryRoomStore {
-    async fn insert(&self, room: Room) {
+    async fn insert(&self, room: Room) -> bool {
         let mut rooms = self.rooms.write().await;
-        rooms.insert(room.id().to_string(), room);
+        match rooms.entry(room.id().to_string()) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(room);
+                true
+            }
+        }
     }
 
     async fn get(&self, id: &str) -> Option<Room> {
@@ -38,6 +49,90 @@ impl RoomStore for InMemoryRoomStore {
     async fn remove(&self, id: &str) {
         let mut rooms = self.rooms.write().await;
         rooms.remove(id);
+        self.resume_tokens.write().await.remove(id);
+    }
+
+    async fn try_add_peer(&self, id: &str, peer: Peer, max_peers: usize) -> Option<usize> {
+        let room = self.get(id).await?;
+        let mut peers = room.peers().write().await;
+        if peers.len() >= max_peers {
+            return None;
+        }
+
+        peers.insert(peer.id, peer);
+        Some(peers.len())
+    }
+
+    async fn remove_peer(&self, id: &str, peer_id: Uuid) -> Option<usize> {
+        match self.get(id).await {
+            Some(room) => {
+                let mut peers = room.peers().write().await;
+                peers.remove(&peer_id);
+                Some(peers.len())
+            }
+            None => Some(0),
+        }
+    }
+
+    async fn rebind_peer(&self, id: &str, peer: Peer) -> Option<Peer> {
+        let room = self.get(id).await?;
+        let mut peers = room.peers().write().await;
+        peers.insert(peer.id, peer)
+    }
+
+    async fn peer_ids(&self, id: &str) -> Vec<Uuid> {
+        match self.get(id).await {
+            Some(room) => room.peers().read().await.keys().copied().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    async fn set_password_hash(&self, id: &str, password_hash: Option<String>) {
+        if let Some(room) = self.get(id).await {
+            room.set_password_hash(password_hash).await;
+        }
+    }
+
+    async fn set_locked(&self, id: &str, locked: bool) {
+        if let Some(room) = self.get(id).await {
+            room.set_locked(locked);
+        }
+    }
+
+    async fn set_resume_token(&self, id: &str, peer_id: Uuid, token: Option<String>) {
+        let mut resume_tokens = self.resume_tokens.write().await;
+        match token {
+            Some(token) => {
+                resume_tokens
+                    .entry(id.to_string())
+                    .or_default()
+                    .insert(peer_id, token);
+            }
+            None => {
+                if let Some(tokens) = resume_tokens.get_mut(id) {
+                    tokens.remove(&peer_id);
+                }
+            }
+        }
+    }
+
+    async fn resume_token(&self, id: &str, peer_id: Uuid) -> Option<String> {
+        self.resume_tokens
+            .read()
+            .await
+            .get(id)
+            .and_then(|tokens| tokens.get(&peer_id))
+            .cloned()
+    }
+
+    async fn clear_resume_tokens(&self, id: &str) {
+        self.resume_tokens.write().await.remove(id);
+    }
+
+    async fn touch(&self, id: &str) {
+        if let Some(room) = self.get(id).await {
+            *room.last_activity().write().await = Instant::now();
+        }
     }
 
     async fn get_stale_room_ids(&self, max_age: Duration) -> Vec<String> {
@@ -58,4 +153,4 @@ impl RoomStore for InMemoryRoomStore {
     async fn count(&self) -> usize {
         self.rooms.read().await.len()
     }
-}
\ No newline at end of file
+}
@@ -1,22 +1,67 @@
 pub mod memory;
+pub mod redis;
 
-use crate::models::Room;
+use crate::models::{Peer, Room};
 use async_trait::async_trait;
 use std::time::Duration;
+use uuid::Uuid;
 
 /// Trait for room storage operations.
 /// Implementations can use memory, Redis, PostgreSQL, etc.
+///
+/// Peer senders are always local to the process, so a returned `Room` only
+/// holds the peers connected to this node. Peer counts returned by the store
+/// cover the whole deployment.
 #[async_trait]
 pub trait RoomStore: Send + Sync {
-    /// Insert a new room
-    async fn insert(&self, room: Room);
+    /// Insert a new room. Returns false, leaving the store unchanged, if the id is taken.
+    async fn insert(&self, room: Room) -> bool;
 
     /// Get a room by ID
     async fn get(&self, id: &str) -> Option<Room>;
 
+    /// Get a room by ID from this node only, without consulting shared storage.
+    /// Used on hot paths that only care about locally connected peers.
+    async fn get_local(&self, id: &str) -> Option<Room> {
+        self.get(id).await
+    }
+
     /// Remove a room by ID
     async fn remove(&self, id: &str);
 
+    /// Add a peer to a room if there is capacity left.
+    /// Returns the new peer count, or `None` if the room is full or gone.
+    async fn try_add_peer(&self, id: &str, peer: Peer, max_peers: usize) -> Option<usize>;
+
+    /// Remove a peer from a room. Returns the remaining peer count, or `None` if the
+    /// shared storage could not be reached and the count is unknown.
+    async fn remove_peer(&self, id: &str, peer_id: Uuid) -> Option<usize>;
+
+    /// Replace the connection of a peer already in the room with `peer`'s.
+    /// Returns the peer it replaced, if that connection was on this node.
+    async fn rebind_peer(&self, id: &str, peer: Peer) -> Option<Peer>;
+
+    /// Get the ids of all peers in a room, on any node
+    async fn peer_ids(&self, id: &str) -> Vec<Uuid>;
+
+    /// Replace the password hash of a room, on every node
+    async fn set_password_hash(&self, id: &str, password_hash: Option<String>);
+
+    /// Lock or unlock a room against new joins, on every node
+    async fn set_locked(&self, id: &str, locked: bool);
+
+    /// Store the token a peer can resume its session with, or revoke it with `None`
+    async fn set_resume_token(&self, id: &str, peer_id: Uuid, token: Option<String>);
+
+    /// Get the token a peer can resume its session with
+    async fn resume_token(&self, id: &str, peer_id: Uuid) -> Option<String>;
+
+    /// Revoke the resume tokens of every peer in a room
+    async fn clear_resume_tokens(&self, id: &str);
+
+    /// Mark a room as active, postponing its expiry
+    async fn touch(&self, id: &str);
+
     /// Get IDs of rooms that haven't had activity for `max_age`
     async fn get_stale_room_ids(&self, max_age: Duration) -> Vec<String>;
 
@@ -24,4 +69,112 @@ pub trait RoomStore: Send + Sync {
     async fn count(&self) -> usize;
 }
 
-pub use memory::InMemoryRoomStore;
\ No newline at end of file
+pub use memory::InMemoryRoomStore;
+pub use redis::RedisRoomStore;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CommandLine, Config, ConfigHandle, OverflowPolicy};
+    use crate::models::{Mailbox, peer_channel};
+    use std::sync::Arc;
+
+    fn room(id: &str) -> Room {
+        Room::new(
+            id.to_string(),
+            None,
+            None,
+            Some("owner".into()),
+            None,
+            false,
+        )
+    }
+
+    fn peer(id: Uuid) -> Peer {
+        let (sender, _receiver) = peer_channel(8, OverflowPolicy::Disconnect);
+        let mailbox = Mailbox::new(sender.clone(), 0, Duration::ZERO, 1);
+        Peer::new(id, sender, Arc::new(mailbox))
+    }
+
+    /// Behaviour every store must share
+    async fn exercise(store: &dyn RoomStore) {
+        let id = format!("test-{}", Uuid::new_v4());
+        let rooms_before = store.count().await;
+
+        assert!(store.insert(room(&id)).await);
+        assert!(!store.insert(room(&id)).await, "ids are claimed once");
+        assert_eq!(store.count().await, rooms_before + 1);
+        assert!(store.get(&id).await.is_some());
+        assert!(store.get(&format!("{id}-missing")).await.is_none());
+
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        assert_eq!(store.try_add_peer(&id, peer(a), 2).await, Some(1));
+        assert_eq!(store.try_add_peer(&id, peer(b), 2).await, Some(2));
+        assert_eq!(store.try_add_peer(&id, peer(c), 2).await, None);
+        let mut peer_ids = store.peer_ids(&id).await;
+        peer_ids.sort();
+        let mut expected = vec![a, b];
+        expected.sort();
+        assert_eq!(peer_ids, expected);
+
+        let previous = store.rebind_peer(&id, peer(a)).await;
+        assert!(previous.is_some_and(|previous| previous.id == a));
+
+        store.set_locked(&id, true).await;
+        store.set_password_hash(&id, Some("hash".to_string())).await;
+        let stored = store.get(&id).await.unwrap();
+        assert!(stored.is_locked());
+        assert_eq!(stored.password_hash().await.as_deref(), Some("hash"));
+
+        store.set_resume_token(&id, a, Some("token-a".into())).await;
+        store.set_resume_token(&id, b, Some("token-b".into())).await;
+        assert_eq!(store.resume_token(&id, a).await.as_deref(), Some("token-a"));
+        store.set_resume_token(&id, a, None).await;
+        assert_eq!(store.resume_token(&id, a).await, None);
+        store.clear_resume_tokens(&id).await;
+        assert_eq!(store.resume_token(&id, b).await, None);
+
+        assert_eq!(store.remove_peer(&id, a).await, Some(1));
+        assert_eq!(store.peer_ids(&id).await, vec![b]);
+        assert_eq!(store.remove_peer(&id, b).await, Some(0));
+
+        store.remove(&id).await;
+        assert!(store.get(&id).await.is_none());
+        assert_eq!(store.count().await, rooms_before);
+        assert_eq!(store.try_add_peer(&id, peer(a), 2).await, None);
+    }
+
+    #[tokio::test]
+    async fn in_memory_store() {
+        exercise(&InMemoryRoomStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn in_memory_store_reports_stale_rooms() {
+        let store = InMemoryRoomStore::new();
+        assert!(store.insert(room("quiet")).await);
+        assert!(
+            store
+                .get_stale_room_ids(Duration::from_secs(60))
+                .await
+                .is_empty()
+        );
+        assert_eq!(
+            store.get_stale_room_ids(Duration::ZERO).await,
+            vec!["quiet"]
+        );
+    }
+
+    /// Runs against the Redis server at `REDIS_TEST_URL` with `cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore = "needs a Redis server at REDIS_TEST_URL"]
+    async fn redis_store() {
+        let url = std::env::var("REDIS_TEST_URL").expect("REDIS_TEST_URL must be set");
+        let cli = CommandLine::parse(["--room-ttl-seconds".to_string(), "60".to_string()]).unwrap();
+        let config = ConfigHandle::new(Config::load(&cli).unwrap(), cli);
+        let store = RedisRoomStore::connect(&url, config)
+            .await
+            .expect("connect to REDIS_TEST_URL");
+        exercise(&store).await;
+    }
+}
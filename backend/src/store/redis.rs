@@ -0,0 +1,594 @@
+use crate::config::ConfigHandle;
+use crate::models::{Peer, Room};
+use crate::store::RoomStore;
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, Script, ScriptInvocation};
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+const KEY_PREFIX: &str = "filecoffee:room:";
+
+/// Sorted set of room ids, scored by the unix time the room expires at
+const INDEX_KEY: &str = "filecoffee:rooms";
+
+/// Sorted set of node ids, scored by the unix time each node counts as alive until
+const NODES_KEY: &str = "filecoffee:nodes";
+
+/// How long a node's peers count as connected after its last heartbeat
+const NODE_TTL: Duration = Duration::from_secs(30);
+
+/// How often a node renews its heartbeat, well within `NODE_TTL`
+const NODE_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Helpers shared by the room scripts. Every script takes the keys
+/// room, peers, sessions, index and nodes, and the arguments TTL in seconds,
+/// expiry as a unix time and room id, followed by its own.
+const PRELUDE: &str = r"
+local function refresh()
+    redis.call('EXPIRE', KEYS[1], ARGV[1])
+    redis.call('EXPIRE', KEYS[2], ARGV[1])
+    redis.call('EXPIRE', KEYS[3], ARGV[1])
+    redis.call('ZADD', KEYS[4], ARGV[2], ARGV[3])
+end
+
+local function live_peer_count(now)
+    local count = 0
+    local entries = redis.call('HGETALL', KEYS[2])
+    for i = 1, #entries, 2 do
+        local alive_until = redis.call('ZSCORE', KEYS[5], entries[i + 1])
+        if alive_until and tonumber(alive_until) >= tonumber(now) then
+            count = count + 1
+        else
+            redis.call('HDEL', KEYS[2], entries[i])
+        end
+    end
+    return count
+end
+";
+
+/// Create a room hash unless the id is taken. ARGV: field/value pairs.
+const INSERT_SCRIPT: &str = r"
+if redis.call('EXISTS', KEYS[1]) == 1 then
+    return 0
+end
+redis.call('HSET', KEYS[1], unpack(ARGV, 4))
+refresh()
+return 1
+";
+
+/// Refresh the TTL of an existing room.
+const TOUCH_SCRIPT: &str = r"
+if redis.call('EXISTS', KEYS[1]) == 0 then
+    return 0
+end
+refresh()
+return 1
+";
+
+/// Set one field of an existing room hash. ARGV: field, value.
+const SET_FIELD_SCRIPT: &str = r"
+if redis.call('EXISTS', KEYS[1]) == 0 then
+    return 0
+end
+redis.call('HSET', KEYS[1], ARGV[4], ARGV[5])
+refresh()
+return 1
+";
+
+/// Add a peer, tagged with its node, if the room has capacity left.
+/// ARGV: current unix time, peer id, node id, max peers. Returns the peer count or -1.
+const ADD_PEER_SCRIPT: &str = r"
+if redis.call('EXISTS', KEYS[1]) == 0 then
+    return -1
+end
+if live_peer_count(ARGV[4]) >= tonumber(ARGV[7]) then
+    return -1
+end
+redis.call('HSET', KEYS[2], ARGV[5], ARGV[6])
+refresh()
+return redis.call('HLEN', KEYS[2])
+";
+
+/// Move a peer still in the room to another node. ARGV: peer id, node id.
+const REBIND_PEER_SCRIPT: &str = r"
+if redis.call('HEXISTS', KEYS[2], ARGV[4]) == 0 then
+    return 0
+end
+redis.call('HSET', KEYS[2], ARGV[4], ARGV[5])
+refresh()
+return 1
+";
+
+/// Remove a peer. ARGV: current unix time, peer id. Returns the remaining peer count.
+const REMOVE_PEER_SCRIPT: &str = r"
+redis.call('HDEL', KEYS[2], ARGV[5])
+return live_peer_count(ARGV[4])
+";
+
+/// List the peers of a room. ARGV: current unix time.
+const PEER_IDS_SCRIPT: &str = r"
+live_peer_count(ARGV[4])
+return redis.call('HKEYS', KEYS[2])
+";
+
+/// Lua scripts for updates that must land together or not at all
+struct Scripts {
+    insert: Script,
+    touch: Script,
+    set_field: Script,
+    add_peer: Script,
+    rebind_peer: Script,
+    remove_peer: Script,
+    peer_ids: Script,
+}
+
+impl Scripts {
+    fn new() -> Self {
+        let script = |body: &str| Script::new(&format!("{PRELUDE}{body}"));
+        Self {
+            insert: script(INSERT_SCRIPT),
+            touch: script(TOUCH_SCRIPT),
+            set_field: script(SET_FIELD_SCRIPT),
+            add_peer: script(ADD_PEER_SCRIPT),
+            rebind_peer: script(REBIND_PEER_SCRIPT),
+            remove_peer: script(REMOVE_PEER_SCRIPT),
+            peer_ids: script(PEER_IDS_SCRIPT),
+        }
+    }
+}
+
+/// Room store shared between backend replicas through Redis.
+///
+/// Room metadata and the peers of each room live in Redis with a native key TTL,
+/// so rooms expire on their own, and an index of room ids keeps counting cheap.
+/// Peers are tagged with the node holding their connection; a node that stops
+/// sending heartbeats takes its peers out of every room. Peer senders cannot
+/// leave the process, so each node keeps the `Room` handles for its own
+/// connections in `local_rooms`.
+///
+/// Every script names the keys it touches, but those include the index and node
+/// set shared by all rooms, so this needs a single Redis instance, not a cluster.
+pub struct RedisRoomStore {
+    conn: ConnectionManager,
+    scripts: Scripts,
+    node_id: Uuid,
+    /// Read for `ROOM_TTL_SECONDS` on every write, so a reload applies to the next one
+    config: ConfigHandle,
+    local_rooms: RwLock<HashMap<String, Room>>,
+}
+
+impl RedisRoomStore {
+    /// Connect to Redis and start this node's heartbeat.
+    /// Rooms expire after `ROOM_TTL_SECONDS` without activity.
+    pub async fn connect(redis_url: &str, config: ConfigHandle) -> redis::RedisResult<Self> {
+        let client = redis::Client::open(redis_url)?;
+        let conn = ConnectionManager::new(client).await?;
+        let node_id = Uuid::new_v4();
+
+        let mut heartbeat_conn = conn.clone();
+        heartbeat(&mut heartbeat_conn, node_id).await?;
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(NODE_HEARTBEAT_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = heartbeat(&mut heartbeat_conn, node_id).await {
+                    tracing::warn!(node_id = %node_id, error = %e, "Failed to renew node heartbeat");
+                }
+            }
+        });
+
+        Ok(Self {
+            conn,
+            scripts: Scripts::new(),
+            node_id,
+            config,
+            local_rooms: RwLock::new(HashMap::new()),
+        })
+    }
+
+    fn room_key(id: &str) -> String {
+        format!("{KEY_PREFIX}{id}")
+    }
+
+    fn peers_key(id: &str) -> String {
+        format!("{KEY_PREFIX}{id}:peers")
+    }
+
+    fn sessions_key(id: &str) -> String {
+        format!("{KEY_PREFIX}{id}:sessions")
+    }
+
+    fn ttl_secs(&self) -> i64 {
+        self.config.current().room_ttl().as_secs().max(1) as i64
+    }
+
+    /// Invoke a room script with the keys and arguments every one of them takes
+    fn room_script<'a>(&self, script: &'a Script, id: &str) -> ScriptInvocation<'a> {
+        let expires_at = unix_time() + self.ttl_secs() as u64;
+
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(Self::room_key(id))
+            .key(Self::peers_key(id))
+            .key(Self::sessions_key(id))
+            .key(INDEX_KEY)
+            .key(NODES_KEY)
+            .arg(self.ttl_secs())
+            .arg(expires_at)
+            .arg(id);
+        invocation
+    }
+
+    /// Refresh the TTL of all room keys
+    async fn expire_room(&self, id: &str) -> redis::RedisResult<()> {
+        let mut conn = self.conn.clone();
+        self.room_script(&self.scripts.touch, id)
+            .invoke_async(&mut conn)
+            .await
+    }
+
+    /// Get the local handle for a room, creating it from Redis metadata if needed.
+    /// Settings the owner can change are refreshed from `fields` on every call.
+    async fn local_room(&self, id: &str, fields: &HashMap<String, String>) -> Room {
+        let field = |name: &str| fields.get(name).filter(|value| !value.is_empty()).cloned();
+
+        let cached = self.local_rooms.read().await.get(id).cloned();
+        let room = match cached {
+            Some(room) => room,
+            None => {
+                let mut local_rooms = self.local_rooms.write().await;
+                local_rooms
+                    .entry(id.to_string())
+                    .or_insert_with(|| {
+                        Room::new(
+                            id.to_string(),
+                            None,
+                            field("share_token"),
+                            field("owner_token"),
+                            field("owner_peer_id").and_then(|id| id.parse().ok()),
+                            field("require_approval").as_deref() == Some("1"),
+                        )
+                    })
+                    .clone()
+            }
+        };
+
+        room.set_password_hash(field("password_hash")).await;
+        room.set_locked(field("locked").as_deref() == Some("1"));
+        room
+    }
+
+    /// Set one field of a room's metadata in Redis, refreshing its TTL.
+    /// A room that has expired or been removed is left alone rather than recreated.
+    async fn set_field(&self, id: &str, name: &str, value: String) {
+        let mut conn = self.conn.clone();
+        let result: redis::RedisResult<bool> = self
+            .room_script(&self.scripts.set_field, id)
+            .arg(name)
+            .arg(value)
+            .invoke_async(&mut conn)
+            .await;
+        if let Err(e) = result {
+            tracing::error!(room_id = %id, field = name, error = %e, "Failed to update room in Redis");
+        }
+    }
+}
+
+#[async_trait]
+impl RoomStore for RedisRoomStore {
+    async fn insert(&self, room: Room) -> bool {
+        let id = room.id().to_string();
+        let created_at = unix_time();
+        let fields = [
+            ("created_at", created_at.to_string()),
+            (
+                "password_hash",
+                room.password_hash().await.unwrap_or_default(),
+            ),
+            (
+                "share_token",
+                room.share_token().unwrap_or_default().to_string(),
+            ),
+            (
+                "owner_token",
+                room.owner_token().unwrap_or_default().to_string(),
+            ),
+            (
+                "owner_peer_id",
+                room.owner_peer_id()
+                    .map(|id| id.to_string())
+                    .unwrap_or_default(),
+            ),
+            (
+                "require_approval",
+                if room.requires_approval() { "1" } else { "0" }.to_string(),
+            ),
+            ("locked", "0".to_string()),
+        ];
+
+        // Claim the id, store the fields and set the TTL in one step; another node
+        // may be creating the same room
+        let mut conn = self.conn.clone();
+        let inserted: redis::RedisResult<bool> = self
+            .room_script(&self.scripts.insert, &id)
+            .arg(&fields[..])
+            .invoke_async(&mut conn)
+            .await;
+
+        match inserted {
+            Ok(true) => {
+                self.local_rooms.write().await.insert(id, room);
+                true
+            }
+            Ok(false) => false,
+            Err(e) => {
+                tracing::error!(room_id = %id, error = %e, "Failed to store room in Redis");
+                false
+            }
+        }
+    }
+
+    async fn get(&self, id: &str) -> Option<Room> {
+        let mut conn = self.conn.clone();
+        let fields: HashMap<String, String> = match conn.hgetall(Self::room_key(id)).await {
+            Ok(fields) => fields,
+            Err(e) => {
+                tracing::error!(room_id = %id, error = %e, "Failed to load room from Redis");
+                return None;
+            }
+        };
+
+        if fields.is_empty() {
+            // Expired or removed by another node
+            self.local_rooms.write().await.remove(id);
+            return None;
+        }
+
+        Some(self.local_room(id, &fields).await)
+    }
+
+    async fn get_local(&self, id: &str) -> Option<Room> {
+        self.local_rooms.read().await.get(id).cloned()
+    }
+
+    async fn remove(&self, id: &str) {
+        let mut conn = self.conn.clone();
+        let result: redis::RedisResult<()> = redis::pipe()
+            .atomic()
+            .del(&[
+                Self::room_key(id),
+                Self::peers_key(id),
+                Self::sessions_key(id),
+            ])
+            .ignore()
+            .zrem(INDEX_KEY, id)
+            .ignore()
+            .query_async(&mut conn)
+            .await;
+
+        if let Err(e) = result {
+            tracing::error!(room_id = %id, error = %e, "Failed to remove room from Redis");
+        }
+
+        self.local_rooms.write().await.remove(id);
+    }
+
+    async fn try_add_peer(&self, id: &str, peer: Peer, max_peers: usize) -> Option<usize> {
+        let room = self.get(id).await?;
+        let mut conn = self.conn.clone();
+
+        // Dead nodes' peers are pruned before the capacity check, so they cannot fill the room
+        let count: i64 = match self
+            .room_script(&self.scripts.add_peer, id)
+            .arg(unix_time())
+            .arg(peer.id.to_string())
+            .arg(self.node_id.to_string())
+            .arg(max_peers)
+            .invoke_async(&mut conn)
+            .await
+        {
+            Ok(count) => count,
+            Err(e) => {
+                tracing::error!(room_id = %id, error = %e, "Failed to add peer in Redis");
+                return None;
+            }
+        };
+        if count < 0 {
+            return None;
+        }
+
+        room.peers().write().await.insert(peer.id, peer);
+        Some(count as usize)
+    }
+
+    async fn remove_peer(&self, id: &str, peer_id: Uuid) -> Option<usize> {
+        if let Some(room) = self.local_rooms.read().await.get(id) {
+            room.peers().write().await.remove(&peer_id);
+        }
+
+        let mut conn = self.conn.clone();
+        let result: redis::RedisResult<usize> = self
+            .room_script(&self.scripts.remove_peer, id)
+            .arg(unix_time())
+            .arg(peer_id.to_string())
+            .invoke_async(&mut conn)
+            .await;
+
+        result
+            .inspect_err(
+                |e| tracing::error!(room_id = %id, error = %e, "Failed to remove peer in Redis"),
+            )
+            .ok()
+    }
+
+    async fn rebind_peer(&self, id: &str, peer: Peer) -> Option<Peer> {
+        // The peer's connection may have moved here from another node
+        let mut conn = self.conn.clone();
+        let result: redis::RedisResult<()> = self
+            .room_script(&self.scripts.rebind_peer, id)
+            .arg(peer.id.to_string())
+            .arg(self.node_id.to_string())
+            .invoke_async(&mut conn)
+            .await;
+        if let Err(e) = result {
+            tracing::error!(room_id = %id, error = %e, "Failed to rebind peer in Redis");
+        }
+
+        let room = self.get(id).await?;
+        let mut peers = room.peers().write().await;
+        peers.insert(peer.id, peer)
+    }
+
+    async fn peer_ids(&self, id: &str) -> Vec<Uuid> {
+        let mut conn = self.conn.clone();
+        match self
+            .room_script(&self.scripts.peer_ids, id)
+            .arg(unix_time())
+            .invoke_async::<Vec<String>>(&mut conn)
+            .await
+        {
+            Ok(ids) => ids.iter().filter_map(|id| id.parse().ok()).collect(),
+            Err(e) => {
+                tracing::error!(room_id = %id, error = %e, "Failed to list peers in Redis");
+                Vec::new()
+            }
+        }
+    }
+
+    async fn set_password_hash(&self, id: &str, password_hash: Option<String>) {
+        self.set_field(
+            id,
+            "password_hash",
+            password_hash.clone().unwrap_or_default(),
+        )
+        .await;
+        if let Some(room) = self.local_rooms.read().await.get(id) {
+            room.set_password_hash(password_hash).await;
+        }
+    }
+
+    async fn set_locked(&self, id: &str, locked: bool) {
+        let value = if locked { "1" } else { "0" };
+        self.set_field(id, "locked", value.to_string()).await;
+        if let Some(room) = self.local_rooms.read().await.get(id) {
+            room.set_locked(locked);
+        }
+    }
+
+    async fn set_resume_token(&self, id: &str, peer_id: Uuid, token: Option<String>) {
+        let mut conn = self.conn.clone();
+        let result: redis::RedisResult<()> = match token {
+            Some(token) => {
+                redis::pipe()
+                    .hset(Self::sessions_key(id), peer_id.to_string(), token)
+                    .ignore()
+                    .expire(Self::sessions_key(id), self.ttl_secs())
+                    .ignore()
+                    .query_async(&mut conn)
+                    .await
+            }
+            None => conn.hdel(Self::sessions_key(id), peer_id.to_string()).await,
+        };
+
+        if let Err(e) = result {
+            tracing::error!(room_id = %id, error = %e, "Failed to update resume token in Redis");
+        }
+    }
+
+    async fn resume_token(&self, id: &str, peer_id: Uuid) -> Option<String> {
+        let mut conn = self.conn.clone();
+        match conn
+            .hget::<_, _, Option<String>>(Self::sessions_key(id), peer_id.to_string())
+            .await
+        {
+            Ok(token) => token,
+            Err(e) => {
+                tracing::error!(room_id = %id, error = %e, "Failed to load resume token from Redis");
+                None
+            }
+        }
+    }
+
+    async fn clear_resume_tokens(&self, id: &str) {
+        let mut conn = self.conn.clone();
+        let result: redis::RedisResult<()> = conn.del(Self::sessions_key(id)).await;
+        if let Err(e) = result {
+            tracing::error!(room_id = %id, error = %e, "Failed to clear resume tokens in Redis");
+        }
+    }
+
+    async fn touch(&self, id: &str) {
+        if let Some(room) = self.local_rooms.read().await.get(id) {
+            *room.last_activity().write().await = Instant::now();
+        }
+
+        if let Err(e) = self.expire_room(id).await {
+            tracing::warn!(room_id = %id, error = %e, "Failed to refresh room TTL");
+        }
+    }
+
+    /// Redis expires rooms by itself; this only reports local handles whose
+    /// Redis key has already disappeared so they can be dropped.
+    async fn get_stale_room_ids(&self, _max_age: Duration) -> Vec<String> {
+        let ids: Vec<String> = self.local_rooms.read().await.keys().cloned().collect();
+        let mut conn = self.conn.clone();
+        let mut stale = Vec::new();
+
+        for id in ids {
+            match conn.exists::<_, bool>(Self::room_key(&id)).await {
+                Ok(false) => stale.push(id),
+                Ok(true) => {}
+                Err(e) => {
+                    tracing::warn!(room_id = %id, error = %e, "Failed to check room in Redis")
+                }
+            }
+        }
+
+        stale
+    }
+
+    async fn count(&self) -> usize {
+        let now = unix_time();
+
+        // Rooms leave the index once their expiry has passed
+        let mut conn = self.conn.clone();
+        let result: redis::RedisResult<(usize,)> = redis::pipe()
+            .atomic()
+            .zrembyscore(INDEX_KEY, "-inf", now)
+            .ignore()
+            .zcard(INDEX_KEY)
+            .query_async(&mut conn)
+            .await;
+
+        match result {
+            Ok((count,)) => count,
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to count rooms in Redis");
+                0
+            }
+        }
+    }
+}
+
+/// Mark this node as alive for another `NODE_TTL`, and forget nodes that have died
+async fn heartbeat(conn: &mut ConnectionManager, node_id: Uuid) -> redis::RedisResult<()> {
+    let now = unix_time();
+    redis::pipe()
+        .atomic()
+        .zadd(NODES_KEY, node_id.to_string(), now + NODE_TTL.as_secs())
+        .ignore()
+        .zrembyscore(NODES_KEY, "-inf", format!("({now}"))
+        .ignore()
+        .query_async(conn)
+        .await
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
@@ -7,7 +7,11 @@ import { Label } from "@/components/ui/label";
 interface PasswordSetupProps {
   fileName: string;
   onCancel: () => void;
-  onStart: (password?: string) => void;
+  onStart: (
+    password?: string,
+    roomName?: string,
+    requireApproval?: boolean
+  ) => void;
 }
 
 export const PasswordSetup = ({
@@ -16,9 +20,15 @@ export const PasswordSetup = ({
   onStart,
 }: PasswordSetupProps) => {
   const [password, setPassword] = useState("");
+  const [roomName, setRoomName] = useState("");
+  const [requireApproval, setRequireApproval] = useState(false);
 
   const handleStart = () => {
-    onStart(password.trim() || undefined);
+    onStart(
+      password.trim() || undefined,
+      roomName.trim() || undefined,
+      requireApproval
+    );
   };
 
   return (
@@ -45,6 +55,22 @@ export const PasswordSetup = ({
           </p>
         </div>
 
+        <div className="space-y-2 mb-6">
+          <Label htmlFor="room-name" className="text-foreground">
+            Room name (optional)
+          </Label>
+          <Input
+            id="room-name"
+            placeholder="e.g. design-review"
+            value={roomName}
+            onChange={(e) => setRoomName(e.target.value)}
+            className="bg-background border-border focus:border-accent transition-smooth"
+          />
+          <p className="text-xs text-muted-foreground">
+            Leave empty for a generated name
+          </p>
+        </div>
+
         <div className="space-y-2 mb-8">
           <Label htmlFor="password" className="text-foreground">
             Password (optional)
@@ -62,6 +88,19 @@ export const PasswordSetup = ({
           </p>
         </div>
 
+        <div className="flex items-center gap-2 -mt-4 mb-8">
+          <input
+            id="require-approval"
+            type="checkbox"
+            checked={requireApproval}
+            onChange={(e) => setRequireApproval(e.target.checked)}
+            className="h-4 w-4 accent-accent"
+          />
+          <Label htmlFor="require-approval" className="text-foreground">
+            Ask me before anyone joins
+          </Label>
+        </div>
+
         <div className="flex gap-3">
           <Button
             variant="outline"
@@ -1,5 +1,5 @@
 import { useState } from "react";
-import { Copy, Check, Link2 } from "lucide-react";
+import { Copy, Check, Link2, Lock, Unlock, UserX, XCircle } from "lucide-react";
 import { Button } from "@/components/ui/button";
 import { Input } from "@/components/ui/input";
 import { Label } from "@/components/ui/label";
@@ -10,12 +10,20 @@ interface ShareLinksProps {
   longUrl: string;
   progress: number;
   isConnected: boolean;
+  isLocked?: boolean;
+  onToggleLock?: () => void;
+  onKickPeer?: () => void;
+  onCloseRoom?: () => void;
 }
 
 export const ShareLinks = ({
   longUrl,
   progress,
   isConnected,
+  isLocked = false,
+  onToggleLock,
+  onKickPeer,
+  onCloseRoom,
 }: ShareLinksProps) => {
   const [copiedLong, setCopiedLong] = useState(false);
 
@@ -75,6 +83,33 @@ export const ShareLinks = ({
           </div>
         </div>
 
+        {(onToggleLock || onKickPeer || onCloseRoom) && (
+          <div className="flex flex-wrap justify-center gap-2">
+            {onToggleLock && (
+              <Button variant="outline" size="sm" onClick={onToggleLock}>
+                {isLocked ? (
+                  <Unlock className="h-4 w-4 mr-2" />
+                ) : (
+                  <Lock className="h-4 w-4 mr-2" />
+                )}
+                {isLocked ? "Unlock room" : "Lock room"}
+              </Button>
+            )}
+            {onKickPeer && isConnected && (
+              <Button variant="outline" size="sm" onClick={onKickPeer}>
+                <UserX className="h-4 w-4 mr-2" />
+                Remove peer
+              </Button>
+            )}
+            {onCloseRoom && (
+              <Button variant="outline" size="sm" onClick={onCloseRoom}>
+                <XCircle className="h-4 w-4 mr-2" />
+                Close room
+              </Button>
+            )}
+          </div>
+        )}
+
         <div className="pt-4 border-t border-border">
           {!isConnected && (
             <p className="text-sm text-muted-foreground text-center">
@@ -1,5 +1,22 @@
+import type { IceServer } from "@/lib/utils";
+
 export const DATA_CHANNEL_LABEL = "fileTransfer";
 
+// How often to check whether the relay socket has drained enough to send more
+export const RELAY_DRAIN_POLL_MS = 50;
+
+// Wait before resuming the session after the WebSocket drops
+export const RECONNECT_DELAY_MS = 1000;
+
+// What it takes to resume a session after the WebSocket drops
+export interface SessionInfo {
+  room_id: string;
+  peer_id: string;
+  resume_token: string;
+  // Highest signal seq received, so signals resent after a reconnect are skipped
+  last_seq?: number;
+}
+
 export enum ViewType {
   UPLOAD,
   DOWNLOAD,
@@ -12,35 +29,139 @@ export enum ClientMessageType {
   CreateRoom = "CreateRoom",
   JoinRoom = "JoinRoom",
   Signal = "Signal",
+  ResumeSession = "ResumeSession",
+  KickPeer = "KickPeer",
+  LockRoom = "LockRoom",
+  SetPassword = "SetPassword",
+  CloseRoom = "CloseRoom",
+  AnswerJoinRequest = "AnswerJoinRequest",
+  RequestRelay = "RequestRelay",
 }
 
 export type ClientMessage =
-  | { type: ClientMessageType.CreateRoom; password?: string }
-  | { type: ClientMessageType.JoinRoom; room_id: string; password?: string }
-  | { type: ClientMessageType.Signal; data: JSON };
+  | {
+      type: ClientMessageType.CreateRoom;
+      password?: string;
+      room_name?: string;
+      require_approval?: boolean;
+    }
+  | {
+      type: ClientMessageType.JoinRoom;
+      room_id: string;
+      password?: string;
+      token?: string;
+      display_name?: string;
+    }
+  | {
+      type: ClientMessageType.ResumeSession;
+      room_id: string;
+      peer_id: string;
+      resume_token: string;
+      last_seq?: number;
+    }
+  | { type: ClientMessageType.Signal; data: JSON; to?: string }
+  | { type: ClientMessageType.KickPeer; owner_token: string; peer_id: string }
+  | { type: ClientMessageType.LockRoom; owner_token: string; locked: boolean }
+  | {
+      type: ClientMessageType.SetPassword;
+      owner_token: string;
+      password?: string;
+    }
+  | { type: ClientMessageType.CloseRoom; owner_token: string }
+  | {
+      type: ClientMessageType.AnswerJoinRequest;
+      owner_token: string;
+      request_id: string;
+      approve: boolean;
+    }
+  | { type: ClientMessageType.RequestRelay; to: string };
 
 export enum ServerMessageType {
   RoomCreated = "RoomCreated",
   RoomJoined = "RoomJoined",
+  SessionResumed = "SessionResumed",
   PeerJoined = "PeerJoined",
   PeerLeft = "PeerLeft",
   Signal = "Signal",
   Error = "Error",
   RoomExists = "RoomExists",
+  PasswordAttemptFailed = "PasswordAttemptFailed",
+  Kicked = "Kicked",
+  RoomLocked = "RoomLocked",
+  PasswordChanged = "PasswordChanged",
+  RoomClosed = "RoomClosed",
+  JoinPending = "JoinPending",
+  JoinRequest = "JoinRequest",
+  JoinRequestCancelled = "JoinRequestCancelled",
+  JoinRejected = "JoinRejected",
+  RelayReady = "RelayReady",
+  RelayConnected = "RelayConnected",
+  ServerShutdown = "ServerShutdown",
 }
 
 export type ServerMessage =
-  | { type: ServerMessageType.RoomCreated; room_id: string }
-  | { type: ServerMessageType.RoomJoined }
-  | { type: ServerMessageType.PeerJoined }
-  | { type: ServerMessageType.PeerLeft }
-  | { type: ServerMessageType.Signal; data: JSON }
-  | { type: ServerMessageType.Error; message: string }
+  | {
+      type: ServerMessageType.RoomCreated;
+      room_id: string;
+      share_token?: string;
+      owner_token: string;
+      peer_id: string;
+      resume_token: string;
+      ice_servers: IceServer[];
+    }
+  | {
+      type: ServerMessageType.RoomJoined;
+      peer_id: string;
+      peers: string[];
+      resume_token: string;
+      ice_servers: IceServer[];
+    }
+  | {
+      type: ServerMessageType.SessionResumed;
+      peer_id: string;
+      peers: string[];
+      resume_token: string;
+      ice_servers: IceServer[];
+    }
+  | { type: ServerMessageType.PeerJoined; peer_id: string; peer_count: number }
+  | { type: ServerMessageType.PeerLeft; peer_id: string; peer_count: number }
+  | { type: ServerMessageType.Signal; from: string; data: JSON; seq: number }
+  | {
+      type: ServerMessageType.Error;
+      code: string;
+      message: string;
+      retry_after_secs?: number;
+      // Quote it when reporting a problem
+      request_id: string;
+    }
   | {
       type: ServerMessageType.RoomExists;
       exists: boolean;
       has_password: boolean;
-    };
+    }
+  | { type: ServerMessageType.PasswordAttemptFailed; failed_attempts: number }
+  | { type: ServerMessageType.Kicked }
+  | { type: ServerMessageType.RoomLocked; locked: boolean }
+  | { type: ServerMessageType.PasswordChanged; has_password: boolean }
+  | { type: ServerMessageType.RoomClosed }
+  | { type: ServerMessageType.JoinPending; request_id: string }
+  | {
+      type: ServerMessageType.JoinRequest;
+      request_id: string;
+      display_name?: string;
+      fingerprint: string;
+    }
+  | { type: ServerMessageType.JoinRequestCancelled; request_id: string }
+  | { type: ServerMessageType.JoinRejected; reason: string }
+  | {
+      type: ServerMessageType.RelayReady;
+      peer_id: string;
+      token: string;
+      max_bytes: number;
+      max_bytes_per_sec: number;
+    }
+  | { type: ServerMessageType.RelayConnected; peer_id: string }
+  | { type: ServerMessageType.ServerShutdown; reconnect_after: number };
 
 export enum SignalLabelType {
   Offer = "offer",
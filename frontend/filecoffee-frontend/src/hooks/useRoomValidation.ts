@@ -10,6 +10,7 @@ interface UseRoomValidationResult {
 export const useRoomValidation = (
   action: string | undefined,
   roomId: string | undefined,
+  shareToken?: string,
 ): UseRoomValidationResult => {
   const [isValidating, setIsValidating] = useState(true);
   const [roomExists, setRoomExists] = useState<boolean | null>(null);
@@ -28,15 +29,24 @@ export const useRoomValidation = (
       setError(null);
 
       try {
+        const query = shareToken
+          ? `?token=${encodeURIComponent(shareToken)}`
+          : "";
         const response = await fetch(
-          `${API_BASE_URL}/api/rooms/${roomId}`,
+          `${API_BASE_URL}/api/rooms/${roomId}${query}`,
         );
 
         if (response.ok) {
           setRoomExists(true);
         } else {
           setRoomExists(false);
-          setError("Room not found or has expired.");
+          // Errors come as JSON with a code, a message and a request id
+          const body = await response.json().catch(() => null);
+          setError(
+            response.status === 404 || !body?.message
+              ? "Room not found or has expired."
+              : `${body.message} (reference ${body.request_id})`,
+          );
         }
       } catch (err) {
         console.error("Error checking room:", err);
@@ -48,7 +58,7 @@ export const useRoomValidation = (
     };
 
     checkRoom();
-  }, [roomId]);
+  }, [roomId, shareToken]);
 
   return { isValidating, roomExists, error };
 };
@@ -4,8 +4,9 @@ import {
   ViewType,
   SignalLabelType,
   ClientMessageType,
+  SessionInfo,
 } from "@/constants/enums";
-import { fetchIceServers, IceServer } from "@/lib/utils";
+import { fetchIceServers, IceServer, relayUrl } from "@/lib/utils";
 
 export interface DownloadHandlerContext {
   setCurrentView: (view: ViewType) => void;
@@ -15,8 +16,12 @@ export interface DownloadHandlerContext {
   setFileName: (name: string) => void;
   toast: any;
   toastId: string | number;
+  roomId: string;
   wsRef: React.MutableRefObject<WebSocket | null>;
+  sessionRef: React.MutableRefObject<SessionInfo | null>;
+  reconnectDelayRef: React.MutableRefObject<number>;
   peerConnectionRef: React.MutableRefObject<RTCPeerConnection | null>;
+  relaySocketRef: React.MutableRefObject<WebSocket | null>;
   iceServersPromiseRef: React.MutableRefObject<Promise<IceServer[]> | null>;
   candidateQueueRef: React.MutableRefObject<RTCIceCandidate[]>;
   receivedChunksRef: React.MutableRefObject<ArrayBuffer[]>;
@@ -41,6 +46,12 @@ class RoomJoinedStrategy implements DownloadMessageStrategy {
     if (message.type !== ServerMessageType.RoomJoined) return;
 
     context.toast.dismiss(context.toastId);
+    context.iceServersPromiseRef.current = Promise.resolve(message.ice_servers);
+    context.sessionRef.current = {
+      room_id: context.roomId,
+      peer_id: message.peer_id,
+      resume_token: message.resume_token,
+    };
     context.toast.success("Joined room successfully!");
     context.setCurrentView(ViewType.DOWNLOAD);
   }
@@ -53,6 +64,62 @@ class PeerLeftStrategy implements DownloadMessageStrategy {
       context.peerConnectionRef.current.close();
       context.peerConnectionRef.current = null;
     }
+    if (context.relaySocketRef.current) {
+      context.relaySocketRef.current.close();
+      context.relaySocketRef.current = null;
+    }
+  }
+}
+
+class JoinPendingStrategy implements DownloadMessageStrategy {
+  handle(message: ServerMessage, context: DownloadHandlerContext) {
+    context.toast.loading("Waiting for the sender to let you in...", {
+      id: context.toastId,
+    });
+  }
+}
+
+class JoinRejectedStrategy implements DownloadMessageStrategy {
+  handle(message: ServerMessage, context: DownloadHandlerContext) {
+    if (message.type !== ServerMessageType.JoinRejected) return;
+
+    context.toast.dismiss(context.toastId);
+    context.toast.error(message.reason);
+    context.setError(message.reason);
+  }
+}
+
+class SessionResumedStrategy implements DownloadMessageStrategy {
+  handle(message: ServerMessage, context: DownloadHandlerContext) {
+    if (message.type !== ServerMessageType.SessionResumed) return;
+
+    context.toast.dismiss(context.toastId);
+    context.iceServersPromiseRef.current = Promise.resolve(message.ice_servers);
+    if (context.sessionRef.current) {
+      context.sessionRef.current.resume_token = message.resume_token;
+    }
+    context.toast.success("Reconnected.");
+  }
+}
+
+class RemovedStrategy implements DownloadMessageStrategy {
+  handle(message: ServerMessage, context: DownloadHandlerContext) {
+    context.sessionRef.current = null;
+    const reason =
+      message.type === ServerMessageType.Kicked
+        ? "You were removed from the room by the sender."
+        : "The sender closed the room.";
+
+    context.toast.error(reason);
+    context.setError(reason);
+    if (context.peerConnectionRef.current) {
+      context.peerConnectionRef.current.close();
+      context.peerConnectionRef.current = null;
+    }
+    if (context.relaySocketRef.current) {
+      context.relaySocketRef.current.close();
+      context.relaySocketRef.current = null;
+    }
   }
 }
 
@@ -60,6 +127,15 @@ class SignalStrategy implements DownloadMessageStrategy {
   async handle(message: ServerMessage, context: DownloadHandlerContext) {
     if (message.type !== ServerMessageType.Signal) return;
 
+    // Signals resent after a reconnect may have been received already
+    const session = context.sessionRef.current;
+    if (session) {
+      if (session.last_seq !== undefined && message.seq <= session.last_seq) {
+        return;
+      }
+      session.last_seq = message.seq;
+    }
+
     const signal = message.data as any;
 
     if (signal.type === SignalLabelType.Offer) {
@@ -91,7 +167,7 @@ class SignalStrategy implements DownloadMessageStrategy {
         context.toast.success("Connected to peer! Waiting for data...");
       };
 
-      dc.onmessage = (e) => this.handleDataChannelMessage(e, context, dc);
+      dc.onmessage = (e) => handleTransferMessage(e, context, dc);
     };
 
     peerConnection.onicecandidate = (event) => {
@@ -143,83 +219,108 @@ class SignalStrategy implements DownloadMessageStrategy {
       context.candidateQueueRef.current.push(candidate);
     }
   }
+}
 
-  private handleDataChannelMessage(
-    e: MessageEvent,
-    context: DownloadHandlerContext,
-    dc: RTCDataChannel
-  ) {
-    const data = e.data;
-
-    // 1. Handle Metadata (String)
-    if (typeof data === "string") {
-      try {
-        const metadata = JSON.parse(data);
-        if (metadata.type === "metadata") {
-          context.fileMetadataRef.current = {
-            name: metadata.fileName,
-            size: metadata.fileSize,
-            type: metadata.fileType,
-          };
-          context.receivedChunksRef.current = [];
-          context.receivedBytesRef.current = 0;
-          context.setProgress(0);
-          context.setDownloadUrl(null);
-          context.setFileName(metadata.fileName);
-          context.toast.info(`Receiving ${metadata.fileName}...`);
-        }
-      } catch (err) {
-        console.error("Error parsing metadata:", err);
+// File data arrives the same way over a data channel or the relay socket
+function handleTransferMessage(
+  e: MessageEvent,
+  context: DownloadHandlerContext,
+  dc: RTCDataChannel | WebSocket
+) {
+  const data = e.data;
+
+  // 1. Handle Metadata (String)
+  if (typeof data === "string") {
+    try {
+      const metadata = JSON.parse(data);
+      if (metadata.type === "metadata") {
+        context.fileMetadataRef.current = {
+          name: metadata.fileName,
+          size: metadata.fileSize,
+          type: metadata.fileType,
+        };
+        context.receivedChunksRef.current = [];
+        context.receivedBytesRef.current = 0;
+        context.setProgress(0);
+        context.setDownloadUrl(null);
+        context.setFileName(metadata.fileName);
+        context.toast.info(`Receiving ${metadata.fileName}...`);
       }
+    } catch (err) {
+      console.error("Error parsing metadata:", err);
     }
-    // 2. Handle Binary Chunk
-    else if (data instanceof ArrayBuffer) {
-      context.receivedChunksRef.current.push(data);
-      context.receivedBytesRef.current += data.byteLength;
-
-      if (
-        context.fileMetadataRef.current &&
-        context.fileMetadataRef.current.size > 0
-      ) {
-        const percent =
-          (context.receivedBytesRef.current /
-            context.fileMetadataRef.current.size) *
-          100;
-        context.setProgress(Math.round(percent));
-      }
-
-      const percent = Math.round(
+  }
+  // 2. Handle Binary Chunk
+  else if (data instanceof ArrayBuffer) {
+    context.receivedChunksRef.current.push(data);
+    context.receivedBytesRef.current += data.byteLength;
+
+    if (
+      context.fileMetadataRef.current &&
+      context.fileMetadataRef.current.size > 0
+    ) {
+      const percent =
         (context.receivedBytesRef.current /
-          (context.fileMetadataRef.current?.size || 1)) *
-          100
+          context.fileMetadataRef.current.size) *
+        100;
+      context.setProgress(Math.round(percent));
+    }
+
+    const percent = Math.round(
+      (context.receivedBytesRef.current /
+        (context.fileMetadataRef.current?.size || 1)) *
+        100
+    );
+
+    if (percent > context.lastReportedProgress.current) {
+      dc.send(
+        JSON.stringify({
+          type: SignalLabelType.Progress,
+          percent: percent,
+        })
       );
+    }
 
-      if (percent > context.lastReportedProgress.current) {
-        dc.send(
-          JSON.stringify({
-            type: SignalLabelType.Progress,
-            percent: percent,
-          })
-        );
-      }
+    context.lastReportedProgress.current = percent;
+
+    // Check if finished
+    if (
+      context.fileMetadataRef.current &&
+      context.receivedBytesRef.current >= context.fileMetadataRef.current.size
+    ) {
+      console.log("File transfer complete. Reassembling...");
+      const blob = new Blob(context.receivedChunksRef.current, {
+        type: context.fileMetadataRef.current.type,
+      });
+      const url = URL.createObjectURL(blob);
+      context.setDownloadUrl(url);
+      context.setFileName(context.fileMetadataRef.current.name);
+      context.toast.success("File transfer complete!");
+    }
+  }
+}
 
-      context.lastReportedProgress.current = percent;
-
-      // Check if finished
-      if (
-        context.fileMetadataRef.current &&
-        context.receivedBytesRef.current >= context.fileMetadataRef.current.size
-      ) {
-        console.log("File transfer complete. Reassembling...");
-        const blob = new Blob(context.receivedChunksRef.current, {
-          type: context.fileMetadataRef.current.type,
-        });
-        const url = URL.createObjectURL(blob);
-        context.setDownloadUrl(url);
-        context.setFileName(context.fileMetadataRef.current.name);
-        context.toast.success("File transfer complete!");
-      }
+class RelayReadyStrategy implements DownloadMessageStrategy {
+  handle(message: ServerMessage, context: DownloadHandlerContext) {
+    if (message.type !== ServerMessageType.RelayReady) return;
+
+    context.toast.info("Direct connection failed, receiving through the server...");
+    if (context.relaySocketRef.current) {
+      context.relaySocketRef.current.close();
     }
+    const relay = new WebSocket(relayUrl(context.roomId, message.token));
+    relay.binaryType = "arraybuffer";
+    context.relaySocketRef.current = relay;
+
+    relay.onmessage = (e) => handleTransferMessage(e, context, relay);
+    relay.onclose = (event) => {
+      if (event.code === 1008) {
+        context.toast.error(event.reason || "Relay limit reached.");
+      }
+      if (context.relaySocketRef.current === relay) {
+        context.relaySocketRef.current = null;
+      }
+    };
   }
 }
 
@@ -228,8 +329,23 @@ class ErrorStrategy implements DownloadMessageStrategy {
     if (message.type !== ServerMessageType.Error) return;
 
     context.toast.dismiss(context.toastId);
-    context.toast.error(message.message);
+    console.error("Server error:", message.message, message.request_id);
+    context.toast.error(message.message, {
+      description: `Reference: ${message.request_id}`,
+    });
     context.setError(message.message);
+    if (message.code === "SESSION_EXPIRED") {
+      context.sessionRef.current = null;
+    }
+  }
+}
+
+class ServerShutdownStrategy implements DownloadMessageStrategy {
+  handle(message: ServerMessage, context: DownloadHandlerContext) {
+    if (message.type !== ServerMessageType.ServerShutdown) return;
+
+    // The transfer itself doesn't need the server; reconnect once it goes away
+    context.reconnectDelayRef.current = message.reconnect_after * 1000;
   }
 }
 
@@ -240,9 +356,22 @@ export class DownloadWebSocketStrategyManager {
 
   constructor() {
     this.register(ServerMessageType.RoomJoined, new RoomJoinedStrategy());
+    this.register(
+      ServerMessageType.SessionResumed,
+      new SessionResumedStrategy()
+    );
     this.register(ServerMessageType.PeerLeft, new PeerLeftStrategy());
     this.register(ServerMessageType.Signal, new SignalStrategy());
+    this.register(ServerMessageType.RelayReady, new RelayReadyStrategy());
     this.register(ServerMessageType.Error, new ErrorStrategy());
+    this.register(ServerMessageType.JoinPending, new JoinPendingStrategy());
+    this.register(ServerMessageType.JoinRejected, new JoinRejectedStrategy());
+    this.register(ServerMessageType.Kicked, new RemovedStrategy());
+    this.register(ServerMessageType.RoomClosed, new RemovedStrategy());
+    this.register(
+      ServerMessageType.ServerShutdown,
+      new ServerShutdownStrategy()
+    );
   }
 
   register(type: ServerMessageType, strategy: DownloadMessageStrategy) {
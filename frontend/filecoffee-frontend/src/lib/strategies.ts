@@ -5,8 +5,10 @@ import {
   DATA_CHANNEL_LABEL,
   SignalLabelType,
   ClientMessageType,
+  SessionInfo,
+  RELAY_DRAIN_POLL_MS,
 } from "@/constants/enums";
-import { fetchIceServers } from "@/lib/utils";
+import { fetchIceServers, IceServer, relayUrl } from "@/lib/utils";
 
 export interface HandlerContext {
   setShareUrls: (urls: { long: string } | null) => void;
@@ -17,6 +19,13 @@ export interface HandlerContext {
   wsRef: React.MutableRefObject<WebSocket | null>;
   peerConnectionRef: React.MutableRefObject<RTCPeerConnection | null>;
   dataChannelRef: React.MutableRefObject<RTCDataChannel | null>;
+  relaySocketRef: React.MutableRefObject<WebSocket | null>;
+  iceServersRef: React.MutableRefObject<IceServer[] | null>;
+  ownerTokenRef: React.MutableRefObject<string | null>;
+  sessionRef: React.MutableRefObject<SessionInfo | null>;
+  remotePeerIdRef: React.MutableRefObject<string | null>;
+  reconnectDelayRef: React.MutableRefObject<number>;
+  setIsLocked: (locked: boolean) => void;
   selectedFile: File | null;
   toastId: string | number;
 }
@@ -31,10 +40,16 @@ class RoomCreatedStrategy implements MessageStrategy {
 
     context.toast.dismiss(context.toastId);
     context.toast.success("Room created! Ready to share.");
-    const { room_id } = message;
+    const { room_id, share_token, owner_token, peer_id, resume_token } =
+      message;
+    context.iceServersRef.current = message.ice_servers;
+    context.ownerTokenRef.current = owner_token;
+    context.sessionRef.current = { room_id, peer_id, resume_token };
 
     const baseUrl = window.location.origin;
-    const longUrl = `${baseUrl}/download/${room_id}`;
+    // The share token goes in the fragment, so it never reaches server logs
+    const tokenFragment = share_token ? `#${share_token}` : "";
+    const longUrl = `${baseUrl}/download/${room_id}${tokenFragment}`;
 
     context.setShareUrls({
       long: longUrl,
@@ -43,6 +58,19 @@ class RoomCreatedStrategy implements MessageStrategy {
   }
 }
 
+class SessionResumedStrategy implements MessageStrategy {
+  handle(message: ServerMessage, context: HandlerContext) {
+    if (message.type !== ServerMessageType.SessionResumed) return;
+
+    context.toast.dismiss(context.toastId);
+    context.iceServersRef.current = message.ice_servers;
+    if (context.sessionRef.current) {
+      context.sessionRef.current.resume_token = message.resume_token;
+    }
+    context.toast.success("Reconnected.");
+  }
+}
+
 class PeerLeftStrategy implements MessageStrategy {
   handle(message: ServerMessage, context: HandlerContext) {
     context.toast.info("Peer disconnected.");
@@ -56,11 +84,18 @@ class PeerLeftStrategy implements MessageStrategy {
       context.dataChannelRef.current.close();
       context.dataChannelRef.current = null;
     }
+    if (context.relaySocketRef.current) {
+      context.relaySocketRef.current.close();
+      context.relaySocketRef.current = null;
+    }
   }
 }
 
 class PeerJoinedStrategy implements MessageStrategy {
   async handle(message: ServerMessage, context: HandlerContext) {
+    if (message.type !== ServerMessageType.PeerJoined) return;
+
+    context.remotePeerIdRef.current = message.peer_id;
     console.log("Peer joined! Starting WebRTC connection...");
     context.toast.info("A peer has joined! connecting...");
     context.setIsConnected(true);
@@ -75,7 +110,8 @@ class PeerJoinedStrategy implements MessageStrategy {
     }
 
     // First, create the RTCPeerConnection
-    const iceServers = await fetchIceServers();
+    const iceServers =
+      context.iceServersRef.current ?? (await fetchIceServers());
     console.log("Using ICE servers:", iceServers);
 
     const peerConnection = new RTCPeerConnection({
@@ -89,6 +125,16 @@ class PeerJoinedStrategy implements MessageStrategy {
 
     this.setupDataChannel(dataChannel, context);
 
+    // No direct or TURN path between the peers: ask the server to relay the file
+    peerConnection.onconnectionstatechange = () => {
+      if (peerConnection.connectionState !== "failed") return;
+      const peerId = context.remotePeerIdRef.current;
+      if (!peerId || !context.wsRef.current) return;
+      context.wsRef.current.send(
+        JSON.stringify({ type: ClientMessageType.RequestRelay, to: peerId })
+      );
+    };
+
     peerConnection.onicecandidate = (event) => {
       if (event.candidate && context.wsRef.current) {
         context.wsRef.current.send(
@@ -125,83 +171,137 @@ class PeerJoinedStrategy implements MessageStrategy {
     dataChannel.onopen = () => {
       console.log("Data channel opened");
       context.toast.success("Connected! Sending file...");
+      sendFile(dataChannel, context);
+    };
 
-      if (!context.selectedFile) return;
+    dataChannel.onmessage = (event) => handleProgressMessage(event, context);
 
-      // 1. Send metadata first
-      const metaData = JSON.stringify({
-        type: "metadata",
-        fileName: context.selectedFile.name,
-        fileSize: context.selectedFile.size,
-        fileType: context.selectedFile.type,
-      });
+    dataChannel.onclose = () => {
+      console.log("Data channel closed");
+    };
+  }
+}
 
-      dataChannel.send(metaData);
+// Send the selected file over a data channel, or over the relay socket
+function sendFile(channel: RTCDataChannel | WebSocket, context: HandlerContext) {
+  if (!context.selectedFile) return;
 
-      // 2. Read and Chunk the file
-      // Note: This might consume more memory on low-end devices but significantly speeds up transfer.
-      const CHUNK_SIZE = 256 * 1024;
-      const MAX_BUFFERED_AMOUNT = 64 * 1024 * 1024;
+  // 1. Send metadata first
+  const metaData = JSON.stringify({
+    type: "metadata",
+    fileName: context.selectedFile.name,
+    fileSize: context.selectedFile.size,
+    fileType: context.selectedFile.type,
+  });
 
-      dataChannel.bufferedAmountLowThreshold = 0;
+  channel.send(metaData);
 
-      let offset = 0;
-      const fileReader = new FileReader();
+  // 2. Read and Chunk the file
+  // Note: This might consume more memory on low-end devices but significantly speeds up transfer.
+  const CHUNK_SIZE = 256 * 1024;
+  const MAX_BUFFERED_AMOUNT = 64 * 1024 * 1024;
 
-      fileReader.onload = (e) => {
-        const buffer = e.target?.result as ArrayBuffer;
-        if (!buffer) return;
+  let offset = 0;
+  const fileReader = new FileReader();
 
-        try {
-          dataChannel.send(buffer);
-          offset += buffer.byteLength;
-          sendNextChunk();
-        } catch (error) {
-          console.error("Error sending chunk:", error);
-          context.toast.error("Error sending file data");
-        }
-      };
+  fileReader.onload = (e) => {
+    const buffer = e.target?.result as ArrayBuffer;
+    if (!buffer) return;
 
-      const sendNextChunk = () => {
-        if (!context.selectedFile || offset >= context.selectedFile.size) {
-          return;
-        }
+    try {
+      channel.send(buffer);
+      offset += buffer.byteLength;
+      sendNextChunk();
+    } catch (error) {
+      console.error("Error sending chunk:", error);
+      context.toast.error("Error sending file data");
+    }
+  };
 
-        // If the buffer is full, wait for it to drain
-        if (dataChannel.bufferedAmount > MAX_BUFFERED_AMOUNT) {
-          return;
-        }
+  const sendNextChunk = () => {
+    if (!context.selectedFile || offset >= context.selectedFile.size) {
+      return;
+    }
 
-        const slice = context.selectedFile.slice(offset, offset + CHUNK_SIZE);
-        fileReader.readAsArrayBuffer(slice);
-      };
+    // If the buffer is full, wait for it to drain
+    if (channel.bufferedAmount > MAX_BUFFERED_AMOUNT) {
+      // WebSockets have no bufferedamountlow event
+      if (channel instanceof WebSocket) {
+        setTimeout(sendNextChunk, RELAY_DRAIN_POLL_MS);
+      }
+      return;
+    }
 
-      dataChannel.onbufferedamountlow = () => {
-        sendNextChunk();
-      };
+    const slice = context.selectedFile.slice(offset, offset + CHUNK_SIZE);
+    fileReader.readAsArrayBuffer(slice);
+  };
 
+  if (!(channel instanceof WebSocket)) {
+    channel.bufferedAmountLowThreshold = 0;
+    channel.onbufferedamountlow = () => {
       sendNextChunk();
     };
+  }
+
+  sendNextChunk();
+}
 
-    dataChannel.onmessage = (event) => {
-      try {
-        const msg = JSON.parse(event.data);
-        if (
-          msg.type === SignalLabelType.Progress &&
-          typeof msg.percent === "number"
-        ) {
-          context.setTransferProgress(msg.percent);
-          if (msg.percent === 100) {
-            context.toast.success("File transfer completed successfully!");
+function handleProgressMessage(event: MessageEvent, context: HandlerContext) {
+  try {
+    const msg = JSON.parse(event.data);
+    if (
+      msg.type === SignalLabelType.Progress &&
+      typeof msg.percent === "number"
+    ) {
+      context.setTransferProgress(msg.percent);
+      if (msg.percent === 100) {
+        context.toast.success("File transfer completed successfully!");
+      }
+    }
+  } catch (e) {
+    // Ignore binary data or other non-JSON messages
+  }
+}
+
+class RelayReadyStrategy implements MessageStrategy {
+  handle(message: ServerMessage, context: HandlerContext) {
+    if (message.type !== ServerMessageType.RelayReady) return;
+
+    const session = context.sessionRef.current;
+    if (!session) return;
+
+    context.toast.info("Direct connection failed, sending through the server...");
+    if (context.relaySocketRef.current) {
+      context.relaySocketRef.current.close();
+    }
+    const relay = new WebSocket(relayUrl(session.room_id, message.token));
+    relay.binaryType = "arraybuffer";
+    context.relaySocketRef.current = relay;
+
+    relay.onmessage = (event) => {
+      // The server says when the receiver is connected; data sent before is dropped
+      if (typeof event.data === "string") {
+        try {
+          const msg = JSON.parse(event.data);
+          if (msg.type === ServerMessageType.RelayConnected) {
+            context.toast.success("Connected! Sending file...");
+            sendFile(relay, context);
+            return;
           }
+        } catch (e) {
+          // Not JSON, fall through
         }
-      } catch (e) {
-        // Ignore binary data or other non-JSON messages
       }
+      handleProgressMessage(event, context);
     };
 
-    dataChannel.onclose = () => {
-      console.log("Data channel closed");
+    relay.onclose = (event) => {
+      if (event.code === 1008) {
+        context.toast.error(event.reason || "Relay limit reached.");
+      }
+      if (context.relaySocketRef.current === relay) {
+        context.relaySocketRef.current = null;
+      }
     };
   }
 }
@@ -209,6 +309,16 @@ class PeerJoinedStrategy implements MessageStrategy {
 class SignalStrategy implements MessageStrategy {
   async handle(message: ServerMessage, context: HandlerContext) {
     if (message.type !== ServerMessageType.Signal) return;
+
+    // Signals resent after a reconnect may have been received already
+    const session = context.sessionRef.current;
+    if (session) {
+      if (session.last_seq !== undefined && message.seq <= session.last_seq) {
+        return;
+      }
+      session.last_seq = message.seq;
+    }
+
     if (!context.peerConnectionRef.current) return;
 
     const signal = message.data as any;
@@ -226,13 +336,105 @@ class SignalStrategy implements MessageStrategy {
   }
 }
 
+class RoomLockedStrategy implements MessageStrategy {
+  handle(message: ServerMessage, context: HandlerContext) {
+    if (message.type !== ServerMessageType.RoomLocked) return;
+
+    context.setIsLocked(message.locked);
+    context.toast.info(message.locked ? "Room locked." : "Room unlocked.");
+  }
+}
+
+class JoinRequestStrategy implements MessageStrategy {
+  handle(message: ServerMessage, context: HandlerContext) {
+    if (message.type !== ServerMessageType.JoinRequest) return;
+
+    const { request_id, display_name, fingerprint } = message;
+    const answer = (approve: boolean) => {
+      const ws = context.wsRef.current;
+      if (!ws || !context.ownerTokenRef.current) return;
+      ws.send(
+        JSON.stringify({
+          type: ClientMessageType.AnswerJoinRequest,
+          owner_token: context.ownerTokenRef.current,
+          request_id,
+          approve,
+        })
+      );
+    };
+
+    context.toast(`${display_name ?? "Someone"} wants to join`, {
+      id: request_id,
+      description: `Device code ${fingerprint}`,
+      duration: Infinity,
+      action: { label: "Let in", onClick: () => answer(true) },
+      cancel: { label: "Decline", onClick: () => answer(false) },
+    });
+  }
+}
+
+class JoinRequestCancelledStrategy implements MessageStrategy {
+  handle(message: ServerMessage, context: HandlerContext) {
+    if (message.type !== ServerMessageType.JoinRequestCancelled) return;
+
+    context.toast.dismiss(message.request_id);
+  }
+}
+
+class RoomClosedStrategy implements MessageStrategy {
+  handle(message: ServerMessage, context: HandlerContext) {
+    context.sessionRef.current = null;
+    context.toast.info("Room closed.");
+    context.setIsConnected(false);
+    context.setTransferProgress(0);
+    context.setShareUrls(null);
+    context.setCurrentView(ViewType.UPLOAD);
+    if (context.peerConnectionRef.current) {
+      context.peerConnectionRef.current.close();
+      context.peerConnectionRef.current = null;
+    }
+    if (context.relaySocketRef.current) {
+      context.relaySocketRef.current.close();
+      context.relaySocketRef.current = null;
+    }
+  }
+}
+
+class ServerShutdownStrategy implements MessageStrategy {
+  handle(message: ServerMessage, context: HandlerContext) {
+    if (message.type !== ServerMessageType.ServerShutdown) return;
+
+    // The transfer itself doesn't need the server; reconnect once it goes away
+    context.reconnectDelayRef.current = message.reconnect_after * 1000;
+    context.toast.info("Server restarting. Your room stays open.");
+  }
+}
+
+class PasswordAttemptFailedStrategy implements MessageStrategy {
+  handle(message: ServerMessage, context: HandlerContext) {
+    if (message.type !== ServerMessageType.PasswordAttemptFailed) return;
+
+    context.toast.warning(
+      `Someone entered a wrong password for your room (${message.failed_attempts} failed attempts).`
+    );
+  }
+}
+
 class ErrorStrategy implements MessageStrategy {
   handle(message: ServerMessage, context: HandlerContext) {
     if (message.type !== ServerMessageType.Error) return;
 
     context.toast.dismiss(context.toastId);
-    console.error("Server error:", message.message);
-    context.toast.error(message.message);
+    console.error("Server error:", message.message, message.request_id);
+    context.toast.error(message.message, {
+      description: `Reference: ${message.request_id}`,
+    });
+    if (message.code === "SESSION_EXPIRED") {
+      context.sessionRef.current = null;
+      context.setIsConnected(false);
+      context.setShareUrls(null);
+      context.setCurrentView(ViewType.UPLOAD);
+    }
   }
 }
 
@@ -241,10 +443,30 @@ export class WebSocketStrategyManager {
 
   constructor() {
     this.register(ServerMessageType.RoomCreated, new RoomCreatedStrategy());
+    this.register(
+      ServerMessageType.SessionResumed,
+      new SessionResumedStrategy()
+    );
     this.register(ServerMessageType.PeerLeft, new PeerLeftStrategy());
     this.register(ServerMessageType.PeerJoined, new PeerJoinedStrategy());
     this.register(ServerMessageType.Signal, new SignalStrategy());
+    this.register(ServerMessageType.RelayReady, new RelayReadyStrategy());
     this.register(ServerMessageType.Error, new ErrorStrategy());
+    this.register(ServerMessageType.RoomLocked, new RoomLockedStrategy());
+    this.register(ServerMessageType.RoomClosed, new RoomClosedStrategy());
+    this.register(
+      ServerMessageType.ServerShutdown,
+      new ServerShutdownStrategy()
+    );
+    this.register(ServerMessageType.JoinRequest, new JoinRequestStrategy());
+    this.register(
+      ServerMessageType.JoinRequestCancelled,
+      new JoinRequestCancelledStrategy()
+    );
+    this.register(
+      ServerMessageType.PasswordAttemptFailed,
+      new PasswordAttemptFailedStrategy()
+    );
   }
 
   register(type: ServerMessageType, strategy: MessageStrategy) {
@@ -1,13 +1,13 @@
 import { clsx, type ClassValue } from "clsx";
 import { twMerge } from "tailwind-merge";
-import { API_BASE_URL } from "../config";
+import { API_BASE_URL, WS_BASE_URL } from "../config";
 
 export function cn(...inputs: ClassValue[]) {
     return twMerge(clsx(inputs));
 }
 
 export interface IceServer {
-    urls: string;
+    urls: string | string[];
     username?: string;
     credential?: string;
 }
@@ -16,6 +16,7 @@ export interface IceConfig {
     iceServers: IceServer[];
 }
 
+// Public STUN servers only; TURN credentials arrive with RoomCreated/RoomJoined
 export async function fetchIceServers(): Promise<IceServer[]> {
     try {
         const response = await fetch(`${API_BASE_URL}/api/ice-servers`);
@@ -31,6 +32,11 @@ export async function fetchIceServers(): Promise<IceServer[]> {
     }
 }
 
+// Relay socket for when WebRTC cannot connect; the token comes with RelayReady
+export function relayUrl(roomId: string, token: string): string {
+    return `${WS_BASE_URL}/ws/relay/${encodeURIComponent(roomId)}?token=${encodeURIComponent(token)}`;
+}
+
 export function getFileSize(file: { size: number }): string {
     const bytes = file.size;
     if (bytes === 0) return "0 KB";
@@ -9,10 +9,11 @@ import {
   ServerMessage,
   ServerMessageType,
   SignalLabelType,
+  RECONNECT_DELAY_MS,
+  SessionInfo,
 } from "@/constants/enums.ts";
-import { useParams } from "react-router-dom";
+import { useLocation, useParams } from "react-router-dom";
 import { useRoomValidation } from "@/hooks/useRoomValidation.ts";
-import { fetchIceServers } from "@/lib/utils";
 import { toast } from "sonner";
 import { WS_BASE_URL } from "@/config";
 import {
@@ -26,15 +27,20 @@ const Download = () => {
 
   // Room handling
   const { action, room_id } = useParams<{ action: string; room_id: string }>();
+  const shareToken = useLocation().hash.slice(1) || undefined;
   const {
     isValidating,
     roomExists,
     error: validationError,
-  } = useRoomValidation(action, room_id);
+  } = useRoomValidation(action, room_id, shareToken);
 
   // WebSocket Refs
   const wsRef = useRef<WebSocket | null>(null);
+  const sessionRef = useRef<SessionInfo | null>(null);
+  // Wait before resuming; a server shutting down asks for longer
+  const reconnectDelayRef = useRef(RECONNECT_DELAY_MS);
   const peerConnectionRef = useRef<RTCPeerConnection | null>(null);
+  const relaySocketRef = useRef<WebSocket | null>(null);
 
   // File Transfer State
   const [progress, setProgress] = useState(0);
@@ -56,9 +62,13 @@ const Download = () => {
 
   useEffect(() => {
     return () => {
+      sessionRef.current = null;
       if (wsRef.current) {
         wsRef.current.close();
       }
+      if (relaySocketRef.current) {
+        relaySocketRef.current.close();
+      }
     };
   }, []);
 
@@ -66,11 +76,16 @@ const Download = () => {
     if (!room_id) return;
 
     // Reset everything for a fresh attempt
+    sessionRef.current = null;
     if (wsRef.current) wsRef.current.close();
     if (peerConnectionRef.current) {
       peerConnectionRef.current.close();
       peerConnectionRef.current = null;
     }
+    if (relaySocketRef.current) {
+      relaySocketRef.current.close();
+      relaySocketRef.current = null;
+    }
     candidateQueueRef.current = [];
     receivedChunksRef.current = [];
     receivedBytesRef.current = 0;
@@ -80,8 +95,31 @@ const Download = () => {
 
     const toastId = toast.loading("Connecting to room...");
 
-    // Start fetching ICE servers immediately when we start connecting
-    iceServersPromiseRef.current = fetchIceServers();
+    // ICE servers arrive with RoomJoined
+    iceServersPromiseRef.current = null;
+
+    connect(
+      {
+        type: ClientMessageType.JoinRoom,
+        room_id,
+        password: password || undefined,
+        token: shareToken,
+      },
+      toastId
+    );
+  }
+
+  // Take our place in the room back after the WebSocket dropped
+  function resumeSession() {
+    const session = sessionRef.current;
+    if (!session) return;
+
+    const toastId = toast.loading("Connection lost, reconnecting...");
+    connect({ type: ClientMessageType.ResumeSession, ...session }, toastId);
+  }
+
+  function connect(openMessage: ClientMessage, toastId: string | number) {
+    if (!room_id) return;
 
     // Create the WebSocket connection
     const ws = new WebSocket(`${WS_BASE_URL}/ws`);
@@ -89,12 +127,7 @@ const Download = () => {
 
     ws.onopen = () => {
       console.log("Connected to WebSocket");
-      const msg: ClientMessage = {
-        type: ClientMessageType.JoinRoom,
-        room_id,
-        password: password || undefined,
-      };
-      ws.send(JSON.stringify(msg));
+      ws.send(JSON.stringify(openMessage));
     };
 
     ws.onmessage = async (event) => {
@@ -110,8 +143,12 @@ const Download = () => {
           setFileName,
           toast,
           toastId,
+          roomId: room_id,
           wsRef,
+          sessionRef,
+          reconnectDelayRef,
           peerConnectionRef,
+          relaySocketRef,
           iceServersPromiseRef,
           candidateQueueRef,
           receivedChunksRef,
@@ -141,6 +178,11 @@ const Download = () => {
       console.log("WebSocket connection closed");
       if (wsRef.current === ws) {
         wsRef.current = null;
+        // Closed by the network or server rather than by us
+        if (sessionRef.current) {
+          setTimeout(resumeSession, reconnectDelayRef.current);
+          reconnectDelayRef.current = RECONNECT_DELAY_MS;
+        }
       }
     };
   }
@@ -7,11 +7,14 @@ import {
   ClientMessage,
   ServerMessage,
   ClientMessageType,
+  RECONNECT_DELAY_MS,
+  SessionInfo,
 } from "@/constants/enums.ts";
 import coffeeLogoImg from "@/assets/coffee-logo.png";
 import { toast } from "sonner";
 import { WS_BASE_URL } from "@/config";
 import { HandlerContext, WebSocketStrategyManager } from "@/lib/strategies";
+import { IceServer } from "@/lib/utils";
 
 const Index = () => {
   const [currentView, setCurrentView] = useState<ViewType>(ViewType.UPLOAD);
@@ -22,6 +25,14 @@ const Index = () => {
   const wsRef = useRef<WebSocket | null>(null);
   const peerConnectionRef = useRef<RTCPeerConnection | null>(null);
   const dataChannelRef = useRef<RTCDataChannel | null>(null);
+  const relaySocketRef = useRef<WebSocket | null>(null);
+  const iceServersRef = useRef<IceServer[] | null>(null);
+  const ownerTokenRef = useRef<string | null>(null);
+  const sessionRef = useRef<SessionInfo | null>(null);
+  const remotePeerIdRef = useRef<string | null>(null);
+  // Wait before resuming; a server shutting down asks for longer
+  const reconnectDelayRef = useRef(RECONNECT_DELAY_MS);
+  const [isLocked, setIsLocked] = useState(false);
 
   const handleFileSelect = (file: File) => {
     setSelectedFile(file);
@@ -29,29 +40,86 @@ const Index = () => {
   };
 
   const handleCancel = () => {
+    sessionRef.current = null;
     if (wsRef.current) {
       wsRef.current.close();
       wsRef.current = null;
     }
+    if (relaySocketRef.current) {
+      relaySocketRef.current.close();
+      relaySocketRef.current = null;
+    }
 
     setSelectedFile(null);
     setCurrentView(ViewType.UPLOAD);
   };
 
-  const handleStart = async (password?: string) => {
+  const sendHostControl = (msg: ClientMessage) => {
+    if (wsRef.current?.readyState === WebSocket.OPEN) {
+      wsRef.current.send(JSON.stringify(msg));
+    }
+  };
+
+  const handleToggleLock = () => {
+    if (!ownerTokenRef.current) return;
+    sendHostControl({
+      type: ClientMessageType.LockRoom,
+      owner_token: ownerTokenRef.current,
+      locked: !isLocked,
+    });
+  };
+
+  const handleKickPeer = () => {
+    if (!ownerTokenRef.current || !remotePeerIdRef.current) return;
+    sendHostControl({
+      type: ClientMessageType.KickPeer,
+      owner_token: ownerTokenRef.current,
+      peer_id: remotePeerIdRef.current,
+    });
+  };
+
+  const handleCloseRoom = () => {
+    if (!ownerTokenRef.current) return;
+    sendHostControl({
+      type: ClientMessageType.CloseRoom,
+      owner_token: ownerTokenRef.current,
+    });
+  };
+
+  const handleStart = async (
+    password?: string,
+    roomName?: string,
+    requireApproval?: boolean
+  ) => {
     const toastId = toast.loading("Creating room...");
+    connect(
+      {
+        type: ClientMessageType.CreateRoom,
+        password: password,
+        room_name: roomName,
+        require_approval: requireApproval,
+      },
+      toastId
+    );
+  };
+
+  // Take our place in the room back after the WebSocket dropped
+  const resumeSession = () => {
+    const session = sessionRef.current;
+    if (!session) return;
+
+    const toastId = toast.loading("Connection lost, reconnecting...");
+    connect({ type: ClientMessageType.ResumeSession, ...session }, toastId);
+  };
+
+  const connect = (openMessage: ClientMessage, toastId: string | number) => {
     // Create the WebSocket connection
     const ws = new WebSocket(`${WS_BASE_URL}/ws`);
     wsRef.current = ws;
 
     ws.onopen = () => {
       console.log("Connected to WebSocket");
-      // Send CreateRoom message
-      const msg: ClientMessage = {
-        type: ClientMessageType.CreateRoom,
-        password: password,
-      };
-      ws.send(JSON.stringify(msg));
+      ws.send(JSON.stringify(openMessage));
     };
 
     ws.onmessage = async (event) => {
@@ -68,6 +136,13 @@ const Index = () => {
           wsRef,
           peerConnectionRef,
           dataChannelRef,
+          relaySocketRef,
+          iceServersRef,
+          ownerTokenRef,
+          sessionRef,
+          reconnectDelayRef,
+          remotePeerIdRef,
+          setIsLocked,
           selectedFile,
           toastId,
         };
@@ -91,6 +166,11 @@ const Index = () => {
       console.log("WebSocket connection closed");
       if (wsRef.current === ws) {
         wsRef.current = null;
+        // Closed by the network or server rather than by us
+        if (sessionRef.current) {
+          setTimeout(resumeSession, reconnectDelayRef.current);
+          reconnectDelayRef.current = RECONNECT_DELAY_MS;
+        }
       }
     };
   };
@@ -138,6 +218,10 @@ const Index = () => {
               longUrl={shareUrls.long}
               progress={transferProgress}
               isConnected={isConnected}
+              isLocked={isLocked}
+              onToggleLock={handleToggleLock}
+              onKickPeer={handleKickPeer}
+              onCloseRoom={handleCloseRoom}
             />
           )}
         </div>